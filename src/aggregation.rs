@@ -0,0 +1,162 @@
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use usiem::components::common::SiemComponentStateStorage;
+use usiem::prelude::alert::SiemAlert;
+
+const STORAGE_PREFIX : &str = "notion_aggr:";
+
+/// Page that groups the alerts with the same `aggr_key`
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct AggregatedAlert {
+    pub page_id : String,
+    pub url : String,
+    /// Alerts fired after this time (milliseconds) open a new page
+    pub aggr_limit : i64,
+    pub occurrences : u64,
+}
+
+/// Alerts with an `aggr_key` and an `aggr_limit` are grouped in the same page until `aggr_limit` is reached
+pub fn is_aggregable(alert : &SiemAlert) -> bool {
+    alert.aggr_limit > 0 && !alert.aggr_key.is_empty()
+}
+
+/// Tracks the open pages of each aggregation key. They are kept in memory and in the component state storage,
+/// so a restarted component keeps appending to the same pages.
+///
+/// The key is the `aggr_key` of the alert, prefixed with the database id when the alert is not sent to the
+/// default database (see [`aggregation_key`]).
+#[derive(Default, Clone)]
+pub struct AlertAggregator {
+    open : BTreeMap<String, AggregatedAlert>,
+}
+
+impl AlertAggregator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Open page where the alert must be aggregated
+    pub fn find(&mut self, storage : &dyn SiemComponentStateStorage, aggr_key : &str, alert : &SiemAlert) -> Option<AggregatedAlert> {
+        if !is_aggregable(alert) {
+            return None;
+        }
+        let entry = self.get(storage, aggr_key)?;
+        if alert.date >= entry.aggr_limit {
+            self.open.remove(aggr_key);
+            return None;
+        }
+        Some(entry)
+    }
+
+    /// Page of the aggregation key, even if its `aggr_limit` has been reached
+    pub fn get(&mut self, storage : &dyn SiemComponentStateStorage, aggr_key : &str) -> Option<AggregatedAlert> {
+        if let Some(entry) = self.open.get(aggr_key) {
+            return Some(entry.clone());
+        }
+        let stored = storage.get_value(storage_key(aggr_key)).ok()?;
+        let entry : AggregatedAlert = usiem::serde_json::from_str(&stored).ok()?;
+        self.open.insert(aggr_key.to_owned(), entry.clone());
+        Some(entry)
+    }
+
+    pub fn save(&mut self, storage : &mut dyn SiemComponentStateStorage, aggr_key : &str, entry : AggregatedAlert) {
+        if let Ok(value) = usiem::serde_json::to_string(&entry) {
+            let _ = storage.set_value(storage_key(aggr_key), value, true);
+        }
+        self.open.insert(aggr_key.to_owned(), entry);
+    }
+
+    /// The page is no longer usable (deleted or archived)
+    pub fn close(&mut self, storage : &mut dyn SiemComponentStateStorage, aggr_key : &str) {
+        self.open.remove(aggr_key);
+        let _ = storage.set_value(storage_key(aggr_key), String::new(), true);
+    }
+}
+
+/// Alerts sent to other databases than the default one are aggregated separately, even with the same `aggr_key`
+pub fn aggregation_key(alert : &SiemAlert, database_id : &str, default_database : &str) -> String {
+    if database_id == default_database {
+        alert.aggr_key.clone()
+    } else {
+        format!("{}/{}", database_id, alert.aggr_key)
+    }
+}
+
+fn storage_key(aggr_key : &str) -> Cow<'static, str> {
+    Cow::Owned(format!("{}{}", STORAGE_PREFIX, aggr_key))
+}
+
+#[cfg(test)]
+mod aggregator {
+    use usiem::components::common::SiemComponentStateStorage;
+    use usiem::prelude::{alert::{SiemAlert, AlertSeverity}, SiemLog};
+    use crate::mock::MemoryStateStorage;
+    use super::*;
+
+    const KEY : &str = "example::rule::10.10.10.10";
+
+    fn alert(date : i64, aggr_limit : i64) -> SiemAlert {
+        SiemAlert {
+            title: String::from("Brute force"),
+            description: String::new(),
+            severity: AlertSeverity::HIGH,
+            date,
+            tags: vec![],
+            techniques : vec![],
+            rule: String::from("ruleset::example::rule1"),
+            log: SiemLog::new("log", 0, "localhost"),
+            aggr_limit,
+            aggr_key: String::from("example::rule::10.10.10.10"),
+        }
+    }
+
+    fn entry(aggr_limit : i64) -> AggregatedAlert {
+        AggregatedAlert {
+            page_id : "page1".to_owned(),
+            url : "https://www.notion.so/page1".to_owned(),
+            aggr_limit,
+            occurrences : 1
+        }
+    }
+
+    #[test]
+    fn should_aggregate_until_limit() {
+        let mut storage = MemoryStateStorage::new();
+        let mut aggregator = AlertAggregator::new();
+        assert_eq!(None, aggregator.find(&storage, KEY, &alert(1000, 5000)));
+        aggregator.save(&mut storage, "example::rule::10.10.10.10", entry(5000));
+        assert_eq!(Some(entry(5000)), aggregator.find(&storage, KEY, &alert(4999, 9000)));
+        assert_eq!(None, aggregator.find(&storage, KEY, &alert(5000, 9000)));
+    }
+
+    #[test]
+    fn should_not_aggregate_without_key_or_limit() {
+        let mut storage = MemoryStateStorage::new();
+        let mut aggregator = AlertAggregator::new();
+        aggregator.save(&mut storage, "example::rule::10.10.10.10", entry(5000));
+        assert_eq!(None, aggregator.find(&storage, KEY, &alert(1000, 0)));
+        let mut no_key = alert(1000, 5000);
+        no_key.aggr_key = String::new();
+        assert_eq!(None, aggregator.find(&storage, "", &no_key));
+    }
+
+    #[test]
+    fn should_recover_from_storage() {
+        let mut storage = MemoryStateStorage::new();
+        AlertAggregator::new().save(&mut storage, "example::rule::10.10.10.10", entry(5000));
+        let mut restarted = AlertAggregator::new();
+        assert_eq!(Some(entry(5000)), restarted.find(&storage, KEY, &alert(2000, 9000)));
+        restarted.close(&mut storage, "example::rule::10.10.10.10");
+        assert_eq!(None, restarted.find(&storage, KEY, &alert(2000, 9000)));
+        assert_eq!(Ok(String::new()), storage.get_value(Cow::Borrowed("notion_aggr:example::rule::10.10.10.10")).map_err(|_| ()));
+    }
+
+    #[test]
+    fn should_separate_databases() {
+        let alert = alert(1000, 5000);
+        assert_eq!(KEY, aggregation_key(&alert, "database1", "database1"));
+        assert_eq!(format!("database2/{}", KEY), aggregation_key(&alert, "database2", "database1"));
+    }
+}
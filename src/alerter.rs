@@ -1,22 +1,48 @@
 use std::borrow::Cow;
 use std::collections::BTreeMap;
 use std::sync::Arc;
-use std::sync::atomic::AtomicI64;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::time::Duration;
 
 use usiem::components::common::*;
-use usiem::crossbeam_channel::{Receiver, Sender, bounded};
-use usiem::prelude::alert::SiemAlert;
+use usiem::crossbeam_channel::{Receiver, RecvTimeoutError, Sender, bounded};
 use usiem::prelude::dataset::text_map::TextMapSynDataset;
-use usiem::prelude::dataset::{SiemDatasetType};
+use usiem::prelude::dataset::{SiemDataset, SiemDatasetType};
 use usiem::prelude::dataset::holder::DatasetHolder;
 use usiem::prelude::metrics::{SiemMetric, SiemMetricDefinition};
 use usiem::prelude::SiemComponent;
+use usiem::prelude::alert::SiemAlert;
 use usiem::prelude::*;
 
-use crate::client::{self, NotionClient, NotionResult};
+use crate::aggregation::{aggregation_key, AggregatedAlert, AlertAggregator};
+use crate::api::block::BlockElement;
+use crate::api::page::Page;
+use crate::client::{alert_date, NotionClient, NotionClientBuilder, NotionError, NotionErrorCode, NotionResult};
+use crate::mapping::PropertyMapping;
+use crate::queue::{AlertQueue, PendingAlert};
+use crate::retry::RetryPolicy;
+use crate::routing::{RouteTarget, RoutingTable};
+use crate::schema::SchemaMigrationMode;
+use crate::template::{PageTemplate, PageTemplates};
 
-const NOTION_API_KEY : &'static str = "API_KEY";
-const NOTION_DATABASE : &'static str = "DATABASE_ID";
+const NOTION_API_KEY : &str = "API_KEY";
+const NOTION_DATABASE : &str = "DATABASE_ID";
+/// Optional: overrides the Notion API endpoint
+const NOTION_API_URL : &str = "API_URL";
+/// Optional: page where the database is created when DATABASE_ID is not set
+const NOTION_PARENT_PAGE : &str = "PARENT_PAGE_ID";
+/// Storage key of the database created in PARENT_PAGE_ID
+const CREATED_DATABASE : &str = "notion_database";
+const NEW_DATABASE_TITLE : &str = "SIEM Alerts";
+/// Optional dataset that maps the alert fields to the database properties
+const NOTION_PROPERTIES : &str = "NotionAlerterProperties";
+/// Optional dataset that maps each tenant to the database of its alerts. The API key of the
+/// tenant is the secret `API_KEY.<tenant>`, or API_KEY if it is not set.
+const NOTION_TENANTS : &str = "NotionAlerterTenants";
+/// Optional dataset with the routing rules, reloaded when it is updated
+const NOTION_ROUTES : &str = "NotionAlerterRoutes";
+/// Optional dataset with the page templates of each rule or rule prefix, reloaded when it is updated
+const NOTION_TEMPLATES : &str = "NotionAlerterTemplates";
 
 #[derive(Clone)]
 struct NotionMetrics {
@@ -31,6 +57,12 @@ pub struct NotionAlert {
     conn: Box<dyn SiemComponentStateStorage>,
     datasets: DatasetHolder,
     metrics: NotionMetrics,
+    aggregator: AlertAggregator,
+    queue: AlertQueue,
+    retry: RetryPolicy,
+    routes: RoutingTable,
+    templates: PageTemplates,
+    migration: SchemaMigrationMode,
 }
 
 impl NotionAlert {
@@ -45,9 +77,439 @@ impl NotionAlert {
                 generated_alerts : SiemMetric::Counter(Arc::new(AtomicI64::new(0)))
             },
             conn : Box::new(DummyStateStorage{}),
+            aggregator : AlertAggregator::new(),
+            queue : AlertQueue::default(),
+            retry : RetryPolicy::default(),
+            routes : RoutingTable::default(),
+            templates : PageTemplates::default(),
+            migration : SchemaMigrationMode::Disabled,
             kernel
         }
     }
+
+    /// Retries of each request to Notion
+    pub fn retry_policy(mut self, policy : RetryPolicy) -> Self {
+        self.retry = policy;
+        self
+    }
+
+    /// Backoff of the alerts that could not be sent after the request retries. They are kept in
+    /// the component state storage and retried until Notion accepts them.
+    pub fn queue_policy(mut self, policy : RetryPolicy) -> Self {
+        self.queue = AlertQueue::new(policy);
+        self
+    }
+
+    /// Adds the missing properties and options to the database on startup, or only notifies them with `DryRun`
+    pub fn schema_migration(mut self, mode : SchemaMigrationMode) -> Self {
+        self.migration = mode;
+        self
+    }
+}
+
+/// Client of a database where the alerts are sent
+struct Destination {
+    client : NotionClient,
+    /// The database schema was checked
+    checked : bool,
+}
+
+/// Clients of the databases where the alerts are sent, by database id and API key
+struct Destinations {
+    default_database : String,
+    default_api_key : String,
+    clients : BTreeMap<(String, String), Destination>,
+}
+
+impl Destinations {
+    fn new(default : Destination, api_key : &str) -> Self {
+        let mut destinations = Self {
+            default_database : default.client.database_id().to_owned(),
+            default_api_key : api_key.to_owned(),
+            clients : BTreeMap::new(),
+        };
+        destinations.clients.insert((destinations.default_database.clone(), api_key.to_owned()), default);
+        destinations
+    }
+
+    /// Client of the database, created on first use
+    fn get(&mut self, alerter : &NotionAlert, database_id : &str, api_key : &str) -> NotionResult<&mut Destination> {
+        let key = (database_id.to_owned(), api_key.to_owned());
+        if !self.clients.contains_key(&key) {
+            let client = alerter.client_builder(api_key, database_id).build()?;
+            self.clients.insert(key.clone(), Destination { client, checked : false });
+        }
+        Ok(self.clients.get_mut(&key).unwrap())
+    }
+
+    /// Drops the clients so they are created again with the current secrets and property mapping
+    fn reset(&mut self, api_key : Option<&str>) {
+        if let Some(api_key) = api_key {
+            self.default_api_key = api_key.to_owned();
+        }
+        self.clients.clear();
+    }
+}
+
+enum AlertOutcome {
+    Created(Page),
+    Aggregated(AggregatedAlert),
+}
+
+impl NotionAlert {
+    /// Appends the alert to the open page of its aggr_key or creates a new one. Errors come with the alert
+    /// to queue if Notion is not available, which only has what was not sent yet.
+    fn process_alert(&mut self, destination : &mut Destination, aggr_key : &str, alert : &SiemAlert, template : &PageTemplate) -> Result<AlertOutcome, (NotionError, Option<Box<PendingAlert>>)> {
+        if let Err(e) = self.ensure_database(destination) {
+            let pending = match e.is_retriable() {
+                true => Some(Box::new(self.pending_alert(&destination.client, aggr_key, alert, template))),
+                false => None
+            };
+            return Err((e, pending));
+        }
+        let client = &destination.client;
+        if let Some(mut entry) = self.aggregator.find(self.conn.as_ref(), aggr_key, alert) {
+            let number = entry.occurrences + 1;
+            let (error, remaining) = match client.append_blocks(&entry.page_id, &entry.url, client.occurrence_blocks(alert, number)) {
+                Ok(_) => match client.update_occurrences(&entry.page_id, &alert_date(alert), number) {
+                    Ok(_) => {
+                        entry.occurrences = number;
+                        self.aggregator.save(self.conn.as_mut(), aggr_key, entry.clone());
+                        return Ok(AlertOutcome::Aggregated(entry));
+                    },
+                    // The blocks are in the page, only the properties are updated again
+                    Err(e) => (e, Vec::new())
+                },
+                Err(NotionError::Incomplete(incomplete)) => (incomplete.error, incomplete.remaining),
+                Err(e) => (e, Vec::new())
+            };
+            if error.is_retriable() {
+                let pending = self.pending_occurrence(client, aggr_key, entry, number, alert, &remaining);
+                return Err((error, Some(Box::new(pending))));
+            }
+            // The page was deleted or archived: open a new one
+            self.aggregator.close(self.conn.as_mut(), aggr_key);
+        }
+        let result = client.send_alert_with_template(alert, template);
+        self.register_page(aggr_key, alert.aggr_limit, &result);
+        match result {
+            Ok(page) => Ok(AlertOutcome::Created(page)),
+            Err(e) if e.is_retriable() => {
+                let mut pending = PendingAlert::new(alert, aggr_key, &client.alert_page_with_template(alert, template), now());
+                if let NotionError::Incomplete(incomplete) = &e {
+                    pending.set_incomplete(incomplete);
+                }
+                Err((e, Some(Box::new(pending))))
+            },
+            Err(e) => Err((e, None))
+        }
+    }
+
+    /// Alert to queue when the database could not be checked: a new occurrence in the open page of its
+    /// aggr_key, or a new page
+    fn pending_alert(&mut self, client : &NotionClient, aggr_key : &str, alert : &SiemAlert, template : &PageTemplate) -> PendingAlert {
+        match self.aggregator.find(self.conn.as_ref(), aggr_key, alert) {
+            Some(entry) => {
+                let number = entry.occurrences + 1;
+                self.pending_occurrence(client, aggr_key, entry, number, alert, &client.occurrence_blocks(alert, number))
+            },
+            None => PendingAlert::new(alert, aggr_key, &client.alert_page_with_template(alert, template), now())
+        }
+    }
+
+    /// Occurrence to queue. Its number is reserved, so the following alerts are numbered after it.
+    fn pending_occurrence(&mut self, client : &NotionClient, aggr_key : &str, mut entry : AggregatedAlert, number : u64, alert : &SiemAlert, blocks : &[BlockElement]) -> PendingAlert {
+        entry.occurrences = number;
+        let pending = PendingAlert::occurrence(alert, aggr_key, &entry, client.database_id(), blocks, now());
+        self.aggregator.save(self.conn.as_mut(), aggr_key, entry);
+        pending
+    }
+
+    /// Opens the aggregation of the page created for an aggregable alert. The page exists even if some
+    /// blocks are missing: they are appended later.
+    fn register_page(&mut self, aggr_key : &str, aggr_limit : i64, result : &NotionResult<Page>) {
+        let created = match result {
+            Ok(page) => (&page.id, &page.url),
+            Err(NotionError::Incomplete(incomplete)) => (&incomplete.page_id, &incomplete.url),
+            Err(_) => return
+        };
+        if aggr_key.is_empty() || aggr_limit <= 0 {
+            return;
+        }
+        self.aggregator.save(self.conn.as_mut(), aggr_key, AggregatedAlert {
+            page_id : created.0.clone(),
+            url : created.1.clone(),
+            aggr_limit,
+            occurrences : 1
+        });
+    }
+
+    /// Sends what is left of a queued alert and returns the url of its page
+    fn replay(&mut self, client : &NotionClient, pending : &mut PendingAlert) -> NotionResult<String> {
+        let page = pending.page()?;
+        let page_id = match &pending.page_id {
+            Some(page_id) => page_id.clone(),
+            None => {
+                let result = client.create_page(page);
+                self.register_page(&pending.aggr_key, pending.aggr_limit, &result);
+                return result.map(|page| page.url);
+            }
+        };
+        if !page.children.is_empty() {
+            client.append_blocks(&page_id, &pending.url, page.children)?;
+            pending.set_appended();
+        }
+        if let Some(occurrence) = &pending.occurrence {
+            // A later occurrence already updated the properties
+            let latest = self.aggregator.get(self.conn.as_ref(), &pending.aggr_key).map(|v| v.occurrences);
+            if latest == Some(occurrence.number) {
+                client.update_occurrences(&page_id, &occurrence.last_seen, occurrence.number)?;
+            }
+        }
+        Ok(pending.url.clone())
+    }
+
+    /// Sends the alert to the databases of the first routing rule that matches it, or to the database
+    /// of its tenant. Alerts are dropped if the rule has no destinations.
+    fn handle_alert(&mut self, destinations : &mut Destinations, alert : &SiemAlert) {
+        let tenant = alert.log.tenant();
+        let tenant_route = self.route(destinations, tenant);
+        let routes = match self.routes.route(alert) {
+            None => vec![tenant_route],
+            Some(targets) => {
+                let mut routes = Vec::with_capacity(targets.len());
+                for target in targets {
+                    let route = match target {
+                        RouteTarget::Default => tenant_route.clone(),
+                        RouteTarget::Database(database_id) => (database_id.clone(), self.api_key(destinations, tenant, database_id))
+                    };
+                    if !routes.contains(&route) {
+                        routes.push(route);
+                    }
+                }
+                routes
+            }
+        };
+        if routes.is_empty() {
+            return;
+        }
+        let template = self.template(alert);
+        for (database_id, api_key) in routes {
+            self.send_alert(destinations, &database_id, &api_key, alert, &template);
+        }
+    }
+
+    fn send_alert(&mut self, destinations : &mut Destinations, database_id : &str, api_key : &str, alert : &SiemAlert, template : &PageTemplate) {
+        let aggr_key = aggregation_key(alert, database_id, &destinations.default_database);
+        let destination = match destinations.get(self, database_id, api_key) {
+            Ok(destination) => destination,
+            Err(e) => return self.notify(alert_error_message(&alert.title, &alert.rule, &e))
+        };
+        match self.process_alert(destination, &aggr_key, alert, template) {
+            Ok(AlertOutcome::Created(page)) => {
+                self.count_alert();
+                self.notify(format!("Alert '{}' created in Notion: {}", alert.title, page.url));
+            },
+            Ok(AlertOutcome::Aggregated(entry)) => {
+                self.notify(format!("Alert '{}' aggregated in Notion ({} occurrences): {}", alert.title, entry.occurrences, entry.url));
+            },
+            Err((e, Some(pending))) => {
+                let mut pending = *pending;
+                self.queue.reschedule(&mut pending, now());
+                if let Some(discarded) = self.queue.push(pending) {
+                    self.notify(format!("Alert '{}' discarded, the queue of pending alerts is full", discarded.title));
+                }
+                self.queue.persist(self.conn.as_mut());
+                self.notify(format!("Alert '{}' queued ({} pending), Notion is not available: {}", alert.title, self.queue.len(), e));
+            },
+            Err((e, None)) => self.notify(alert_error_message(&alert.title, &alert.rule, &e))
+        }
+    }
+
+    /// Sends the queued alerts whose next attempt is due. If Notion is still not available
+    /// the remaining alerts wait for the next attempt.
+    fn retry_pending(&mut self, destinations : &mut Destinations) {
+        let now = now();
+        let mut due = self.queue.take_due(now).into_iter();
+        let mut attempted = false;
+        while let Some(mut pending) = due.next() {
+            attempted = true;
+            let database_id = match pending.database_id() {
+                "" => destinations.default_database.clone(),
+                v => v.to_owned()
+            };
+            let api_key = self.api_key(destinations, &pending.tenant, &database_id);
+            let result = match destinations.get(self, &database_id, &api_key) {
+                Ok(destination) => match self.ensure_database(destination) {
+                    Ok(_) => self.replay(&destination.client, &mut pending),
+                    Err(e) => Err(e)
+                },
+                Err(e) => Err(e)
+            };
+            match result {
+                Ok(url) => match &pending.occurrence {
+                    Some(occurrence) => self.notify(format!("Alert '{}' aggregated in Notion ({} occurrences): {}", pending.title, occurrence.number, url)),
+                    None => {
+                        self.count_alert();
+                        self.notify(format!("Alert '{}' created in Notion: {}", pending.title, url));
+                    }
+                },
+                Err(e) if e.is_retriable() => {
+                    if let NotionError::Incomplete(incomplete) = &e {
+                        pending.set_incomplete(incomplete);
+                    }
+                    let retry = self.queue.reschedule(&mut pending, now);
+                    let next_attempt = pending.next_attempt;
+                    let mut waiting = Vec::new();
+                    if retry {
+                        waiting.push(pending);
+                    } else {
+                        self.notify(alert_error_message(&pending.title, &pending.rule, &e));
+                    }
+                    for mut other in due {
+                        other.next_attempt = other.next_attempt.max(next_attempt);
+                        waiting.push(other);
+                    }
+                    self.queue.restore(waiting);
+                    break;
+                },
+                Err(e) => self.notify(alert_error_message(&pending.title, &pending.rule, &e))
+            }
+        }
+        if attempted {
+            self.queue.persist(self.conn.as_mut());
+        }
+    }
+
+    /// Checks the database on its first alert, or if Notion was not available when the component started.
+    /// Invalid databases are checked again with the next alert.
+    fn ensure_database(&self, destination : &mut Destination) -> NotionResult<()> {
+        if !destination.checked {
+            let report = destination.client.check_valid_siem_database()?;
+            if !report.is_valid() {
+                return Err(NotionError::InvalidSchema(report));
+            }
+            if !report.is_empty() {
+                self.notify(report.to_string());
+            }
+            destination.checked = true;
+        }
+        Ok(())
+    }
+
+    /// Database and API key of the alerts of the tenant. Tenants without a database in
+    /// NotionAlerterTenants use the default database.
+    fn route(&self, destinations : &Destinations, tenant : &str) -> (String, String) {
+        let database_id = match self.custom_map(NOTION_TENANTS).and_then(|tenants| tenants.get(tenant)) {
+            Some(database_id) if !tenant.is_empty() => database_id.to_string(),
+            _ => destinations.default_database.clone()
+        };
+        let api_key = self.api_key(destinations, tenant, &database_id);
+        (database_id, api_key)
+    }
+
+    /// Tenant databases use the `API_KEY.<tenant>` secret if it is set
+    fn api_key(&self, destinations : &Destinations, tenant : &str, database_id : &str) -> String {
+        if database_id == destinations.default_database {
+            return destinations.default_api_key.clone();
+        }
+        self.secrets().and_then(|secrets| secrets.get(&format!("{}.{}", NOTION_API_KEY, tenant)))
+            .map(|v| v.to_string())
+            .unwrap_or_else(|| destinations.default_api_key.clone())
+    }
+
+    /// Page template of the rule of the alert. Invalid templates are notified once and the default one is used.
+    fn template(&mut self, alert : &SiemAlert) -> Arc<PageTemplate> {
+        let (template, error) = self.templates.get(&alert.rule);
+        if let Some(e) = error {
+            self.notify(format!("Invalid page template of rule '{}' in {}, the default template is used: {}", alert.rule, NOTION_TEMPLATES, e));
+        }
+        template
+    }
+
+    /// Replaces the routing rules. Invalid rules are notified and the previous ones are kept.
+    fn load_routes(&mut self, dataset : &TextMapSynDataset) -> bool {
+        match RoutingTable::from_dataset(dataset) {
+            Ok(routes) => {
+                self.routes = routes;
+                true
+            },
+            Err(e) => {
+                self.notify(format!("Invalid routing rules in {}, the previous rules are kept: {}", NOTION_ROUTES, e));
+                false
+            }
+        }
+    }
+
+    /// Stores the dataset in place of the previous one of its type. The clients are created again
+    /// when the secrets or the property mapping change.
+    fn update_dataset(&mut self, destinations : &mut Destinations, dataset : &SiemDataset) {
+        let dataset_type = dataset.dataset_type();
+        let mut datasets : Vec<SiemDataset> = [
+            SiemDatasetType::Secrets(Cow::Borrowed("NotionAlerter")),
+            SiemDatasetType::CustomMapText(Cow::Borrowed(NOTION_PROPERTIES)),
+            SiemDatasetType::CustomMapText(Cow::Borrowed(NOTION_TENANTS)),
+            SiemDatasetType::CustomMapText(Cow::Borrowed(NOTION_ROUTES)),
+            SiemDatasetType::CustomMapText(Cow::Borrowed(NOTION_TEMPLATES)),
+        ].iter()
+            .filter(|known| **known != dataset_type)
+            .filter_map(|known| self.datasets.get(known).cloned())
+            .collect();
+        datasets.push(dataset.clone());
+        self.datasets = DatasetHolder::from_datasets(datasets);
+        if dataset_type == SiemDatasetType::Secrets(Cow::Borrowed("NotionAlerter")) {
+            let api_key = self.secrets().and_then(|secrets| secrets.get(NOTION_API_KEY)).map(|v| v.to_string());
+            destinations.reset(api_key.as_deref());
+        } else if dataset_type == SiemDatasetType::CustomMapText(Cow::Borrowed(NOTION_PROPERTIES)) {
+            destinations.reset(None);
+        } else if dataset.dataset_type() == SiemDatasetType::CustomMapText(Cow::Borrowed(NOTION_ROUTES)) {
+            if let Ok(dataset) = dataset.try_into() {
+                if self.load_routes(dataset) {
+                    self.notify(format!("Loaded {} routing rules", self.routes.len()));
+                }
+            }
+        } else if dataset.dataset_type() == SiemDatasetType::CustomMapText(Cow::Borrowed(NOTION_TEMPLATES)) {
+            if let Ok(dataset) = <&TextMapSynDataset>::try_from(dataset) {
+                self.templates = PageTemplates::new(dataset.clone());
+            }
+        }
+    }
+
+    fn secrets(&self) -> Option<&TextMapSynDataset> {
+        self.datasets.get(&SiemDatasetType::Secrets(Cow::Borrowed("NotionAlerter"))).and_then(|v| v.try_into().ok())
+    }
+
+    fn custom_map(&self, name : &'static str) -> Option<&TextMapSynDataset> {
+        self.datasets.get(&SiemDatasetType::CustomMapText(Cow::Borrowed(name))).and_then(|v| v.try_into().ok())
+    }
+
+    /// Client with the API_URL, the property mapping and the retry policy of the component
+    fn client_builder(&self, api_key : &str, database_id : &str) -> NotionClientBuilder {
+        let mut builder = NotionClient::builder(api_key, database_id);
+        if let Some(api_url) = self.secrets().and_then(|secrets| secrets.get(NOTION_API_URL)) {
+            builder = builder.base_url(api_url);
+        }
+        if let Some(properties) = self.custom_map(NOTION_PROPERTIES) {
+            builder = builder.property_mapping(PropertyMapping::from_dataset(properties));
+        }
+        builder.retry_policy(self.retry.clone())
+    }
+
+    fn count_alert(&self) {
+        if let SiemMetric::Counter(counter) = &self.metrics.generated_alerts {
+            counter.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn notify(&self, message : String) {
+        let _ = self.kernel.send(SiemMessage::Notification(self.id, Cow::Owned(message)));
+    }
+}
+
+impl Default for NotionAlert {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl SiemComponent for NotionAlert {
@@ -67,47 +529,97 @@ impl SiemComponent for NotionAlert {
     }
 
     fn run(&mut self) {
-        let secret_dataset : &TextMapSynDataset = match self.datasets.get(&SiemDatasetType::Secrets(Cow::Borrowed("NotionAlerter"))) {
-            Some(v) => match v.try_into() {
-                Ok(v) => v,
-                Err(_) => return
-            },
+        let secret_dataset = match self.secrets() {
+            Some(v) => v,
             None => return 
         };
         let api_key = match secret_dataset.get(NOTION_API_KEY) {
-            Some(v) => v,
+            Some(v) => v.to_string(),
             None => return
         };
+        // Without DATABASE_ID, the database created in PARENT_PAGE_ID by a previous run is used
         let database_id = match secret_dataset.get(NOTION_DATABASE) {
-            Some(v) => v,
-            None => return
+            Some(v) => v.to_string(),
+            None => match (self.conn.get_value(Cow::Borrowed(CREATED_DATABASE)), secret_dataset.get(NOTION_PARENT_PAGE)) {
+                (Ok(v), _) if !v.is_empty() => v,
+                (_, Some(_)) => String::new(),
+                _ => return
+            }
         };
-        let client = client::NotionClient::new(api_key, database_id);
-        if !client.check_valid_siem_database().unwrap() {
-            return;
+        let parent_page = secret_dataset.get(NOTION_PARENT_PAGE).map(|v| v.to_string());
+        let mut client = match self.client_builder(&api_key, &database_id).build() {
+            Ok(client) => client,
+            Err(e) => {
+                self.notify(format!("Cannot access the Notion database: {}", e));
+                return;
+            }
+        };
+        if let (true, Some(parent_page)) = (database_id.is_empty(), parent_page) {
+            match client.create_siem_database(&parent_page, NEW_DATABASE_TITLE) {
+                Ok(database) => {
+                    let _ = self.conn.set_value(Cow::Borrowed(CREATED_DATABASE), database.id.clone(), true);
+                    self.notify(format!("Created the Notion database {} for the alerts", database.id));
+                },
+                Err(e) => {
+                    self.notify(format!("Cannot create the Notion database: {}", e));
+                    return;
+                }
+            }
+        }
+        if self.migration != SchemaMigrationMode::Disabled {
+            let dry_run = self.migration == SchemaMigrationMode::DryRun;
+            match client.migrate_siem_database(dry_run) {
+                Ok(migration) if migration.changes.is_empty() && migration.unresolved.is_empty() => {},
+                Ok(migration) if dry_run => self.notify(format!("Planned changes of the Notion database: {}", migration)),
+                Ok(migration) => self.notify(format!("Migrated the Notion database: {}", migration)),
+                Err(e) => self.notify(format!("Cannot migrate the Notion database: {}", e))
+            }
+        }
+        self.queue.load(self.conn.as_ref());
+        if let Some(routes) = self.custom_map(NOTION_ROUTES).cloned() {
+            self.load_routes(&routes);
         }
+        if let Some(templates) = self.custom_map(NOTION_TEMPLATES).cloned() {
+            self.templates = PageTemplates::new(templates);
+        }
+        let mut checked = false;
+        match client.check_valid_siem_database() {
+            Ok(report) => {
+                if !report.is_empty() {
+                    self.notify(report.to_string());
+                }
+                // The database is checked again before each alert, until it is fixed
+                checked = report.is_valid();
+            },
+            // Alerts are queued until Notion is available
+            Err(e) if e.is_retriable() => self.notify(format!("Notion is not available, alerts will be queued: {}", e)),
+            Err(e) => {
+                self.notify(format!("Cannot access the Notion database: {}", e));
+                return;
+            }
+        }
+        let mut destinations = Destinations::new(Destination { client, checked }, &api_key);
 
         loop {
-            let msg = self.local_channel.1.recv().unwrap();
-            match msg {
-                SiemMessage::Alert(alert) => {
-                    match send_alert_with_retry(&client, &alert) {
-                        Ok(_) => {},
-                        Err(_) => {
-                            self.kernel.send(SiemMessage::Notification(self.id, Cow::Owned(format!("Cannot generate alert: {:?}", alert)))).unwrap();
-                        }
-                    }
+            let msg = match self.queue.next_attempt() {
+                Some(next_attempt) => match self.local_channel.1.recv_timeout(Duration::from_millis((next_attempt - now()).max(0) as u64)) {
+                    Ok(msg) => Some(msg),
+                    Err(RecvTimeoutError::Timeout) => None,
+                    Err(RecvTimeoutError::Disconnected) => return
                 },
-                SiemMessage::Command(_,call) => {
-                    match call {
-                        command::SiemCommandCall::STOP_COMPONENT(_) => return,
-                        _ => {},
-                    }
+                None => match self.local_channel.1.recv() {
+                    Ok(msg) => Some(msg),
+                    Err(_) => return
                 }
+            };
+            match msg {
+                Some(SiemMessage::Alert(alert)) => self.handle_alert(&mut destinations, &alert),
+                Some(SiemMessage::Dataset(dataset)) => self.update_dataset(&mut destinations, &dataset),
+                Some(SiemMessage::Command(_, command::SiemCommandCall::STOP_COMPONENT(_))) => return,
                 _ => {},
             }
+            self.retry_pending(&mut destinations);
         }
-        
     }
 
     fn set_storage(&mut self, conn: Box<dyn SiemComponentStateStorage>) {
@@ -117,7 +629,23 @@ impl SiemComponent for NotionAlert {
     fn capabilities(&self) -> SiemComponentCapabilities {
         let datasets = vec![DatasetDefinition::new(
             dataset::SiemDatasetType::Secrets(Cow::Borrowed("NotionAlerter")),
-            Cow::Borrowed("Access notion API_KEY and DATABASE_ID"),
+            Cow::Borrowed("Access notion API_KEY, DATABASE_ID or PARENT_PAGE_ID and optionally API_URL and the API_KEY.<tenant> of each tenant"),
+            UserRole::Engineer,
+        ), DatasetDefinition::new(
+            dataset::SiemDatasetType::CustomMapText(Cow::Borrowed(NOTION_PROPERTIES)),
+            Cow::Borrowed("Optional names or ids of the database properties where the alert fields are stored"),
+            UserRole::Engineer,
+        ), DatasetDefinition::new(
+            dataset::SiemDatasetType::CustomMapText(Cow::Borrowed(NOTION_TENANTS)),
+            Cow::Borrowed("Optional database id of the alerts of each tenant"),
+            UserRole::Engineer,
+        ), DatasetDefinition::new(
+            dataset::SiemDatasetType::CustomMapText(Cow::Borrowed(NOTION_ROUTES)),
+            Cow::Borrowed("Optional routing rules of the alerts by severity, rule, tags and MITRE techniques"),
+            UserRole::Engineer,
+        ), DatasetDefinition::new(
+            dataset::SiemDatasetType::CustomMapText(Cow::Borrowed(NOTION_TEMPLATES)),
+            Cow::Borrowed("Optional page templates of each rule or rule prefix"),
             UserRole::Engineer,
         )];
         let metrics = vec![SiemMetricDefinition {
@@ -147,63 +675,66 @@ impl SiemComponent for NotionAlert {
     }
 
     fn id(&self) -> u64 {
-        return self.id;
+        self.id
     }
 
     fn name(&self) -> &str {
-        return &"NotionAlerter";
+        "NotionAlerter"
     }
 }
 
-
-fn send_alert_with_retry(client :&NotionClient, alert :&SiemAlert) -> NotionResult<()>{
-    let mut errors = 0;
-    loop {
-        if let Err(e) = client.send_alert(alert) {
-            errors += 1;
-            if errors > 5 {
-                return Err(e)
-            }
-        }else{
-            return Ok(())
-        }
-        
-    }
+fn alert_error_message(title : &str, rule : &str, error : &NotionError) -> String {
+    let reason = match error.code() {
+        Some(NotionErrorCode::Unauthorized) => "the API key is not valid",
+        _ if matches!(error, NotionError::InvalidApiKey) => "the API key is not valid",
+        Some(NotionErrorCode::RestrictedResource) | Some(NotionErrorCode::ObjectNotFound) => "the database is not shared with the integration",
+        Some(NotionErrorCode::ValidationError) => "the database schema does not accept the alert",
+        _ if matches!(error, NotionError::InvalidSchema(_)) => "the database is not valid",
+        _ if error.is_retriable() => "Notion is not available",
+        _ => "the request was rejected"
+    };
+    format!("Cannot generate alert '{}' of rule '{}', {}: {}", title, rule, reason, error)
 }
 
+fn now() -> i64 {
+    usiem::chrono::Utc::now().timestamp_millis()
+}
 
 #[cfg(test)]
 mod component {
-    use std::{borrow::Cow, sync::Arc, time::UNIX_EPOCH};
+    use std::{borrow::Cow, sync::Arc};
 
     use usiem::{prelude::{dataset::{SiemDataset, text_map::{TextMapSynDataset, TextMapDataset}, holder::DatasetHolder}, SiemComponent, command::{SiemCommandHeader, SiemCommandCall}, mitre::MitreTechniques, alert::{AlertSeverity, SiemAlert}, SiemLog, SiemEvent, auth::{AuthEvent, LoginOutcome, AuthLoginType, RemoteLogin}}, crossbeam_channel::bounded, components::common::SiemMessage};
 
     use crate::NotionAlert;
+    use crate::mock::{MockNotionServer, MockResponse, MemoryStateStorage};
 
-    use super::{NOTION_DATABASE, NOTION_API_KEY};
+    use super::{Destination, Destinations, NOTION_DATABASE, NOTION_API_KEY, NOTION_API_URL, NOTION_PROPERTIES, NOTION_PARENT_PAGE, NOTION_TENANTS, NOTION_ROUTES, NOTION_TEMPLATES, now};
+    use crate::queue::PendingAlert;
+    use crate::retry::RetryPolicy;
+    use crate::schema::SchemaMigrationMode;
+    use crate::template::PageTemplate;
+    use std::time::Duration;
+    use usiem::components::common::SiemComponentStateStorage;
 
-    #[test]
-    fn shoul_generate_alert() {
-        let db_id : String = match std::env::var("USIEM_NOTION_DB") {
-            Ok(v) => v,
-            Err(_) => return
-        };
-        let api_key = std::env::var("USIEM_NOTION_APIKEY").expect("USIEM_NOTION_DB is defined but not USIEM_NOTION_APIKEY");
+    const API_KEY : &str = "secret_test";
+    const DATABASE_ID : &str = "d9824bdc-8445-4327-be8b-5b47500af6ce";
 
-        let mut dataset = TextMapDataset::new();
-        dataset.insert(Cow::Borrowed(NOTION_DATABASE), Cow::Owned(db_id));
-        dataset.insert(Cow::Borrowed(NOTION_API_KEY), Cow::Owned(api_key));
+    fn secrets(server : &MockNotionServer) -> DatasetHolder {
+        DatasetHolder::from_datasets(vec![secret_dataset(server)])
+    }
 
+    fn secret_dataset(server : &MockNotionServer) -> SiemDataset {
+        let mut dataset = TextMapDataset::new();
+        dataset.insert(Cow::Borrowed(NOTION_DATABASE), Cow::Borrowed(DATABASE_ID));
+        dataset.insert(Cow::Borrowed(NOTION_API_KEY), Cow::Borrowed(API_KEY));
+        dataset.insert(Cow::Borrowed(NOTION_API_URL), Cow::Owned(server.base_url()));
         let (comm, _recv) = bounded(10);
         let dataset = Arc::new(dataset);
-        let secret_dataset = SiemDataset::Secrets((Cow::Borrowed("NotionAlerter"), TextMapSynDataset::new(dataset, comm)));
-        let datasets = DatasetHolder::from_datasets(vec![secret_dataset]);
-        let mut comp = NotionAlert::new();
-        comp.set_datasets(datasets);
-        let local_channel = comp.local_channel();
-        let join = std::thread::spawn(move || {
-            comp.run();
-        });
+        SiemDataset::Secrets((Cow::Borrowed("NotionAlerter"), TextMapSynDataset::new(dataset, comm)))
+    }
+
+    fn test_alert() -> SiemAlert {
         let mut log = SiemLog::new(String::from("2021/02/05 15:05:55,0123456789,TRAFFIC,drop,2305,2021/02/05 15:05:55,192.168.2.1,192.168.3.2,0.0.0.0,0.0.0.0,intrazone-default,,,not-applicable,vsys1,untrust,untrust,ethernet1,,Log Collector,2021/02/05 15:05:55,0,1,35861,514,0,0,0x0,udp,deny,102,102,0,1,2021/02/08 16:06:59,0,any,0,3996658540,0x0,192.168.0.0-192.168.255.255,192.168.0.0-192.168.255.255,0,1,0,policy-deny,0,0,0,0,,pa-test,from-policy,,,0,,0,,N/A,0,0,0,0,aa1d1908-68cd-467a-bc90-68a57096ef3b,0,0,,,,,,,"), 0, "localhost");
         log.set_tenant(Cow::Borrowed("Contoso"));
         log.set_event(SiemEvent::Auth(AuthEvent {
@@ -215,25 +746,688 @@ mod component {
                 user_name: Cow::Borrowed("cancamusa"),
             }),
         }));
-        let alert = SiemAlert {
-            title: format!("(TEST) shoul_generate_alert {:?}", std::time::SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs()),
+        SiemAlert {
+            title: String::from("(TEST) shoul_generate_alert"),
             description: String::from("This is a test of the NotionAlerter component"),
             severity: AlertSeverity::CRITICAL,
             date: usiem::chrono::Utc::now().timestamp_millis(),
             tags: vec![String::from("Critical")],
             techniques : vec![MitreTechniques::T1001, MitreTechniques::T1003_001],
             rule: String::from("ruleset::example::rule1"),
-            log: log.clone(),
+            log,
             aggr_limit: 0,
             aggr_key: String::from("example::rule"),
-        };
-        local_channel.send(SiemMessage::Alert(alert)).unwrap();
-        local_channel.send(SiemMessage::Command(SiemCommandHeader{
+        }
+    }
+
+    fn stop_command() -> SiemMessage {
+        SiemMessage::Command(SiemCommandHeader{
             comm_id :0,
             comp_id : 0,
-            user : format!("zero")
-        }, SiemCommandCall::STOP_COMPONENT(String::new()))).unwrap();
+            user : "zero".to_owned()
+        }, SiemCommandCall::STOP_COMPONENT(String::new()))
+    }
+
+    #[test]
+    fn shoul_generate_alert() {
+        let server = MockNotionServer::start(API_KEY);
+        server.add_siem_database(DATABASE_ID);
+        let (kernel, kernel_recv) = bounded(10);
+        let mut comp = NotionAlert::new();
+        comp.set_datasets(secrets(&server));
+        comp.set_kernel_sender(kernel);
+        let local_channel = comp.local_channel();
+        let join = std::thread::spawn(move || {
+            comp.run();
+        });
+        local_channel.send(SiemMessage::Alert(test_alert())).unwrap();
+        local_channel.send(stop_command()).unwrap();
+        join.join().unwrap();
+        let pages = server.pages();
+        assert_eq!(1, pages.len());
+        match kernel_recv.try_recv() {
+            Ok(SiemMessage::Notification(_, msg)) => assert_eq!(format!("Alert '(TEST) shoul_generate_alert' created in Notion: {}", pages[0]["url"].as_str().unwrap()), msg),
+            _ => panic!("Expected a notification")
+        }
+        assert_eq!("(TEST) shoul_generate_alert", pages[0]["properties"]["Name"]["title"][0]["plain_text"]);
+    }
+
+    #[test]
+    fn should_notify_invalid_database() {
+        let server = MockNotionServer::start(API_KEY);
+        let (kernel, kernel_recv) = bounded(10);
+        let mut comp = NotionAlert::new();
+        comp.set_datasets(secrets(&server));
+        comp.set_kernel_sender(kernel);
+        comp.run();
+        match kernel_recv.try_recv() {
+            Ok(SiemMessage::Notification(_, msg)) => assert!(msg.contains("object_not_found")),
+            _ => panic!("Expected a notification")
+        }
+    }
+
+    #[test]
+    fn should_notify_schema_report() {
+        let server = MockNotionServer::start(API_KEY);
+        let mut database = crate::mock::siem_database(DATABASE_ID);
+        database["properties"].as_object_mut().unwrap().remove("Status");
+        database["properties"]["Fired"] = usiem::serde_json::json!({"id": "fIrd", "name": "Fired", "type": "rich_text", "rich_text": {}});
+        server.add_database(database);
+        // The component keeps running with an invalid database, it can be fixed by hand
+        let notifications = run_component(NotionAlert::new(), vec![secret_dataset(&server)], &MemoryStateStorage::new(), vec![]);
+        assert_eq!(vec!["The Notion database does not have the required properties: missing status property 'Status', property 'Fired' is rich_text instead of date".to_owned()], notifications);
+    }
+
+    #[test]
+    fn should_notify_rejected_alert() {
+        let server = MockNotionServer::start(API_KEY);
+        let mut database = crate::mock::siem_database(DATABASE_ID);
+        database["properties"]["Fired"]["type"] = "rich_text".into();
+        server.add_database(database);
+        let (kernel, kernel_recv) = bounded(10);
+        let mut comp = NotionAlert::new();
+        comp.set_datasets(secrets(&server));
+        comp.set_kernel_sender(kernel);
+        let local_channel = comp.local_channel();
+        // The schema check gets a valid definition, but the stored database has Fired as rich_text
+        server.push_response(MockResponse::new(200, crate::mock::siem_database(DATABASE_ID)));
+        let join = std::thread::spawn(move || {
+            comp.run();
+        });
+        local_channel.send(SiemMessage::Alert(test_alert())).unwrap();
+        local_channel.send(stop_command()).unwrap();
+        join.join().unwrap();
+        match kernel_recv.try_recv() {
+            Ok(SiemMessage::Notification(_, msg)) => {
+                assert!(msg.contains("the database schema does not accept the alert"), "{}", msg);
+                assert!(msg.contains("validation_error"), "{}", msg);
+            },
+            _ => panic!("Expected a notification")
+        }
+    }
+
+    fn aggregable_alert() -> SiemAlert {
+        let mut alert = test_alert();
+        alert.aggr_limit = alert.date + 3_600_000;
+        alert
+    }
+
+    fn run_alerts(server : &MockNotionServer, storage : &MemoryStateStorage, alerts : Vec<SiemAlert>) -> Vec<String> {
+        run_component(NotionAlert::new(), vec![secret_dataset(server)], storage, alerts.into_iter().map(SiemMessage::Alert).collect())
+    }
+
+    /// Runs the component with the datasets until it has processed the messages and returns its notifications
+    fn run_component(mut comp : NotionAlert, datasets : Vec<SiemDataset>, storage : &MemoryStateStorage, messages : Vec<SiemMessage>) -> Vec<String> {
+        let (kernel, kernel_recv) = bounded(10);
+        comp.set_datasets(DatasetHolder::from_datasets(datasets));
+        comp.set_kernel_sender(kernel);
+        comp.set_storage(Box::new(storage.clone()));
+        let local_channel = comp.local_channel();
+        let join = std::thread::spawn(move || {
+            comp.run();
+        });
+        for message in messages {
+            let _ = local_channel.send(message);
+        }
+        let _ = local_channel.send(stop_command());
         join.join().unwrap();
+        kernel_recv.try_iter().filter_map(|msg| match msg {
+            SiemMessage::Notification(_, msg) => Some(msg.into_owned()),
+            _ => None
+        }).collect()
+    }
+
+    #[test]
+    fn should_aggregate_alerts() {
+        let server = MockNotionServer::start(API_KEY);
+        server.add_siem_database(DATABASE_ID);
+        let storage = MemoryStateStorage::new();
+        let notifications = run_alerts(&server, &storage, vec![aggregable_alert(), aggregable_alert(), aggregable_alert()]);
+        let pages = server.pages();
+        assert_eq!(1, pages.len());
+        let url = pages[0]["url"].as_str().unwrap();
+        assert_eq!(format!("Alert '(TEST) shoul_generate_alert' aggregated in Notion (3 occurrences): {}", url), notifications[2]);
+        let children = server.children(pages[0]["id"].as_str().unwrap());
+        let occurrences = children.iter().filter(|b| b["heading_3"]["rich_text"][0]["plain_text"].as_str().unwrap_or_default().starts_with("Occurrence")).count();
+        assert_eq!(2, occurrences);
+        assert_eq!(vec!["notion_aggr:example::rule"], storage.keys());
+
+        // A restarted component keeps using the same page
+        run_alerts(&server, &storage, vec![aggregable_alert()]);
+        assert_eq!(1, server.pages().len());
+
+        // Expired aggregations open a new page
+        let mut expired = aggregable_alert();
+        expired.date += 3_600_000;
+        expired.aggr_limit = expired.date + 3_600_000;
+        run_alerts(&server, &storage, vec![expired, test_alert()]);
+        assert_eq!(3, server.pages().len());
+    }
+
+    #[test]
+    fn should_open_new_page_when_archived() {
+        let server = MockNotionServer::start(API_KEY);
+        server.add_siem_database(DATABASE_ID);
+        let storage = MemoryStateStorage::new();
+        run_alerts(&server, &storage, vec![aggregable_alert()]);
+        let page_id = server.pages()[0]["id"].as_str().unwrap().to_owned();
+        let client = crate::client::NotionClient::builder(API_KEY, DATABASE_ID).base_url(&server.base_url()).build().unwrap();
+        client.archive_page(&page_id).unwrap();
+        let notifications = run_alerts(&server, &storage, vec![aggregable_alert(), aggregable_alert()]);
+        assert_eq!(2, server.pages().len());
+        assert!(notifications[0].contains("created in Notion"), "{}", notifications[0]);
+        assert!(notifications[1].contains("(2 occurrences)"), "{}", notifications[1]);
+    }
 
+    fn fast_policy(max_retries : u32) -> RetryPolicy {
+        RetryPolicy {
+            max_retries,
+            base_delay : Duration::from_millis(10),
+            max_delay : Duration::from_millis(20)
+        }
+    }
+
+    fn unavailable() -> MockResponse {
+        MockResponse::error(503, "service_unavailable", "Notion is unavailable")
+    }
+
+    /// Waits for the notifications until one contains the text
+    fn wait_notification(kernel_recv : &usiem::crossbeam_channel::Receiver<SiemMessage>, notifications : &mut Vec<String>, text : &str) {
+        while let Ok(msg) = kernel_recv.recv_timeout(Duration::from_secs(5)) {
+            if let SiemMessage::Notification(_, msg) = msg {
+                notifications.push(msg.to_string());
+                if msg.contains(text) {
+                    return;
+                }
+            }
+        }
+        panic!("Expected a notification containing '{}': {:?}", text, notifications);
+    }
+
+    #[test]
+    fn should_retry_queued_alerts() {
+        let server = MockNotionServer::start(API_KEY);
+        server.add_siem_database(DATABASE_ID);
+        server.push_response(MockResponse::new(200, crate::mock::siem_database(DATABASE_ID)));
+        server.push_response(unavailable());
+        server.push_response(unavailable());
+        let storage = MemoryStateStorage::new();
+        let (kernel, kernel_recv) = bounded(10);
+        let mut comp = NotionAlert::new().retry_policy(fast_policy(1)).queue_policy(fast_policy(u32::MAX));
+        comp.set_datasets(secrets(&server));
+        comp.set_kernel_sender(kernel);
+        comp.set_storage(Box::new(storage.clone()));
+        let local_channel = comp.local_channel();
+        let join = std::thread::spawn(move || {
+            comp.run();
+        });
+        local_channel.send(SiemMessage::Alert(test_alert())).unwrap();
+        let mut notifications = Vec::new();
+        wait_notification(&kernel_recv, &mut notifications, "created in Notion");
+        local_channel.send(stop_command()).unwrap();
+        join.join().unwrap();
+        assert!(notifications[0].contains("queued (1 pending), Notion is not available"), "{:?}", notifications);
+        assert_eq!(1, server.pages().len());
+        assert_eq!(Ok("[]".to_owned()), storage.get_value(Cow::Borrowed("notion_queue")).map_err(|_| ()));
+    }
+
+    #[test]
+    fn should_resume_incomplete_pages() {
+        let server = MockNotionServer::start(API_KEY);
+        server.add_siem_database(DATABASE_ID);
+        server.push_response_for("PATCH", "/children", unavailable());
+        let storage = MemoryStateStorage::new();
+        let (kernel, kernel_recv) = bounded(10);
+        let mut comp = NotionAlert::new().retry_policy(RetryPolicy::none()).queue_policy(fast_policy(u32::MAX));
+        comp.set_datasets(secrets(&server));
+        comp.set_kernel_sender(kernel);
+        comp.set_storage(Box::new(storage.clone()));
+        let local_channel = comp.local_channel();
+        let join = std::thread::spawn(move || {
+            comp.run();
+        });
+        let mut alert = aggregable_alert();
+        alert.log = SiemLog::new("🚨".repeat(300_000), 0, "localhost");
+        local_channel.send(SiemMessage::Alert(alert.clone())).unwrap();
+        let mut notifications = Vec::new();
+        wait_notification(&kernel_recv, &mut notifications, "created in Notion");
+        local_channel.send(stop_command()).unwrap();
+        join.join().unwrap();
+        assert!(notifications[0].contains("queued (1 pending), Notion is not available: Some blocks were not appended"), "{:?}", notifications);
+        // The blocks are appended to the page created before the failure
+        let pages = server.pages();
+        assert_eq!(1, pages.len());
+        let page_id = pages[0]["id"].as_str().unwrap();
+        let blocks = server.children(page_id);
+        let message : String = blocks.iter().filter(|v| v["code"]["language"] == "plain text").flat_map(|v| v["code"]["rich_text"].as_array().unwrap().iter().map(|t| t["plain_text"].as_str().unwrap().to_owned())).collect();
+        assert_eq!(alert.log.message(), message);
+        let aggregated = storage.get_value(Cow::Borrowed("notion_aggr:example::rule")).unwrap();
+        assert!(aggregated.contains(page_id), "{}", aggregated);
+    }
+
+    #[test]
+    fn should_queue_occurrences_of_open_pages() {
+        let server = MockNotionServer::start(API_KEY);
+        let mut database = crate::mock::siem_database(DATABASE_ID);
+        database["properties"]["Occurrences"] = usiem::serde_json::json!({"id": "oCc", "name": "Occurrences", "type": "number", "number": {"format": "number"}});
+        database["properties"]["Last seen"] = usiem::serde_json::json!({"id": "lSt", "name": "Last seen", "type": "date", "date": {}});
+        server.add_database(database);
+        server.push_response_for("POST", "/pages", unavailable());
+        let (kernel, kernel_recv) = bounded(10);
+        let mut comp = NotionAlert::new().retry_policy(RetryPolicy::none()).queue_policy(fast_policy(u32::MAX));
+        comp.set_datasets(secrets(&server));
+        comp.set_kernel_sender(kernel);
+        comp.set_storage(Box::new(MemoryStateStorage::new()));
+        let local_channel = comp.local_channel();
+        let join = std::thread::spawn(move || {
+            comp.run();
+        });
+        let mut notifications = Vec::new();
+        // The page created from the queue opens the aggregation
+        local_channel.send(SiemMessage::Alert(aggregable_alert())).unwrap();
+        wait_notification(&kernel_recv, &mut notifications, "created in Notion");
+        let page_id = server.pages()[0]["id"].as_str().unwrap().to_owned();
+        // Only the properties are updated again
+        server.push_response_for("PATCH", &format!("/pages/{}", page_id), unavailable());
+        local_channel.send(SiemMessage::Alert(aggregable_alert())).unwrap();
+        wait_notification(&kernel_recv, &mut notifications, "(2 occurrences)");
+        // The blocks are appended to the open page
+        server.push_response_for("PATCH", "/children", unavailable());
+        local_channel.send(SiemMessage::Alert(aggregable_alert())).unwrap();
+        wait_notification(&kernel_recv, &mut notifications, "(3 occurrences)");
+        local_channel.send(stop_command()).unwrap();
+        join.join().unwrap();
+        assert_eq!(3, notifications.iter().filter(|v| v.contains("queued (1 pending)")).count(), "{:?}", notifications);
+        let pages = server.pages();
+        assert_eq!(1, pages.len());
+        assert_eq!(Some(3.0), pages[0]["properties"]["Occurrences"]["number"].as_f64());
+        let children = server.children(&page_id);
+        let occurrences : Vec<&str> = children.iter().filter_map(|b| b["heading_3"]["rich_text"][0]["plain_text"].as_str()).filter(|v| v.starts_with("Occurrence")).collect();
+        assert_eq!(2, occurrences.len(), "{:?}", occurrences);
+        assert!(occurrences[0].starts_with("Occurrence 2") && occurrences[1].starts_with("Occurrence 3"), "{:?}", occurrences);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn should_delay_due_alerts_after_failed_retry() {
+        let server = MockNotionServer::start(API_KEY);
+        server.add_siem_database(DATABASE_ID);
+        server.push_response(unavailable());
+        let mut comp = NotionAlert::new().retry_policy(RetryPolicy::none()).queue_policy(RetryPolicy {
+            max_retries : u32::MAX,
+            base_delay : Duration::from_secs(3600),
+            max_delay : Duration::from_secs(3600)
+        });
+        comp.set_datasets(secrets(&server));
+        let client = comp.client_builder(API_KEY, DATABASE_ID).build().unwrap();
+        let page = client.alert_page_with_template(&test_alert(), &PageTemplate::default());
+        comp.queue.push(PendingAlert::new(&test_alert(), "", &page, 0));
+        comp.queue.push(PendingAlert::new(&test_alert(), "", &page, 0));
+        let mut destinations = Destinations::new(Destination { client, checked : true }, API_KEY);
+        let start = now();
+        comp.retry_pending(&mut destinations);
+        // The second alert waits for the new attempt of the first one instead of its past one
+        assert_eq!(1, server.requests().iter().filter(|r| r.method == "POST" && r.path == "/v1/pages").count());
+        assert_eq!(0, server.pages().len());
+        let pending = comp.queue.take_due(i64::MAX);
+        assert_eq!(2, pending.len());
+        assert!(pending.iter().all(|v| v.next_attempt >= start + 1_800_000), "{:?}", pending.iter().map(|v| v.next_attempt).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn should_replay_queue_after_restart() {
+        let server = MockNotionServer::start(API_KEY);
+        server.add_siem_database(DATABASE_ID);
+        server.push_response(MockResponse::new(200, crate::mock::siem_database(DATABASE_ID)));
+        server.push_response(unavailable());
+        let storage = MemoryStateStorage::new();
+        let notifications = {
+            let (kernel, kernel_recv) = bounded(10);
+            let mut comp = NotionAlert::new().retry_policy(RetryPolicy::none()).queue_policy(RetryPolicy {
+                max_retries : u32::MAX,
+                base_delay : Duration::from_secs(3600),
+                max_delay : Duration::from_secs(3600)
+            });
+            comp.set_datasets(secrets(&server));
+            comp.set_kernel_sender(kernel);
+            comp.set_storage(Box::new(storage.clone()));
+            let local_channel = comp.local_channel();
+            let join = std::thread::spawn(move || {
+                comp.run();
+            });
+            local_channel.send(SiemMessage::Alert(test_alert())).unwrap();
+            local_channel.send(stop_command()).unwrap();
+            join.join().unwrap();
+            kernel_recv.try_iter().count()
+        };
+        assert_eq!(1, notifications);
+        assert_eq!(0, server.pages().len());
+
+        let (kernel, kernel_recv) = bounded(10);
+        let mut comp = NotionAlert::new().retry_policy(fast_policy(1));
+        comp.set_datasets(secrets(&server));
+        comp.set_kernel_sender(kernel);
+        comp.set_storage(Box::new(storage.clone()));
+        let local_channel = comp.local_channel();
+        let join = std::thread::spawn(move || {
+            comp.run();
+        });
+        let mut notifications = Vec::new();
+        wait_notification(&kernel_recv, &mut notifications, "created in Notion");
+        local_channel.send(stop_command()).unwrap();
+        join.join().unwrap();
+        let pages = server.pages();
+        assert_eq!(1, pages.len());
+        assert_eq!("(TEST) shoul_generate_alert", pages[0]["properties"]["Name"]["title"][0]["plain_text"]);
+        assert!(!server.children(pages[0]["id"].as_str().unwrap()).is_empty());
+    }
+
+    #[test]
+    fn should_start_while_notion_is_down() {
+        let server = MockNotionServer::start(API_KEY);
+        server.add_siem_database(DATABASE_ID);
+        server.push_response(unavailable());
+        let (kernel, kernel_recv) = bounded(10);
+        let mut comp = NotionAlert::new().retry_policy(RetryPolicy::none());
+        comp.set_datasets(secrets(&server));
+        comp.set_kernel_sender(kernel);
+        let local_channel = comp.local_channel();
+        let join = std::thread::spawn(move || {
+            comp.run();
+        });
+        local_channel.send(SiemMessage::Alert(test_alert())).unwrap();
+        let mut notifications = Vec::new();
+        wait_notification(&kernel_recv, &mut notifications, "created in Notion");
+        local_channel.send(stop_command()).unwrap();
+        join.join().unwrap();
+        assert!(notifications[0].contains("Notion is not available, alerts will be queued"), "{:?}", notifications);
+        assert_eq!(1, server.pages().len());
+    }
+
+    #[test]
+    fn should_use_property_mapping_dataset() {
+        let server = MockNotionServer::start(API_KEY);
+        let mut database = crate::mock::siem_database(DATABASE_ID);
+        let properties = database["properties"].as_object_mut().unwrap();
+        let mut name = properties.remove("Name").unwrap();
+        name["name"] = "Nombre".into();
+        properties.insert("Nombre".to_owned(), name);
+        let mut fired = properties.remove("Fired").unwrap();
+        fired["name"] = "Disparada".into();
+        properties.insert("Disparada".to_owned(), fired);
+        server.add_database(database);
+
+        let mut mapping = TextMapDataset::new();
+        mapping.insert(Cow::Borrowed("name"), Cow::Borrowed("Nombre"));
+        mapping.insert(Cow::Borrowed("fired"), Cow::Borrowed("fIrd"));
+        let (comm, _recv) = bounded(10);
+        let mapping = SiemDataset::CustomMapText((Cow::Borrowed(NOTION_PROPERTIES), TextMapSynDataset::new(Arc::new(mapping), comm)));
+
+        let notifications = run_component(NotionAlert::new(), vec![secret_dataset(&server), mapping], &MemoryStateStorage::new(), vec![SiemMessage::Alert(test_alert())]);
+        assert!(notifications[0].contains("created in Notion"), "{:?}", notifications);
+        let pages = server.pages();
+        assert_eq!("(TEST) shoul_generate_alert", pages[0]["properties"]["Nombre"]["title"][0]["plain_text"]);
+        assert!(pages[0]["properties"]["Disparada"]["date"]["start"].is_string());
+    }
+
+    #[test]
+    fn should_create_database_in_parent_page() {
+        let server = MockNotionServer::start(API_KEY);
+        let mut dataset = TextMapDataset::new();
+        dataset.insert(Cow::Borrowed(NOTION_PARENT_PAGE), Cow::Borrowed("b55c9c91-384d-452b-81db-d1ef79372b75"));
+        dataset.insert(Cow::Borrowed(NOTION_API_KEY), Cow::Borrowed(API_KEY));
+        dataset.insert(Cow::Borrowed(NOTION_API_URL), Cow::Owned(server.base_url()));
+        let (comm, _recv) = bounded(10);
+        let secrets = SiemDataset::Secrets((Cow::Borrowed("NotionAlerter"), TextMapSynDataset::new(Arc::new(dataset), comm)));
+        let storage = MemoryStateStorage::new();
+        for _ in 0..2 {
+            let notifications = run_component(NotionAlert::new(), vec![secrets.clone()], &storage, vec![SiemMessage::Alert(test_alert())]);
+            assert!(notifications.last().unwrap().contains("created in Notion"), "{:?}", notifications);
+        }
+        // The second run uses the database created by the first one
+        let created = server.requests().iter().filter(|r| r.method == "POST" && r.path == "/v1/databases").count();
+        assert_eq!(1, created);
+        assert_eq!(2, server.pages().len());
+        let database_id = storage.get_value(Cow::Borrowed("notion_database")).unwrap();
+        assert_eq!(database_id, server.pages()[0]["parent"]["database_id"]);
+    }
+
+    #[test]
+    fn should_migrate_schema_on_startup() {
+        for mode in [SchemaMigrationMode::DryRun, SchemaMigrationMode::Apply] {
+            let server = MockNotionServer::start(API_KEY);
+            let mut database = crate::mock::siem_database(DATABASE_ID);
+            database["properties"].as_object_mut().unwrap().remove("Tags");
+            server.add_database(database);
+            // With the dry run the database is not valid and the alerts are rejected until it is fixed
+            let notifications = run_component(NotionAlert::new().schema_migration(mode), vec![secret_dataset(&server)], &MemoryStateStorage::new(), vec![SiemMessage::Alert(test_alert())]);
+            if mode == SchemaMigrationMode::DryRun {
+                assert_eq!("Planned changes of the Notion database: add multi_select property 'Tags', add number property 'Occurrences', add date property 'Last seen'", notifications[0]);
+                assert!(notifications[1].contains("missing multi_select property 'Tags'"));
+                assert!(notifications[2].contains("the database is not valid"), "{:?}", notifications);
+                assert_eq!(0, server.pages().len());
+            } else {
+                assert_eq!("Migrated the Notion database: add multi_select property 'Tags', add number property 'Occurrences', add date property 'Last seen'", notifications[0]);
+                assert!(notifications[1].contains("created in Notion"), "{:?}", notifications);
+                assert_eq!(1, server.pages().len());
+            }
+        }
+    }
+
+    #[test]
+    fn should_route_alerts_by_tenant() {
+        const TENANT_DATABASE : &str = "5a0b6f4e-7c2d-4b8e-9f1a-3c6d8e2b4a70";
+        const TENANT_API_KEY : &str = "secret_contoso";
+        let server = MockNotionServer::start(API_KEY);
+        server.add_api_key(TENANT_API_KEY);
+        server.add_siem_database(DATABASE_ID);
+        server.add_siem_database(TENANT_DATABASE);
+
+        let mut secrets = TextMapDataset::new();
+        secrets.insert(Cow::Borrowed(NOTION_DATABASE), Cow::Borrowed(DATABASE_ID));
+        secrets.insert(Cow::Borrowed(NOTION_API_KEY), Cow::Borrowed(API_KEY));
+        secrets.insert(Cow::Borrowed(NOTION_API_URL), Cow::Owned(server.base_url()));
+        secrets.insert(Cow::Borrowed("API_KEY.Contoso"), Cow::Borrowed(TENANT_API_KEY));
+        let mut tenants = TextMapDataset::new();
+        tenants.insert(Cow::Borrowed("Contoso"), Cow::Borrowed(TENANT_DATABASE));
+        let (comm, _recv) = bounded(10);
+        let secrets = SiemDataset::Secrets((Cow::Borrowed("NotionAlerter"), TextMapSynDataset::new(Arc::new(secrets), comm.clone())));
+        let tenants = SiemDataset::CustomMapText((Cow::Borrowed(NOTION_TENANTS), TextMapSynDataset::new(Arc::new(tenants), comm)));
+
+        let storage = MemoryStateStorage::new();
+        let mut other_tenant = aggregable_alert();
+        other_tenant.log.set_tenant(Cow::Borrowed("Fabrikam"));
+        let notifications = run_component(NotionAlert::new(), vec![secrets, tenants], &storage, vec![SiemMessage::Alert(aggregable_alert()), SiemMessage::Alert(other_tenant)]);
+        assert!(notifications.iter().all(|v| v.contains("created in Notion")), "{:?}", notifications);
+
+        // Same aggr_key, but each tenant has its own page
+        let pages = server.pages();
+        assert_eq!(2, pages.len());
+        assert_eq!(TENANT_DATABASE, pages[0]["parent"]["database_id"]);
+        assert_eq!(DATABASE_ID, pages[1]["parent"]["database_id"]);
+        let tenant_requests : Vec<_> = server.requests().into_iter().filter(|r| r.path.contains(TENANT_DATABASE) || r.body["parent"]["database_id"] == TENANT_DATABASE).collect();
+        assert!(!tenant_requests.is_empty());
+        assert!(tenant_requests.iter().all(|r| r.api_key.as_deref() == Some(TENANT_API_KEY)));
+        let mut keys = storage.keys();
+        keys.sort();
+        assert_eq!(vec![format!("notion_aggr:{}/example::rule", TENANT_DATABASE), "notion_aggr:example::rule".to_owned()], keys);
+    }
+
+    #[test]
+    fn should_notify_invalid_tenant_api_key() {
+        const TENANT_DATABASE : &str = "5a0b6f4e-7c2d-4b8e-9f1a-3c6d8e2b4a70";
+        let server = MockNotionServer::start(API_KEY);
+        server.add_siem_database(DATABASE_ID);
+        server.add_siem_database(TENANT_DATABASE);
+
+        let mut secrets = TextMapDataset::new();
+        secrets.insert(Cow::Borrowed(NOTION_DATABASE), Cow::Borrowed(DATABASE_ID));
+        secrets.insert(Cow::Borrowed(NOTION_API_KEY), Cow::Borrowed(API_KEY));
+        secrets.insert(Cow::Borrowed(NOTION_API_URL), Cow::Owned(server.base_url()));
+        secrets.insert(Cow::Borrowed("API_KEY.Contoso"), Cow::Borrowed("secret_contoso\n"));
+        let mut tenants = TextMapDataset::new();
+        tenants.insert(Cow::Borrowed("Contoso"), Cow::Borrowed(TENANT_DATABASE));
+        let (comm, _recv) = bounded(10);
+        let secrets = SiemDataset::Secrets((Cow::Borrowed("NotionAlerter"), TextMapSynDataset::new(Arc::new(secrets), comm.clone())));
+        let tenants = SiemDataset::CustomMapText((Cow::Borrowed(NOTION_TENANTS), TextMapSynDataset::new(Arc::new(tenants), comm)));
+
+        let mut other_tenant = test_alert();
+        other_tenant.log.set_tenant(Cow::Borrowed("Fabrikam"));
+        let notifications = run_component(NotionAlert::new(), vec![secrets, tenants], &MemoryStateStorage::new(), vec![SiemMessage::Alert(test_alert()), SiemMessage::Alert(other_tenant)]);
+        assert_eq!(2, notifications.len(), "{:?}", notifications);
+        assert_eq!("Cannot generate alert '(TEST) shoul_generate_alert' of rule 'ruleset::example::rule1', the API key is not valid: The API key has characters not allowed in an HTTP header", notifications[0]);
+        // The other tenants are not affected
+        assert!(notifications[1].contains("created in Notion"), "{:?}", notifications);
+        assert_eq!(DATABASE_ID, server.pages()[0]["parent"]["database_id"]);
+    }
+
+    #[test]
+    fn should_apply_tenants_updated_while_running() {
+        const TENANT_DATABASE : &str = "5a0b6f4e-7c2d-4b8e-9f1a-3c6d8e2b4a70";
+        let server = MockNotionServer::start(API_KEY);
+        server.add_siem_database(DATABASE_ID);
+        server.add_siem_database(TENANT_DATABASE);
+
+        let mut tenants = TextMapDataset::new();
+        tenants.insert(Cow::Borrowed("Contoso"), Cow::Borrowed(TENANT_DATABASE));
+        let (comm, _recv) = bounded(10);
+        let tenants = SiemDataset::CustomMapText((Cow::Borrowed(NOTION_TENANTS), TextMapSynDataset::new(Arc::new(tenants), comm)));
+
+        let messages = vec![SiemMessage::Alert(test_alert()), SiemMessage::Dataset(tenants), SiemMessage::Alert(test_alert())];
+        let notifications = run_component(NotionAlert::new(), vec![secret_dataset(&server)], &MemoryStateStorage::new(), messages);
+        assert!(notifications.iter().all(|v| v.contains("created in Notion")), "{:?}", notifications);
+
+        // The secrets are kept and the alerts after the update go to the database of the tenant
+        let pages = server.pages();
+        assert_eq!(2, pages.len());
+        assert_eq!(DATABASE_ID, pages[0]["parent"]["database_id"]);
+        assert_eq!(TENANT_DATABASE, pages[1]["parent"]["database_id"]);
+    }
+
+    fn routes_dataset(rules : usiem::serde_json::Value) -> SiemDataset {
+        let mut routes = TextMapDataset::new();
+        routes.insert(Cow::Borrowed("rules"), Cow::Owned(rules.to_string()));
+        let (comm, _recv) = bounded(10);
+        SiemDataset::CustomMapText((Cow::Borrowed(NOTION_ROUTES), TextMapSynDataset::new(Arc::new(routes), comm)))
+    }
+
+    #[test]
+    fn should_route_alerts_by_rules() {
+        const ROUTED_DATABASE : &str = "0c7e51b2-93d4-4f6a-8b25-7d1e4a9c3f58";
+        let server = MockNotionServer::start(API_KEY);
+        server.add_siem_database(DATABASE_ID);
+        server.add_siem_database(ROUTED_DATABASE);
+        let routes = routes_dataset(usiem::serde_json::json!([
+            {"severity": "critical", "rule": "ruleset::example::*", "to": [ROUTED_DATABASE, "default"]},
+            {"tags": ["Noise"], "to": []}
+        ]));
+
+        let mut noise = test_alert();
+        noise.severity = AlertSeverity::LOW;
+        noise.tags = vec![String::from("Noise")];
+        let notifications = run_component(NotionAlert::new(), vec![secret_dataset(&server), routes], &MemoryStateStorage::new(), vec![
+            SiemMessage::Alert(test_alert()),
+            SiemMessage::Alert(noise.clone()),
+            // Without rules every alert goes to the default database
+            SiemMessage::Dataset(routes_dataset(usiem::serde_json::json!([]))),
+            SiemMessage::Alert(noise)
+        ]);
+        assert_eq!(4, notifications.len(), "{:?}", notifications);
+        assert_eq!("Loaded 0 routing rules", notifications[2]);
+
+        let pages = server.pages();
+        let databases : Vec<&str> = pages.iter().map(|page| page["parent"]["database_id"].as_str().unwrap()).collect();
+        assert_eq!(vec![ROUTED_DATABASE, DATABASE_ID, DATABASE_ID], databases);
+        assert_eq!("(TEST) shoul_generate_alert", pages[1]["properties"]["Name"]["title"][0]["plain_text"]);
+        assert_eq!("Low", pages[2]["properties"]["Priority"]["select"]["name"]);
+    }
+
+    #[test]
+    fn should_not_send_alerts_to_invalid_database() {
+        const ROUTED_DATABASE : &str = "0c7e51b2-93d4-4f6a-8b25-7d1e4a9c3f58";
+        let server = MockNotionServer::start(API_KEY);
+        server.add_siem_database(DATABASE_ID);
+        let mut database = crate::mock::siem_database(ROUTED_DATABASE);
+        database["properties"].as_object_mut().unwrap().remove("Status");
+        server.add_database(database);
+        let routes = routes_dataset(usiem::serde_json::json!([{"rule": "ruleset::example::*", "to": [ROUTED_DATABASE]}]));
+        let notifications = run_component(NotionAlert::new(), vec![secret_dataset(&server), routes], &MemoryStateStorage::new(), vec![SiemMessage::Alert(test_alert()), SiemMessage::Alert(test_alert())]);
+        assert_eq!(2, notifications.len(), "{:?}", notifications);
+        assert!(notifications.iter().all(|v| v.starts_with("Cannot generate alert '(TEST) shoul_generate_alert' of rule 'ruleset::example::rule1', the database is not valid: The Notion database does not have the required properties")), "{:?}", notifications);
+        assert_eq!(0, server.pages().len());
+        // The database is checked again with each alert
+        let checks = server.requests().iter().filter(|r| r.method == "GET" && r.path.contains(ROUTED_DATABASE)).count();
+        assert_eq!(2, checks);
+    }
+
+    #[test]
+    fn should_keep_rules_when_update_is_invalid() {
+        let server = MockNotionServer::start(API_KEY);
+        server.add_siem_database(DATABASE_ID);
+        let routes = routes_dataset(usiem::serde_json::json!([{"rule": "ruleset::example::*", "to": []}]));
+        let notifications = run_component(NotionAlert::new(), vec![secret_dataset(&server), routes], &MemoryStateStorage::new(), vec![
+            SiemMessage::Dataset(routes_dataset(usiem::serde_json::json!([{"severity": "urgent", "to": []}]))),
+            SiemMessage::Alert(test_alert())
+        ]);
+        assert_eq!(1, notifications.len(), "{:?}", notifications);
+        assert!(notifications[0].starts_with("Invalid routing rules in NotionAlerterRoutes, the previous rules are kept: unknown severity 'urgent'"), "{:?}", notifications);
+        assert_eq!(0, server.pages().len());
+    }
+
+    /// Dataset with the template of the rules of `ruleset::example`
+    fn templates_dataset(template : String) -> SiemDataset {
+        let mut templates = TextMapDataset::new();
+        templates.insert(Cow::Borrowed("ruleset::example"), Cow::Owned(template));
+        let (comm, _recv) = bounded(10);
+        SiemDataset::CustomMapText((Cow::Borrowed(NOTION_TEMPLATES), TextMapSynDataset::new(Arc::new(templates), comm)))
+    }
+
+    #[test]
+    fn should_use_page_template_of_rule() {
+        let server = MockNotionServer::start(API_KEY);
+        server.add_siem_database(DATABASE_ID);
+        let templates = templates_dataset(usiem::serde_json::json!({
+            "properties": {"Tags": {"multi_select": "{{tags}}, {{log.tenant}}"}},
+            "blocks": [
+                {"callout": "{{severity}}: {{description}}", "emoji": "🚨"},
+                {"toggle": "Log", "children": [{"code": "{{log.message}}"}]}
+            ]
+        }).to_string());
+
+        let mut other_rule = test_alert();
+        other_rule.rule = String::from("ruleset::other::rule1");
+        let notifications = run_component(NotionAlert::new(), vec![secret_dataset(&server), templates], &MemoryStateStorage::new(), vec![SiemMessage::Alert(test_alert()), SiemMessage::Alert(other_rule)]);
+        assert_eq!(2, notifications.len(), "{:?}", notifications);
+
+        let pages = server.pages();
+        let tags : Vec<&str> = pages[0]["properties"]["Tags"]["multi_select"].as_array().unwrap().iter().map(|v| v["name"].as_str().unwrap()).collect();
+        assert_eq!(vec!["Critical", "Contoso"], tags);
+        let children = server.children(pages[0]["id"].as_str().unwrap());
+        assert_eq!(2, children.len());
+        assert_eq!("Critical: This is a test of the NotionAlerter component", children[0]["callout"]["rich_text"][0]["plain_text"]);
+        assert_eq!("toggle", children[1]["type"]);
+        assert_eq!(1, server.children(children[1]["id"].as_str().unwrap()).len());
+        // Rules without template use the default layout
+        let children = server.children(pages[1]["id"].as_str().unwrap());
+        assert_eq!("heading_1", children[0]["type"]);
+    }
+
+    #[test]
+    fn should_notify_invalid_template_once() {
+        let server = MockNotionServer::start(API_KEY);
+        server.add_siem_database(DATABASE_ID);
+        let valid = templates_dataset(usiem::serde_json::json!({"blocks": [{"callout": "{{severity}}"}]}).to_string());
+        let notifications = run_component(NotionAlert::new(), vec![secret_dataset(&server), templates_dataset("{".to_owned())], &MemoryStateStorage::new(), vec![
+            SiemMessage::Alert(test_alert()),
+            SiemMessage::Alert(test_alert()),
+            // The updated templates replace the invalid one
+            SiemMessage::Dataset(valid),
+            SiemMessage::Alert(test_alert())
+        ]);
+        assert_eq!(4, notifications.len(), "{:?}", notifications);
+        assert!(notifications[0].starts_with("Invalid page template of rule 'ruleset::example::rule1' in NotionAlerterTemplates, the default template is used"), "{:?}", notifications);
+        assert!(notifications[1..].iter().all(|v| v.contains("created in Notion")), "{:?}", notifications);
+        let pages = server.pages();
+        assert_eq!("heading_1", server.children(pages[1]["id"].as_str().unwrap())[0]["type"]);
+        assert_eq!("callout", server.children(pages[2]["id"].as_str().unwrap())[0]["type"]);
+    }
+}
@@ -1,15 +1,41 @@
-use serde::{Deserialize, Serialize};
+use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+use usiem::serde_json::{Map, Value};
 
-use super::database::properties::{RichTextValue, RichTextObject};
+use super::database::properties::{Color, HostedFile, Link, RichTextValue, RichTextObject};
+use super::limits::{MAX_RICH_TEXT_ITEMS, MAX_CHILDREN, MAX_PAYLOAD_BLOCKS, MAX_PAYLOAD_SIZE};
 
+pub mod markdown;
+
+/// Bytes of `{"children":[]}` in a request body
+pub(crate) const CHILDREN_OVERHEAD : usize = 15;
+
+/// Block returned by the Notion API
 #[derive(Default, Debug, Deserialize, Serialize)]
 #[serde(tag = "object", rename="block")]
 pub struct BlockObject {
-
+    pub id : String,
+    #[serde(default)]
+    pub created_time : String,
+    #[serde(default)]
+    pub last_edited_time : String,
+    #[serde(default)]
+    pub has_children : bool,
+    #[serde(default)]
+    pub archived : bool,
+    #[serde(flatten)]
+    pub block : BlockElement,
+    /// Nested blocks. Notion does not return them with the block, `NotionClient::retrieve_block_children` fills them.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub children : Vec<BlockObject>
 }
 
+/// Block types of BlockElement
+const BLOCK_TYPES : [&str; 16] = ["heading_1", "heading_2", "heading_3", "paragraph", "code", "bulleted_list_item", "numbered_list_item", "to_do", "toggle", "callout", "quote", "divider", "table", "table_row", "bookmark", "embed"];
+
+/// Blocks are serialized as requests expect them (`{"paragraph": {...}}`) and deserialized from requests
+/// and from responses, which tell the type of the block.
 #[derive(Default, Debug, Deserialize, Serialize)]
-//#[serde(tag = "type")]
+#[serde(remote = "Self")]
 pub enum BlockElement {
     #[serde(rename = "heading_1")]
     HeadingOne(RichTextValue),
@@ -21,43 +47,533 @@ pub enum BlockElement {
     Paragraph(RichTextValue),
     #[serde(rename = "code")]
     Code(CodeBlock),
+    #[serde(rename = "bulleted_list_item")]
+    BulletedListItem(TextBlock),
+    #[serde(rename = "numbered_list_item")]
+    NumberedListItem(TextBlock),
+    #[serde(rename = "to_do")]
+    ToDo(ToDoBlock),
+    #[serde(rename = "toggle")]
+    Toggle(TextBlock),
+    #[serde(rename = "callout")]
+    Callout(CalloutBlock),
+    #[serde(rename = "quote")]
+    Quote(TextBlock),
+    #[serde(rename = "divider")]
+    Divider(DividerBlock),
+    #[serde(rename = "table")]
+    Table(TableBlock),
+    #[serde(rename = "table_row")]
+    TableRow(TableRowBlock),
+    #[serde(rename = "bookmark")]
+    Bookmark(BookmarkBlock),
+    #[serde(rename = "embed")]
+    Embed(EmbedBlock),
     #[default]
     #[serde(skip_deserializing)]
-    Null
+    Null,
+    /// Block of a type not modeled, like images or child pages, as received. Notion does not allow creating most of them.
+    #[serde(skip)]
+    Unsupported(Value)
+}
+
+impl Serialize for BlockElement {
+    fn serialize<S>(&self, serializer : S) -> Result<S::Ok, S::Error> where S : Serializer {
+        match self {
+            BlockElement::Unsupported(block) => block.serialize(serializer),
+            _ => BlockElement::serialize(self, serializer)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for BlockElement {
+    fn deserialize<D>(deserializer : D) -> Result<Self, D::Error> where D : Deserializer<'de> {
+        let mut object = Map::<String, Value>::deserialize(deserializer)?;
+        let block_type = match object.get("type").and_then(|v| v.as_str()) {
+            Some(block_type) => block_type.to_owned(),
+            None => match object.keys().find(|key| BLOCK_TYPES.contains(&&key[..])) {
+                Some(block_type) => block_type.clone(),
+                None => return Err(D::Error::custom("the block has no type"))
+            }
+        };
+        let content = object.remove(&block_type).unwrap_or_else(|| Value::Object(Map::new()));
+        let mut block = Map::new();
+        block.insert(block_type.clone(), content);
+        if BLOCK_TYPES.contains(&&block_type[..]) {
+            if let Ok(element) = BlockElement::deserialize(Value::Object(block.clone())) {
+                return Ok(element);
+            }
+        }
+        // Blocks of other types, or with content not modeled like a new kind of icon, are kept as received
+        block.insert("type".to_owned(), Value::String(block_type));
+        Ok(BlockElement::Unsupported(Value::Object(block)))
+    }
 }
 
 impl BlockElement {
+    /// Type of the block as named by the Notion API
+    pub fn type_name(&self) -> &str {
+        match self {
+            BlockElement::HeadingOne(_) => "heading_1",
+            BlockElement::HeadingTwo(_) => "heading_2",
+            BlockElement::HeadingThree(_) => "heading_3",
+            BlockElement::Paragraph(_) => "paragraph",
+            BlockElement::Code(_) => "code",
+            BlockElement::BulletedListItem(_) => "bulleted_list_item",
+            BlockElement::NumberedListItem(_) => "numbered_list_item",
+            BlockElement::ToDo(_) => "to_do",
+            BlockElement::Toggle(_) => "toggle",
+            BlockElement::Callout(_) => "callout",
+            BlockElement::Quote(_) => "quote",
+            BlockElement::Divider(_) => "divider",
+            BlockElement::Table(_) => "table",
+            BlockElement::TableRow(_) => "table_row",
+            BlockElement::Bookmark(_) => "bookmark",
+            BlockElement::Embed(_) => "embed",
+            BlockElement::Null => "null",
+            BlockElement::Unsupported(block) => block["type"].as_str().unwrap_or("unsupported"),
+        }
+    }
     pub fn heading_one(content : &str) -> Self {
-        BlockElement::HeadingOne(RichTextValue {
-            rich_text: vec![RichTextObject::new(content)]
-        })
+        BlockElement::HeadingOne(RichTextValue::new(content))
     }
     pub fn heading_two(content : &str) -> Self {
-        BlockElement::HeadingTwo(RichTextValue {
-            rich_text: vec![RichTextObject::new(content)]
-        })
+        BlockElement::HeadingTwo(RichTextValue::new(content))
     }
     pub fn heading_three(content : &str) -> Self {
-        BlockElement::HeadingThree(RichTextValue {
-            rich_text: vec![RichTextObject::new(content)]
-        })
+        BlockElement::HeadingThree(RichTextValue::new(content))
     }
+    /// Code block. Content exceeding MAX_RICH_TEXT_ITEMS segments is truncated, use `code_blocks` to keep it.
     pub fn code(content : &str, language : Option<String>) -> Self {
         BlockElement::Code(CodeBlock {
-            rich_text : vec![RichTextObject::new(content)],
-            language : language.unwrap_or(format!("plain text"))
+            rich_text : RichTextObject::chunks(content).into_iter().take(MAX_RICH_TEXT_ITEMS).collect(),
+            language : language.unwrap_or_else(|| "plain text".to_owned())
         })
     }
     pub fn code_owned(content : String, language : Option<String>) -> Self {
-        BlockElement::Code(CodeBlock {
-            rich_text : vec![RichTextObject::new_owned(content)],
-            language : language.unwrap_or(format!("plain text"))
+        Self::code(&content, language)
+    }
+    /// Code split in as many blocks as needed to fit the rich text limits
+    pub fn code_blocks(content : &str, language : Option<String>) -> Vec<Self> {
+        let language = language.unwrap_or_else(|| "plain text".to_owned());
+        split_segments(content).into_iter().map(|rich_text| BlockElement::Code(CodeBlock {
+            rich_text,
+            language : language.clone()
+        })).collect()
+    }
+    /// Paragraphs needed to fit the rich text limits
+    pub fn paragraphs(content : &str) -> Vec<Self> {
+        split_segments(content).into_iter().map(|rich_text| BlockElement::Paragraph(RichTextValue {
+            rich_text
+        })).collect()
+    }
+    /// Blocks of a Markdown document, see `markdown::markdown_to_blocks`
+    pub fn markdown(content : &str) -> Vec<Self> {
+        markdown::markdown_to_blocks(content)
+    }
+    pub fn bulleted_list_item(content : &str) -> Self {
+        BlockElement::BulletedListItem(TextBlock::new(content))
+    }
+    pub fn numbered_list_item(content : &str) -> Self {
+        BlockElement::NumberedListItem(TextBlock::new(content))
+    }
+    pub fn to_do(content : &str, checked : bool) -> Self {
+        BlockElement::ToDo(ToDoBlock {
+            rich_text : RichTextValue::new(content).rich_text,
+            checked,
+            children : Vec::new()
+        })
+    }
+    /// Toggle that shows the children when expanded
+    pub fn toggle(content : &str, children : Vec<BlockElement>) -> Self {
+        BlockElement::Toggle(TextBlock {
+            children,
+            ..TextBlock::new(content)
+        })
+    }
+    pub fn callout(content : &str, emoji : &str) -> Self {
+        BlockElement::Callout(CalloutBlock {
+            rich_text : RichTextValue::new(content).rich_text,
+            icon : Some(Icon::Emoji { emoji : emoji.to_owned() }),
+            color : None,
+            children : Vec::new()
+        })
+    }
+    pub fn quote(content : &str) -> Self {
+        BlockElement::Quote(TextBlock::new(content))
+    }
+    pub fn divider() -> Self {
+        BlockElement::Divider(DividerBlock {})
+    }
+    /// Table with a row for each item of `rows`. Rows with less cells than the longest one are filled
+    /// with empty cells. The first row is shown as the header if `has_column_header`.
+    pub fn table(rows : &[Vec<String>], has_column_header : bool) -> Self {
+        let table_width = rows.iter().map(|v| v.len()).max().unwrap_or(0).max(1);
+        BlockElement::Table(TableBlock {
+            table_width,
+            has_column_header,
+            has_row_header : false,
+            children : rows.iter().map(|row| BlockElement::TableRow(TableRowBlock {
+                cells : (0..table_width).map(|i| row.get(i).map(|v| RichTextValue::new(v).rich_text).unwrap_or_default()).collect()
+            })).collect()
+        })
+    }
+    pub fn bookmark(url : &str) -> Self {
+        BlockElement::Bookmark(BookmarkBlock {
+            url : url.to_owned(),
+            caption : Vec::new()
         })
     }
+    pub fn embed(url : &str) -> Self {
+        BlockElement::Embed(EmbedBlock {
+            url : url.to_owned()
+        })
+    }
+    /// Nested blocks, including the rows of tables
+    pub fn children(&self) -> &[BlockElement] {
+        match self {
+            BlockElement::BulletedListItem(block) | BlockElement::NumberedListItem(block) | BlockElement::Toggle(block) | BlockElement::Quote(block) => &block.children,
+            BlockElement::ToDo(block) => &block.children,
+            BlockElement::Callout(block) => &block.children,
+            BlockElement::Table(block) => &block.children,
+            _ => &[]
+        }
+    }
+    /// Nested blocks of the blocks that can have them, except the rows of tables
+    pub fn children_mut(&mut self) -> Option<&mut Vec<BlockElement>> {
+        match self {
+            BlockElement::BulletedListItem(block) | BlockElement::NumberedListItem(block) | BlockElement::Toggle(block) | BlockElement::Quote(block) => Some(&mut block.children),
+            BlockElement::ToDo(block) => Some(&mut block.children),
+            BlockElement::Callout(block) => Some(&mut block.children),
+            _ => None
+        }
+    }
 }
 
-#[derive(Default, Debug, Deserialize, Serialize, Hash)]
+/// Groups the text segments in arrays of MAX_RICH_TEXT_ITEMS. Always returns at least one array.
+fn split_segments(content : &str) -> Vec<Vec<RichTextObject>> {
+    let mut groups = Vec::new();
+    let mut segments = RichTextObject::chunks(content).into_iter().peekable();
+    while segments.peek().is_some() {
+        groups.push(segments.by_ref().take(MAX_RICH_TEXT_ITEMS).collect());
+    }
+    if groups.is_empty() {
+        groups.push(Vec::new());
+    }
+    groups
+}
+
+/// Body of `PATCH /v1/blocks/{block_id}/children`
+#[derive(Default, Debug, Deserialize, Serialize)]
+pub struct AppendBlockChildren {
+    pub children : Vec<BlockElement>
+}
+
+/// Groups the blocks in batches that fit in a request: at most MAX_CHILDREN blocks, MAX_PAYLOAD_BLOCKS blocks
+/// counting the nested ones and MAX_PAYLOAD_SIZE bytes. `first_overhead` is the size of the rest of the first
+/// request. A block that does not fit in a request by itself is replaced by a paragraph telling it was omitted.
+/// The first batch may be empty.
+pub fn batch_blocks(blocks : Vec<BlockElement>, first_overhead : usize) -> Vec<Vec<BlockElement>> {
+    let mut batches = Vec::new();
+    let mut current = Vec::new();
+    let mut size = first_overhead;
+    let mut count = 0;
+    for block in blocks {
+        let mut block_size = serialized_size(&block) + 1;
+        let mut block_count = nested_count(&block);
+        let block = if CHILDREN_OVERHEAD + block_size > MAX_PAYLOAD_SIZE || block_count > MAX_PAYLOAD_BLOCKS {
+            let notice = BlockElement::Paragraph(RichTextValue::new("… block omitted: it exceeds the Notion request size limit"));
+            block_size = serialized_size(&notice) + 1;
+            block_count = 1;
+            notice
+        } else {
+            block
+        };
+        let full = current.len() >= MAX_CHILDREN || size + block_size > MAX_PAYLOAD_SIZE || count + block_count > MAX_PAYLOAD_BLOCKS;
+        if full && (!current.is_empty() || batches.is_empty()) {
+            batches.push(std::mem::take(&mut current));
+            size = CHILDREN_OVERHEAD;
+            count = 0;
+        }
+        size += block_size;
+        count += block_count;
+        current.push(block);
+    }
+    if !current.is_empty() {
+        batches.push(current);
+    }
+    batches
+}
+
+/// The block and all its nested blocks
+fn nested_count(block : &BlockElement) -> usize {
+    1 + block.children().iter().map(nested_count).sum::<usize>()
+}
+
+fn serialized_size(block : &BlockElement) -> usize {
+    usiem::serde_json::to_vec(block).map(|v| v.len()).unwrap_or(0)
+}
+
+#[derive(Default, Debug, Deserialize, Serialize)]
 pub struct CodeBlock {
     pub rich_text : Vec<RichTextObject>,
     pub language : String
 }
+
+/// List items, toggles and quotes
+#[derive(Default, Debug, Deserialize, Serialize)]
+pub struct TextBlock {
+    pub rich_text : Vec<RichTextObject>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color : Option<Color>,
+    /// Nested blocks. Requests can only nest two levels of blocks.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub children : Vec<BlockElement>
+}
+
+impl TextBlock {
+    pub fn new(content : &str) -> Self {
+        Self {
+            rich_text : RichTextValue::new(content).rich_text,
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Default, Debug, Deserialize, Serialize)]
+pub struct ToDoBlock {
+    pub rich_text : Vec<RichTextObject>,
+    #[serde(default)]
+    pub checked : bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub children : Vec<BlockElement>
+}
+
+#[derive(Default, Debug, Deserialize, Serialize)]
+pub struct CalloutBlock {
+    pub rich_text : Vec<RichTextObject>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub icon : Option<Icon>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color : Option<Color>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub children : Vec<BlockElement>
+}
+
+/// Icon types of Icon
+const ICON_TYPES : [&str; 3] = ["emoji", "external", "file"];
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case", remote = "Self")]
+pub enum Icon {
+    Emoji {
+        emoji : String
+    },
+    External {
+        external : Link
+    },
+    /// Image uploaded to Notion. It cannot be set by the API.
+    File {
+        file : HostedFile
+    },
+    /// Icon of a type not modeled, like custom emojis, as received
+    #[serde(skip)]
+    Unsupported(Value)
+}
+
+impl Serialize for Icon {
+    fn serialize<S>(&self, serializer : S) -> Result<S::Ok, S::Error> where S : Serializer {
+        match self {
+            Icon::Unsupported(icon) => icon.serialize(serializer),
+            _ => Icon::serialize(self, serializer)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Icon {
+    fn deserialize<D>(deserializer : D) -> Result<Self, D::Error> where D : Deserializer<'de> {
+        let icon = Value::deserialize(deserializer)?;
+        match icon.get("type").and_then(|v| v.as_str()) {
+            Some(icon_type) if ICON_TYPES.contains(&icon_type) => Icon::deserialize(icon).map_err(D::Error::custom),
+            Some(_) => Ok(Icon::Unsupported(icon)),
+            None => Err(D::Error::custom("the icon has no type"))
+        }
+    }
+}
+
+#[derive(Default, Debug, Deserialize, Serialize)]
+pub struct DividerBlock {
+}
+
+/// The rows are the children of the table. They must be sent when the table is created.
+#[derive(Default, Debug, Deserialize, Serialize)]
+pub struct TableBlock {
+    /// Cells of each row. It cannot be changed after the table is created.
+    pub table_width : usize,
+    #[serde(default)]
+    pub has_column_header : bool,
+    #[serde(default)]
+    pub has_row_header : bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub children : Vec<BlockElement>
+}
+
+#[derive(Default, Debug, Deserialize, Serialize)]
+pub struct TableRowBlock {
+    pub cells : Vec<Vec<RichTextObject>>
+}
+
+#[derive(Default, Debug, Deserialize, Serialize)]
+pub struct BookmarkBlock {
+    pub url : String,
+    #[serde(default)]
+    pub caption : Vec<RichTextObject>
+}
+
+#[derive(Default, Debug, Deserialize, Serialize)]
+pub struct EmbedBlock {
+    pub url : String
+}
+
+#[cfg(test)]
+mod batches {
+    use super::*;
+
+    #[test]
+    fn should_batch_by_count() {
+        let blocks : Vec<BlockElement> = (0..250).map(|v| BlockElement::heading_two(&format!("Heading {}", v))).collect();
+        let batches = batch_blocks(blocks, CHILDREN_OVERHEAD);
+        assert_eq!(vec![100, 100, 50], batches.iter().map(|v| v.len()).collect::<Vec<usize>>());
+    }
+
+    #[test]
+    fn should_batch_by_size() {
+        let content = "a".repeat(200_000);
+        let blocks : Vec<BlockElement> = (0..5).map(|_| BlockElement::code(&content, None)).collect();
+        let batches = batch_blocks(blocks, 400_000);
+        assert_eq!(vec![0, 2, 2, 1], batches.iter().map(|v| v.len()).collect::<Vec<usize>>());
+        for batch in &batches {
+            assert!(usiem::serde_json::to_vec(batch).unwrap().len() + CHILDREN_OVERHEAD < MAX_PAYLOAD_SIZE);
+        }
+    }
+
+    #[test]
+    fn should_batch_by_nested_blocks() {
+        let rows : Vec<Vec<String>> = (0..99).map(|v| vec![format!("row {}", v)]).collect();
+        let blocks : Vec<BlockElement> = (0..25).map(|_| BlockElement::table(&rows, false)).collect();
+        let batches = batch_blocks(blocks, CHILDREN_OVERHEAD);
+        // 100 blocks by table
+        assert_eq!(vec![10, 10, 5], batches.iter().map(|v| v.len()).collect::<Vec<usize>>());
+        for batch in &batches {
+            assert!(batch.iter().map(nested_count).sum::<usize>() <= MAX_PAYLOAD_BLOCKS);
+        }
+        let toggle = BlockElement::toggle("Tables", (0..20).map(|_| BlockElement::table(&rows, false)).collect());
+        let batches = batch_blocks(vec![toggle], CHILDREN_OVERHEAD);
+        assert!(matches!(&batches[0][0], BlockElement::Paragraph(_)));
+    }
+
+    #[test]
+    fn should_replace_oversized_blocks() {
+        // Control characters take 6 bytes once escaped
+        let content = "\u{1}".repeat(200_000);
+        let batches = batch_blocks(vec![BlockElement::code(&content, None)], CHILDREN_OVERHEAD);
+        assert_eq!(1, batches.len());
+        match &batches[0][0] {
+            BlockElement::Paragraph(text) => assert!(text.rich_text[0].text().contains("block omitted")),
+            _ => panic!("Expected the omitted block notice")
+        }
+    }
+}
+
+#[cfg(test)]
+mod serialization {
+    use super::*;
+    use crate::api::query::PaginatedList;
+    use usiem::serde_json::{self, json};
+
+    #[test]
+    fn should_serialize_block_catalog() {
+        let blocks = vec![
+            BlockElement::bulleted_list_item("srv01"),
+            BlockElement::numbered_list_item("Isolate the host"),
+            BlockElement::to_do("Reset the credentials", false),
+            BlockElement::toggle("Raw log", vec![BlockElement::code("{}", Some("json".to_owned()))]),
+            BlockElement::callout("Critical asset", "🚨"),
+            BlockElement::quote("Detected by the EDR"),
+            BlockElement::divider(),
+            BlockElement::bookmark("https://attack.mitre.org/techniques/T1059/"),
+            BlockElement::embed("https://grafana.example.com/d/alerts"),
+        ];
+        assert_eq!(json!([
+            {"bulleted_list_item": {"rich_text": [{"type": "text", "text": {"content": "srv01"}}]}},
+            {"numbered_list_item": {"rich_text": [{"type": "text", "text": {"content": "Isolate the host"}}]}},
+            {"to_do": {"rich_text": [{"type": "text", "text": {"content": "Reset the credentials"}}], "checked": false}},
+            {"toggle": {"rich_text": [{"type": "text", "text": {"content": "Raw log"}}], "children": [
+                {"code": {"rich_text": [{"type": "text", "text": {"content": "{}"}}], "language": "json"}}
+            ]}},
+            {"callout": {"rich_text": [{"type": "text", "text": {"content": "Critical asset"}}], "icon": {"type": "emoji", "emoji": "🚨"}}},
+            {"quote": {"rich_text": [{"type": "text", "text": {"content": "Detected by the EDR"}}]}},
+            {"divider": {}},
+            {"bookmark": {"url": "https://attack.mitre.org/techniques/T1059/", "caption": []}},
+            {"embed": {"url": "https://grafana.example.com/d/alerts"}},
+        ]), serde_json::to_value(&blocks).unwrap());
+    }
+
+    #[test]
+    fn should_fill_table_rows() {
+        let table = BlockElement::table(&[
+            vec!["Field".to_owned(), "Value".to_owned()],
+            vec!["source.ip".to_owned()],
+        ], true);
+        let table = serde_json::to_value(&table).unwrap();
+        assert_eq!(2, table["table"]["table_width"]);
+        assert_eq!(true, table["table"]["has_column_header"]);
+        assert_eq!(json!({"table_row": {"cells": [[{"type": "text", "text": {"content": "source.ip"}}], []]}}), table["table"]["children"][1]);
+    }
+
+    #[test]
+    fn should_deserialize_block_responses() {
+        let blocks : PaginatedList<BlockObject> = serde_json::from_value(json!({
+            "object": "list",
+            "results": [
+                {
+                    "object": "block",
+                    "id": "c02fc1d3-db8b-45c5-a222-27595b15aea7",
+                    "parent": {"type": "page_id", "page_id": "59833787-2cf9-4fdf-8782-e53db20768a5"},
+                    "created_time": "2022-03-01T19:05:00.000Z",
+                    "last_edited_time": "2022-03-01T19:05:00.000Z",
+                    "has_children": false,
+                    "archived": false,
+                    "type": "heading_2",
+                    "heading_2": {"rich_text": [{"type": "text", "text": {"content": "Triage", "link": null}, "annotations": {"bold": false, "italic": false, "strikethrough": false, "underline": false, "code": false, "color": "default"}, "plain_text": "Triage", "href": null}], "color": "default", "is_toggleable": false}
+                },
+                {
+                    "object": "block",
+                    "id": "acc7eb06-05cd-4603-a384-5e1e4f1f4e72",
+                    "has_children": true,
+                    "type": "to_do",
+                    "to_do": {"rich_text": [], "checked": true, "color": "default"}
+                },
+                {
+                    "object": "block",
+                    "id": "5e1e4f1f-4e72-4603-a384-acc7eb0605cd",
+                    "has_children": false,
+                    "type": "image",
+                    "image": {"type": "external", "external": {"url": "https://example.com/graph.png"}}
+                }
+            ],
+            "next_cursor": null,
+            "has_more": false,
+            "type": "block",
+            "block": {}
+        })).unwrap();
+        match &blocks.results[0].block {
+            BlockElement::HeadingTwo(text) => assert_eq!("Triage", text.rich_text[0].text()),
+            v => panic!("Unexpected block {:?}", v)
+        }
+        assert!(blocks.results[1].has_children);
+        assert!(matches!(&blocks.results[1].block, BlockElement::ToDo(v) if v.checked));
+        assert_eq!("image", blocks.results[2].block.type_name());
+        assert_eq!(json!({"type": "image", "image": {"type": "external", "external": {"url": "https://example.com/graph.png"}}}), serde_json::to_value(&blocks.results[2].block).unwrap());
+    }
+}
@@ -0,0 +1,717 @@
+//! Conversion between Markdown and Notion blocks and rich text.
+//!
+//! Supports headings, paragraphs, bulleted, numbered and task lists, quotes, code fences, tables,
+//! thematic breaks, bold, italic, strikethrough, inline code and links. Images are converted to links
+//! and HTML is kept as text. Headings of level 4 and beyond are shown as level 3 headings.
+//!
+//! When exporting, toggles are written as list items, callouts as quotes, bookmarks and embeds as links
+//! and mentions as their text, linked when Notion gives their url. Blocks without a Markdown equivalent
+//! other than images are omitted, as are underline and colors.
+
+use pulldown_cmark::{CodeBlockKind, Event, HeadingLevel, Options, Parser, Tag, TagEnd};
+
+use super::{BlockElement, BlockObject, TextBlock, ToDoBlock};
+use crate::api::database::properties::{Annotations, Link, Mention, RichTextContent, RichTextObject, RichTextValue, TextValueInternal};
+use crate::api::limits::{self, MAX_CHILDREN, MAX_RICH_TEXT_ITEMS, MAX_TEXT_LENGTH};
+
+/// Levels of nested children that Notion accepts in a request. Deeper blocks are moved up to the last level.
+const MAX_NESTING : usize = 2;
+
+/// Languages of the code blocks accepted by Notion
+const CODE_LANGUAGES : [&str; 72] = ["abap", "arduino", "bash", "basic", "c", "clojure", "coffeescript", "c++", "c#", "css", "dart", "diff", "docker", "elixir", "elm", "erlang", "flow", "fortran", "f#", "gherkin", "glsl", "go", "graphql", "groovy", "haskell", "html", "java", "javascript", "json", "julia", "kotlin", "latex", "less", "lisp", "livescript", "lua", "makefile", "markdown", "markup", "matlab", "mermaid", "nix", "objective-c", "ocaml", "pascal", "perl", "php", "plain text", "powershell", "prolog", "protobuf", "python", "r", "reason", "ruby", "rust", "sass", "scala", "scheme", "scss", "shell", "sql", "swift", "typescript", "vb.net", "verilog", "vhdl", "visual basic", "webassembly", "xml", "yaml", "java/c/c++/c#"];
+
+/// Blocks of the Markdown document
+pub fn markdown_to_blocks(markdown : &str) -> Vec<BlockElement> {
+    let mut converter = Converter::default();
+    for event in Parser::new_ext(markdown, Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TASKLISTS) {
+        converter.event(event);
+    }
+    limit_nesting(converter.finish(), 0)
+}
+
+/// Rich text of the Markdown document, ex: for a rich text property. Blocks are separated by line breaks
+/// and only their text is kept.
+pub fn markdown_to_rich_text(markdown : &str) -> Vec<RichTextObject> {
+    let mut converter = Converter::default();
+    for event in Parser::new_ext(markdown, Options::ENABLE_STRIKETHROUGH) {
+        match event {
+            Event::Start(tag @ (Tag::Emphasis | Tag::Strong | Tag::Strikethrough | Tag::Link { .. } | Tag::Image { .. })) => converter.start(tag),
+            Event::End(tag @ (TagEnd::Emphasis | TagEnd::Strong | TagEnd::Strikethrough | TagEnd::Link | TagEnd::Image)) => converter.end(tag),
+            Event::End(TagEnd::Paragraph | TagEnd::Heading(_) | TagEnd::CodeBlock | TagEnd::Item) => converter.text("\n", false),
+            Event::Text(text) | Event::Html(text) | Event::InlineHtml(text) => converter.text(&text, false),
+            Event::Code(text) => converter.text(&text, true),
+            Event::SoftBreak | Event::HardBreak => converter.text("\n", false),
+            _ => {}
+        }
+    }
+    let mut text = std::mem::take(&mut converter.inline);
+    if let Some(RichTextObject { content : RichTextContent::Text { text }, .. }) = text.last_mut() {
+        text.content.truncate(text.content.trim_end_matches('\n').len());
+    }
+    split_long_text(text).into_iter().take(MAX_RICH_TEXT_ITEMS).collect()
+}
+
+#[derive(Default)]
+enum Container {
+    #[default]
+    Root,
+    Quote,
+    List { numbered : bool },
+    Item { numbered : bool, checked : Option<bool> },
+}
+
+/// Block that is receiving children
+#[derive(Default)]
+struct Frame {
+    container : Container,
+    /// Text of the quote or list item
+    text : Option<Vec<RichTextObject>>,
+    children : Vec<BlockElement>,
+}
+
+#[derive(Default)]
+struct Table {
+    rows : Vec<Vec<Vec<RichTextObject>>>,
+    row : Vec<Vec<RichTextObject>>,
+}
+
+#[derive(Default)]
+struct Converter {
+    frames : Vec<Frame>,
+    root : Vec<BlockElement>,
+    inline : Vec<RichTextObject>,
+    bold : usize,
+    italic : usize,
+    strikethrough : usize,
+    link : Option<String>,
+    /// Language and content of the code block being read
+    code : Option<(String, String)>,
+    table : Option<Table>,
+}
+
+impl Converter {
+    fn event(&mut self, event : Event<'_>) {
+        match event {
+            Event::Start(tag) => self.start(tag),
+            Event::End(tag) => self.end(tag),
+            Event::Text(text) => match &mut self.code {
+                Some((_, content)) => content.push_str(&text),
+                None => self.text(&text, false),
+            },
+            Event::Code(text) => self.text(&text, true),
+            Event::Html(text) | Event::InlineHtml(text) => self.text(&text, false),
+            Event::SoftBreak | Event::HardBreak => self.text("\n", false),
+            Event::Rule => {
+                self.flush_inline();
+                self.push_block(BlockElement::divider());
+            },
+            Event::TaskListMarker(checked) => {
+                if let Some(Frame { container : Container::Item { checked : item_checked, .. }, .. }) = self.frames.last_mut() {
+                    *item_checked = Some(checked);
+                }
+            },
+            _ => {}
+        }
+    }
+
+    fn start(&mut self, tag : Tag<'_>) {
+        match tag {
+            Tag::Emphasis => self.italic += 1,
+            Tag::Strong => self.bold += 1,
+            Tag::Strikethrough => self.strikethrough += 1,
+            Tag::Link { dest_url, .. } | Tag::Image { dest_url, .. } => self.link = Some(dest_url.to_string()),
+            Tag::BlockQuote(_) => {
+                self.flush_inline();
+                self.frames.push(Frame { container : Container::Quote, ..Default::default() });
+            },
+            Tag::List(start) => {
+                self.flush_inline();
+                self.frames.push(Frame { container : Container::List { numbered : start.is_some() }, ..Default::default() });
+            },
+            Tag::Item => {
+                let numbered = matches!(self.frames.last(), Some(Frame { container : Container::List { numbered : true }, .. }));
+                self.frames.push(Frame { container : Container::Item { numbered, checked : None }, ..Default::default() });
+            },
+            Tag::CodeBlock(kind) => {
+                self.flush_inline();
+                let language = match kind {
+                    CodeBlockKind::Fenced(info) => code_language(&info),
+                    CodeBlockKind::Indented => code_language(""),
+                };
+                self.code = Some((language, String::new()));
+            },
+            Tag::Table(_) => {
+                self.flush_inline();
+                self.table = Some(Table::default());
+            },
+            Tag::Heading { .. } | Tag::HtmlBlock => self.flush_inline(),
+            _ => {}
+        }
+    }
+
+    fn end(&mut self, tag : TagEnd) {
+        match tag {
+            TagEnd::Emphasis => self.italic = self.italic.saturating_sub(1),
+            TagEnd::Strong => self.bold = self.bold.saturating_sub(1),
+            TagEnd::Strikethrough => self.strikethrough = self.strikethrough.saturating_sub(1),
+            TagEnd::Link | TagEnd::Image => self.link = None,
+            TagEnd::Paragraph | TagEnd::HtmlBlock => self.flush_inline(),
+            TagEnd::Heading(level) => {
+                let rich_text = split_long_text(std::mem::take(&mut self.inline)).into_iter().take(MAX_RICH_TEXT_ITEMS).collect();
+                let text = RichTextValue { rich_text };
+                self.push_block(match level {
+                    HeadingLevel::H1 => BlockElement::HeadingOne(text),
+                    HeadingLevel::H2 => BlockElement::HeadingTwo(text),
+                    _ => BlockElement::HeadingThree(text),
+                });
+            },
+            TagEnd::CodeBlock => {
+                if let Some((language, content)) = self.code.take() {
+                    let content = content.strip_suffix('\n').unwrap_or(&content);
+                    for block in BlockElement::code_blocks(content, Some(language)) {
+                        self.push_block(block);
+                    }
+                }
+            },
+            TagEnd::BlockQuote(_) => {
+                self.flush_inline();
+                if let Some(frame) = self.frames.pop() {
+                    self.push_block(BlockElement::Quote(TextBlock {
+                        rich_text : frame.text.unwrap_or_default(),
+                        color : None,
+                        children : frame.children
+                    }));
+                }
+            },
+            TagEnd::List(_) => {
+                if let Some(frame) = self.frames.pop() {
+                    for block in frame.children {
+                        self.push_block(block);
+                    }
+                }
+            },
+            TagEnd::Item => {
+                self.flush_inline();
+                if let Some(Frame { container : Container::Item { numbered, checked }, text, children }) = self.frames.pop() {
+                    let rich_text = text.unwrap_or_default();
+                    self.push_block(match checked {
+                        Some(checked) => BlockElement::ToDo(ToDoBlock { rich_text, checked, children }),
+                        None if numbered => BlockElement::NumberedListItem(TextBlock { rich_text, color : None, children }),
+                        None => BlockElement::BulletedListItem(TextBlock { rich_text, color : None, children }),
+                    });
+                }
+            },
+            TagEnd::TableCell => {
+                let cell = split_long_text(std::mem::take(&mut self.inline)).into_iter().take(MAX_RICH_TEXT_ITEMS).collect();
+                if let Some(table) = &mut self.table {
+                    table.row.push(cell);
+                }
+            },
+            TagEnd::TableHead | TagEnd::TableRow => {
+                if let Some(table) = &mut self.table {
+                    let row = std::mem::take(&mut table.row);
+                    table.rows.push(row);
+                }
+            },
+            TagEnd::Table => {
+                if let Some(table) = self.table.take() {
+                    self.push_block(table_block(table.rows));
+                }
+            },
+            _ => {}
+        }
+    }
+
+    /// Adds the text with the current styles, joining it to the previous text when they have the same styles
+    fn text(&mut self, content : &str, code : bool) {
+        let annotations = Annotations {
+            bold : (self.bold > 0).then_some(true),
+            italic : (self.italic > 0).then_some(true),
+            strikethrough : (self.strikethrough > 0).then_some(true),
+            code : code.then_some(true),
+            ..Default::default()
+        };
+        let annotations = if annotations == Annotations::default() { None } else { Some(annotations) };
+        // Notion rejects relative urls
+        let link = self.link.as_ref().filter(|url| url.starts_with("https://") || url.starts_with("http://") || url.starts_with("mailto:")).map(|url| Link { url : url.clone() });
+        if let Some(RichTextObject { content : RichTextContent::Text { text }, annotations : last_annotations, .. }) = self.inline.last_mut() {
+            if *last_annotations == annotations && text.link == link {
+                text.content.push_str(content);
+                return;
+            }
+        }
+        self.inline.push(RichTextObject {
+            content : RichTextContent::Text { text : TextValueInternal { content : content.to_owned(), link } },
+            annotations,
+            ..Default::default()
+        });
+    }
+
+    /// Ends the text being read: it is the text of the open quote or list item or a new paragraph
+    fn flush_inline(&mut self) {
+        if self.inline.is_empty() {
+            return;
+        }
+        let text = split_long_text(std::mem::take(&mut self.inline));
+        if let Some(frame @ Frame { container : Container::Quote | Container::Item { .. }, text : None, .. }) = self.frames.last_mut() {
+            if frame.children.is_empty() {
+                frame.text = Some(text.into_iter().take(MAX_RICH_TEXT_ITEMS).collect());
+                return;
+            }
+        }
+        let mut text = text.into_iter().peekable();
+        while text.peek().is_some() {
+            self.push_block(BlockElement::Paragraph(RichTextValue { rich_text : text.by_ref().take(MAX_RICH_TEXT_ITEMS).collect() }));
+        }
+    }
+
+    fn push_block(&mut self, block : BlockElement) {
+        match self.frames.last_mut() {
+            Some(frame) => frame.children.push(block),
+            None => self.root.push(block),
+        }
+    }
+
+    fn finish(mut self) -> Vec<BlockElement> {
+        self.flush_inline();
+        while !self.frames.is_empty() {
+            self.end(match self.frames.last().map(|v| &v.container) {
+                Some(Container::Quote) => TagEnd::BlockQuote(None),
+                Some(Container::Item { .. }) => TagEnd::Item,
+                _ => TagEnd::List(false),
+            });
+        }
+        self.root
+    }
+}
+
+/// Splits the text objects that exceed the Notion length limit, keeping their styles
+fn split_long_text(text : Vec<RichTextObject>) -> Vec<RichTextObject> {
+    let mut split = Vec::with_capacity(text.len());
+    for object in text {
+        match &object.content {
+            RichTextContent::Text { text } if limits::text_length(&text.content) > MAX_TEXT_LENGTH => {
+                for chunk in limits::split_text(&text.content, MAX_TEXT_LENGTH) {
+                    split.push(RichTextObject {
+                        content : RichTextContent::Text { text : TextValueInternal { content : chunk.to_owned(), link : text.link.clone() } },
+                        annotations : object.annotations.clone(),
+                        ..Default::default()
+                    });
+                }
+            },
+            _ => split.push(object)
+        }
+    }
+    split
+}
+
+/// Tables can have up to MAX_CHILDREN rows in the request. The last row tells how many rows were left out.
+fn table_block(mut rows : Vec<Vec<Vec<RichTextObject>>>) -> BlockElement {
+    let table_width = rows.iter().map(|v| v.len()).max().unwrap_or(0).max(1);
+    if rows.len() > MAX_CHILDREN {
+        let omitted = rows.len() - (MAX_CHILDREN - 1);
+        rows.truncate(MAX_CHILDREN - 1);
+        rows.push(vec![vec![RichTextObject::new_owned(format!("… {} more rows", omitted))]]);
+    }
+    BlockElement::Table(super::TableBlock {
+        table_width,
+        has_column_header : true,
+        has_row_header : false,
+        children : rows.into_iter().map(|mut cells| {
+            cells.resize_with(table_width, Vec::new);
+            BlockElement::TableRow(super::TableRowBlock { cells })
+        }).collect()
+    })
+}
+
+/// Notion language of the info string of a code fence
+fn code_language(info : &str) -> String {
+    let language = info.split_whitespace().next().unwrap_or_default().to_lowercase();
+    let language = match &language[..] {
+        "" | "text" | "txt" | "plain" => "plain text",
+        "sh" | "zsh" | "console" => "shell",
+        "js" => "javascript",
+        "ts" => "typescript",
+        "py" => "python",
+        "rs" => "rust",
+        "yml" => "yaml",
+        "ps1" | "pwsh" => "powershell",
+        "cpp" => "c++",
+        "csharp" | "cs" => "c#",
+        "dockerfile" => "docker",
+        "md" => "markdown",
+        other => other,
+    };
+    if CODE_LANGUAGES.contains(&language) {
+        language.to_owned()
+    } else {
+        "plain text".to_owned()
+    }
+}
+
+/// Moves the children nested deeper than MAX_NESTING levels after their ancestor of the last level, and
+/// the children after the first MAX_CHILDREN of a block after the block
+pub(crate) fn limit_nesting(blocks : Vec<BlockElement>, level : usize) -> Vec<BlockElement> {
+    let mut limited = Vec::with_capacity(blocks.len());
+    for mut block in blocks {
+        let children = block.children_mut().map(std::mem::take).unwrap_or_default();
+        if level < MAX_NESTING {
+            let mut children = limit_nesting(children, level + 1);
+            let overflow = children.split_off(children.len().min(MAX_CHILDREN));
+            if let Some(nested) = block.children_mut() {
+                *nested = children;
+            }
+            limited.push(block);
+            limited.extend(overflow);
+        } else {
+            limited.push(block);
+            limited.extend(limit_nesting(children, level));
+        }
+    }
+    limited
+}
+
+/// Markdown of the blocks retrieved from a page, with their nested blocks
+pub fn blocks_to_markdown(blocks : &[BlockObject]) -> String {
+    write_blocks(&blocks.iter().map(Node::Object).collect::<Vec<Node<'_>>>())
+}
+
+/// Markdown of the blocks of a request, ex: the children of a PageElement
+pub fn elements_to_markdown(blocks : &[BlockElement]) -> String {
+    write_blocks(&blocks.iter().map(Node::Element).collect::<Vec<Node<'_>>>())
+}
+
+/// Markdown of the rich text
+pub fn rich_text_to_markdown(rich_text : &[RichTextObject]) -> String {
+    rich_text.iter().map(write_rich_text).collect()
+}
+
+/// Retrieved blocks keep their nested blocks apart from the block content
+#[derive(Clone, Copy)]
+enum Node<'a> {
+    Object(&'a BlockObject),
+    Element(&'a BlockElement),
+}
+
+impl<'a> Node<'a> {
+    fn block(&self) -> &'a BlockElement {
+        match self {
+            Node::Object(object) => &object.block,
+            Node::Element(element) => element,
+        }
+    }
+
+    fn children(&self) -> Vec<Node<'a>> {
+        match self {
+            Node::Object(object) if !object.children.is_empty() => object.children.iter().map(Node::Object).collect(),
+            _ => self.block().children().iter().map(Node::Element).collect(),
+        }
+    }
+}
+
+fn write_blocks(nodes : &[Node<'_>]) -> String {
+    let mut markdown = String::new();
+    let mut number = 0;
+    let mut previous_list = None;
+    for node in nodes {
+        let block = node.block();
+        number = if matches!(block, BlockElement::NumberedListItem(_)) { number + 1 } else { 0 };
+        let written = write_block(node, number);
+        if written.is_empty() {
+            continue;
+        }
+        let list = list_kind(block);
+        if !markdown.is_empty() {
+            markdown.push_str(if list.is_some() && previous_list == list { "\n" } else { "\n\n" });
+        }
+        markdown.push_str(&written);
+        previous_list = list;
+    }
+    markdown
+}
+
+/// Consecutive items of the same kind of list are written without blank lines between them
+fn list_kind(block : &BlockElement) -> Option<u8> {
+    match block {
+        BlockElement::BulletedListItem(_) | BlockElement::Toggle(_) => Some(0),
+        BlockElement::NumberedListItem(_) => Some(1),
+        BlockElement::ToDo(_) => Some(2),
+        _ => None
+    }
+}
+
+fn write_block(node : &Node<'_>, number : usize) -> String {
+    match node.block() {
+        BlockElement::HeadingOne(text) => format!("# {}", single_line(&rich_text_to_markdown(&text.rich_text))),
+        BlockElement::HeadingTwo(text) => format!("## {}", single_line(&rich_text_to_markdown(&text.rich_text))),
+        BlockElement::HeadingThree(text) => format!("### {}", single_line(&rich_text_to_markdown(&text.rich_text))),
+        BlockElement::Paragraph(text) => with_children(rich_text_to_markdown(&text.rich_text), node),
+        BlockElement::Code(code) => {
+            let content : String = code.rich_text.iter().map(|v| v.text()).collect();
+            let language = if code.language == "plain text" { "" } else { &code.language[..] };
+            // The fence must be longer than any run of backticks of the code
+            let mut fence = "```".to_owned();
+            while content.contains(&fence) {
+                fence.push('`');
+            }
+            format!("{}{}\n{}\n{}", fence, language, content, fence)
+        },
+        BlockElement::BulletedListItem(item) | BlockElement::Toggle(item) => list_item("- ", &item.rich_text, node),
+        BlockElement::NumberedListItem(item) => list_item(&format!("{}. ", number), &item.rich_text, node),
+        BlockElement::ToDo(item) => list_item(if item.checked { "- [x] " } else { "- [ ] " }, &item.rich_text, node),
+        BlockElement::Quote(quote) => quoted(&with_children(rich_text_to_markdown(&quote.rich_text), node)),
+        BlockElement::Callout(callout) => {
+            let icon = match &callout.icon {
+                Some(super::Icon::Emoji { emoji }) => format!("{} ", emoji),
+                _ => String::new()
+            };
+            quoted(&with_children(format!("{}{}", icon, rich_text_to_markdown(&callout.rich_text)), node))
+        },
+        BlockElement::Divider(_) => "---".to_owned(),
+        BlockElement::Table(table) => {
+            let rows : Vec<Vec<String>> = node.children().iter().filter_map(|row| match row.block() {
+                BlockElement::TableRow(row) => Some(row.cells.iter().map(|cell| table_cell(&rich_text_to_markdown(cell))).collect()),
+                _ => None
+            }).collect();
+            write_table(rows, table.table_width)
+        },
+        BlockElement::Bookmark(bookmark) => {
+            let caption = rich_text_to_markdown(&bookmark.caption);
+            format!("[{}]({})", if caption.is_empty() { &bookmark.url } else { &caption }, bookmark.url)
+        },
+        BlockElement::Embed(embed) => format!("<{}>", embed.url),
+        BlockElement::Unsupported(block) if block["type"] == "image" => {
+            let image = &block["image"];
+            let url = image["external"]["url"].as_str().or_else(|| image["file"]["url"].as_str()).unwrap_or_default();
+            let caption : Vec<RichTextObject> = usiem::serde_json::from_value(image["caption"].clone()).unwrap_or_default();
+            format!("![{}]({})", single_line(&rich_text_to_markdown(&caption)), url)
+        },
+        BlockElement::TableRow(_) | BlockElement::Unsupported(_) | BlockElement::Null => String::new(),
+    }
+}
+
+fn with_children(text : String, node : &Node<'_>) -> String {
+    let children = write_blocks(&node.children());
+    match (text.is_empty(), children.is_empty()) {
+        (_, true) => text,
+        (true, false) => children,
+        (false, false) => format!("{}\n\n{}", text, children),
+    }
+}
+
+/// The lines after the first one and the nested blocks are indented to the text of the item
+fn list_item(marker : &str, rich_text : &[RichTextObject], node : &Node<'_>) -> String {
+    let indent = " ".repeat(marker.len());
+    let text = rich_text_to_markdown(rich_text).replace('\n', &format!("\n{}", indent));
+    let mut item = format!("{}{}", marker, text);
+    let nested = node.children();
+    let children = write_blocks(&nested);
+    if !children.is_empty() {
+        // Other blocks would be read as the continuation of the text
+        item.push_str(if list_kind(nested[0].block()).is_some() { "\n" } else { "\n\n" });
+        item.push_str(&children.lines().map(|line| if line.is_empty() { String::new() } else { format!("{}{}", indent, line) }).collect::<Vec<String>>().join("\n"));
+    }
+    item
+}
+
+fn quoted(text : &str) -> String {
+    text.lines().map(|line| if line.is_empty() { ">".to_owned() } else { format!("> {}", line) }).collect::<Vec<String>>().join("\n")
+}
+
+fn single_line(text : &str) -> String {
+    text.replace('\n', " ")
+}
+
+fn table_cell(text : &str) -> String {
+    text.replace('|', "\\|").replace('\n', "<br>")
+}
+
+/// Markdown tables need a header, the first row is always used as the header
+fn write_table(mut rows : Vec<Vec<String>>, width : usize) -> String {
+    let width = rows.iter().map(|v| v.len()).max().unwrap_or(0).max(width).max(1);
+    if rows.is_empty() {
+        rows.push(Vec::new());
+    }
+    let mut lines = Vec::with_capacity(rows.len() + 1);
+    for (i, mut row) in rows.into_iter().enumerate() {
+        row.resize(width, String::new());
+        lines.push(format!("| {} |", row.join(" | ")));
+        if i == 0 {
+            lines.push(format!("|{}", " --- |".repeat(width)));
+        }
+    }
+    lines.join("\n")
+}
+
+fn write_rich_text(object : &RichTextObject) -> String {
+    let (text, url) = match &object.content {
+        RichTextContent::Text { text } => (text.content.clone(), text.link.as_ref().map(|v| v.url.clone()).or_else(|| object.href.clone())),
+        RichTextContent::Equation { equation } => return format!("${}$", equation.expression),
+        RichTextContent::Mention { mention } => {
+            let text = match (&object.plain_text, mention) {
+                (Some(plain_text), _) => plain_text.clone(),
+                (None, Mention::User { user }) => format!("@{}", user.name.as_deref().unwrap_or(&user.id)),
+                (None, Mention::Page { page }) => page.id.clone(),
+                (None, Mention::Database { database }) => database.id.clone(),
+                (None, Mention::Date { date }) => date.start.clone(),
+                (None, Mention::LinkPreview { link_preview }) => link_preview.url.clone(),
+                (None, Mention::Unsupported(_)) => String::new(),
+            };
+            (text, object.href.clone())
+        }
+    };
+    if text.is_empty() {
+        return text;
+    }
+    let annotations = object.annotations.clone().unwrap_or_default();
+    // Markers cannot be next to the spaces of the text
+    let start = text.len() - text.trim_start().len();
+    let end = text.trim_end().len().max(start);
+    let (leading, content, trailing) = (&text[..start], &text[start..end], &text[end..]);
+    if content.is_empty() {
+        return text;
+    }
+    let mut content = if annotations.code == Some(true) {
+        let fence = if content.contains('`') { "``" } else { "`" };
+        format!("{}{}{}", fence, content, fence)
+    } else {
+        escape(content)
+    };
+    if annotations.bold == Some(true) {
+        content = format!("**{}**", content);
+    }
+    if annotations.italic == Some(true) {
+        content = format!("*{}*", content);
+    }
+    if annotations.strikethrough == Some(true) {
+        content = format!("~~{}~~", content);
+    }
+    if let Some(url) = url {
+        content = format!("[{}]({})", content, url);
+    }
+    format!("{}{}{}", leading, content, trailing)
+}
+
+/// Escapes the characters that would be read as Markdown
+fn escape(text : &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '\\' | '*' | '_' | '`' | '[' | ']' | '~' | '<' | '>') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod conversion {
+    use super::*;
+    use usiem::serde_json::{self, json};
+
+    #[test]
+    fn should_convert_markdown_blocks() {
+        let blocks = markdown_to_blocks("# Suspicious login\n\nThe user **admin** logged in from `10.0.0.1`, see [the runbook](https://wiki.example.com/runbook).\n\n## Steps\n\n1. Check the host\n2. Reset the credentials\n   - Active Directory\n\n- [x] Notify the owner\n- [ ] Close the alert\n\n> Reported by *the EDR*\n\n```sh\nwhoami\n```\n\n---\n\n| Field | Value |\n|-------|-------|\n| source.ip | 10.0.0.1 |\n");
+        assert_eq!(json!([
+            {"heading_1": {"rich_text": [{"type": "text", "text": {"content": "Suspicious login"}}]}},
+            {"paragraph": {"rich_text": [
+                {"type": "text", "text": {"content": "The user "}},
+                {"type": "text", "text": {"content": "admin"}, "annotations": {"bold": true}},
+                {"type": "text", "text": {"content": " logged in from "}},
+                {"type": "text", "text": {"content": "10.0.0.1"}, "annotations": {"code": true}},
+                {"type": "text", "text": {"content": ", see "}},
+                {"type": "text", "text": {"content": "the runbook", "link": {"url": "https://wiki.example.com/runbook"}}},
+                {"type": "text", "text": {"content": "."}}
+            ]}},
+            {"heading_2": {"rich_text": [{"type": "text", "text": {"content": "Steps"}}]}},
+            {"numbered_list_item": {"rich_text": [{"type": "text", "text": {"content": "Check the host"}}]}},
+            {"numbered_list_item": {"rich_text": [{"type": "text", "text": {"content": "Reset the credentials"}}], "children": [
+                {"bulleted_list_item": {"rich_text": [{"type": "text", "text": {"content": "Active Directory"}}]}}
+            ]}},
+            {"to_do": {"rich_text": [{"type": "text", "text": {"content": "Notify the owner"}}], "checked": true}},
+            {"to_do": {"rich_text": [{"type": "text", "text": {"content": "Close the alert"}}], "checked": false}},
+            {"quote": {"rich_text": [
+                {"type": "text", "text": {"content": "Reported by "}},
+                {"type": "text", "text": {"content": "the EDR"}, "annotations": {"italic": true}}
+            ]}},
+            {"code": {"rich_text": [{"type": "text", "text": {"content": "whoami"}}], "language": "shell"}},
+            {"divider": {}},
+            {"table": {"table_width": 2, "has_column_header": true, "has_row_header": false, "children": [
+                {"table_row": {"cells": [[{"type": "text", "text": {"content": "Field"}}], [{"type": "text", "text": {"content": "Value"}}]]}},
+                {"table_row": {"cells": [[{"type": "text", "text": {"content": "source.ip"}}], [{"type": "text", "text": {"content": "10.0.0.1"}}]]}}
+            ]}}
+        ]), serde_json::to_value(&blocks).unwrap());
+    }
+
+    #[test]
+    fn should_limit_nesting() {
+        let blocks = markdown_to_blocks("- a\n  - b\n    - c\n      - d\n");
+        let blocks = serde_json::to_value(&blocks).unwrap();
+        let second = &blocks[0]["bulleted_list_item"]["children"][0]["bulleted_list_item"]["children"];
+        assert_eq!(2, second.as_array().unwrap().len());
+        assert_eq!("c", second[0]["bulleted_list_item"]["rich_text"][0]["text"]["content"]);
+        assert_eq!("d", second[1]["bulleted_list_item"]["rich_text"][0]["text"]["content"]);
+    }
+
+    #[test]
+    fn should_limit_children() {
+        let list : String = (0..250).map(|v| format!("  - item {}\n", v)).collect();
+        let blocks = markdown_to_blocks(&format!("- parent\n{}", list));
+        assert_eq!(151, blocks.len());
+        assert_eq!(MAX_CHILDREN, blocks[0].children().len());
+        let blocks = serde_json::to_value(&blocks).unwrap();
+        assert_eq!("item 100", blocks[1]["bulleted_list_item"]["rich_text"][0]["text"]["content"]);
+
+        let rows : String = (0..150).map(|v| format!("| row {} | value |\n", v)).collect();
+        let blocks = markdown_to_blocks(&format!("| Field | Value |\n|---|---|\n{}", rows));
+        let rows = blocks[0].children();
+        assert_eq!(MAX_CHILDREN, rows.len());
+        let last = serde_json::to_value(&rows[MAX_CHILDREN - 1]).unwrap();
+        assert_eq!("… 52 more rows", last["table_row"]["cells"][0][0]["text"]["content"]);
+        assert_eq!(json!([]), last["table_row"]["cells"][1]);
+    }
+
+    #[test]
+    fn should_keep_text_limits() {
+        let blocks = markdown_to_blocks(&format!("{}\n\n```unknown-language\ncode\n```\n\n[relative](./runbook.md)", "a".repeat(5000)));
+        let blocks = serde_json::to_value(&blocks).unwrap();
+        assert_eq!(3, blocks[0]["paragraph"]["rich_text"].as_array().unwrap().len());
+        assert_eq!("plain text", blocks[1]["code"]["language"]);
+        assert_eq!(json!({"rich_text": [{"type": "text", "text": {"content": "relative"}}]}), blocks[2]["paragraph"]);
+    }
+
+    #[test]
+    fn should_convert_markdown_to_rich_text() {
+        let text = serde_json::to_value(markdown_to_rich_text("# Title\n\nSome **bold** text")).unwrap();
+        assert_eq!(json!([
+            {"type": "text", "text": {"content": "Title\nSome "}},
+            {"type": "text", "text": {"content": "bold"}, "annotations": {"bold": true}},
+            {"type": "text", "text": {"content": " text"}}
+        ]), text);
+    }
+
+    #[test]
+    fn should_export_markdown() {
+        let markdown = "# Suspicious login\n\nThe user **admin** logged in from `10.0.0.1`, see [the runbook](https://wiki.example.com/runbook).\n\n## Steps\n\n1. Check the host\n2. Reset the credentials\n   - Active Directory\n\n- [x] Notify the owner\n- [ ] Close the alert\n\n> Reported by *the EDR*\n\n```shell\nwhoami\n```\n\n---\n\n| Field | Value |\n| --- | --- |\n| source\\_ip | 10.0.0.1 |";
+        assert_eq!(markdown, elements_to_markdown(&markdown_to_blocks(markdown)));
+    }
+
+    #[test]
+    fn should_export_retrieved_blocks() {
+        let blocks : Vec<BlockObject> = serde_json::from_value(json!([
+            {"object": "block", "id": "1", "has_children": true, "type": "toggle", "toggle": {"rich_text": [
+                {"type": "text", "text": {"content": "Assigned to ", "link": null}, "plain_text": "Assigned to ", "href": null},
+                {"type": "mention", "mention": {"type": "user", "user": {"object": "user", "id": "ee5f0f84"}}, "plain_text": "@Ana", "href": null}
+            ]}, "children": [
+                {"object": "block", "id": "2", "type": "paragraph", "paragraph": {"rich_text": [
+                    {"type": "mention", "mention": {"type": "page", "page": {"id": "59833787"}}, "plain_text": "srv01", "href": "https://www.notion.so/59833787"},
+                    {"type": "text", "text": {"content": " is isolated "}, "annotations": {"bold": true, "italic": false, "strikethrough": false, "underline": false, "code": false, "color": "default"}, "plain_text": " is isolated "}
+                ]}}
+            ]},
+            {"object": "block", "id": "3", "type": "callout", "callout": {"rich_text": [{"type": "text", "text": {"content": "Critical asset"}}], "icon": {"type": "emoji", "emoji": "🚨"}}},
+            {"object": "block", "id": "4", "type": "image", "image": {"type": "external", "external": {"url": "https://example.com/graph.png"}, "caption": []}},
+            {"object": "block", "id": "5", "type": "child_page", "child_page": {"title": "Evidences"}}
+        ])).unwrap();
+        assert_eq!("- Assigned to @Ana\n\n  [srv01](https://www.notion.so/59833787) **is isolated** \n\n> 🚨 Critical asset\n\n![](https://example.com/graph.png)", blocks_to_markdown(&blocks));
+    }
+}
@@ -3,8 +3,9 @@ use std::collections::BTreeMap;
 use serde::{Deserialize, Serialize};
 
 #[path ="./properties.rs"]
-pub (crate) mod properties;
+pub mod properties;
 use properties::*;
+use super::page::Parent;
 
 #[derive(Default, Debug, Deserialize, Serialize, Hash)]
 #[serde(tag = "object", rename="database")]
@@ -16,6 +17,91 @@ pub struct DatabaseDefinition {
 }
 
 
+/// Body of `POST /v1/databases`
+#[derive(Debug, Serialize)]
+pub struct DatabaseCreate {
+    pub parent : Parent,
+    pub title : Vec<RichTextObject>,
+    pub properties : BTreeMap<String, PropertySchema>
+}
+
+impl DatabaseCreate {
+    /// Database inside a page
+    pub fn new(parent_page_id : &str, title : &str) -> Self {
+        Self {
+            parent : Parent::PageId { page_id : parent_page_id.to_owned() },
+            title : RichTextObject::chunks(title),
+            properties : BTreeMap::new()
+        }
+    }
+    pub fn property(mut self, name : &str, schema : PropertySchema) -> Self {
+        self.properties.insert(name.to_owned(), schema);
+        self
+    }
+}
+
+/// Body of `PATCH /v1/databases/{database_id}`. Properties are added or changed, select options
+/// that are not in the request are removed from the property.
+#[derive(Debug, Default, Serialize)]
+pub struct DatabaseUpdate {
+    pub properties : BTreeMap<String, PropertySchema>
+}
+
+/// Configuration of a property when a database is created or updated
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PropertySchema {
+    Title {},
+    RichText {},
+    Number { format : String },
+    Select { options : Vec<OptionSchema> },
+    MultiSelect { options : Vec<OptionSchema> },
+    Date {},
+    Checkbox {},
+    Url {},
+    /// The Notion API does not allow creating status properties
+    Status {},
+}
+
+impl PropertySchema {
+    pub fn number() -> Self {
+        PropertySchema::Number { format : "number".to_owned() }
+    }
+    /// Select with the options and colors
+    pub fn select(options : &[(&str, &str)]) -> Self {
+        PropertySchema::Select { options : options.iter().map(|(name, color)| OptionSchema::new(name, Some(color))).collect() }
+    }
+    pub fn multi_select() -> Self {
+        PropertySchema::MultiSelect { options : Vec::new() }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct OptionSchema {
+    pub name : String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub color : Option<String>
+}
+
+impl OptionSchema {
+    pub fn new(name : &str, color : Option<&str>) -> Self {
+        Self {
+            name : name.to_owned(),
+            color : color.map(|v| v.to_owned())
+        }
+    }
+}
+
+/// Ids of the properties of a database, by property name
+#[derive(Default, Debug, Deserialize)]
+pub struct DatabasePropertyIds {
+    pub properties : BTreeMap<String, PropertyId>
+}
+
+#[derive(Default, Debug, Deserialize)]
+pub struct PropertyId {
+    pub id : String
+}
 
 #[derive(Default, Debug, Deserialize, Serialize, Hash)]
 #[serde(tag = "type")]
@@ -24,25 +110,25 @@ pub enum PropertyDefinition {
     #[serde(rename = "title")]
     Title(TitleProperty),
     #[serde(rename = "rich_text")]
-    RichText(RichTextValue),
+    RichText(RichTextProperty),
     #[serde(rename = "date")]
     Date(DateProperty),
     #[serde(rename = "files")]
-    Files,
+    Files(FilesProperty),
     #[serde(rename = "checkbox")]
-    CheckBox,
+    CheckBox(CheckboxProperty),
     #[serde(rename = "url")]
-    Url,
+    Url(UrlProperty),
     #[serde(rename = "email")]
-    Email,
+    Email(EmailProperty),
     #[serde(rename = "phone_number")]
-    PhoneNumber,
+    PhoneNumber(PhoneNumberProperty),
     #[serde(rename = "formula")]
-    Formula,
+    Formula(FormulaProperty),
     #[serde(rename = "relation")]
-    Relation,
-    #[serde(rename = "Rollup")]
-    Rollup,
+    Relation(RelationProperty),
+    #[serde(rename = "rollup")]
+    Rollup(RollupProperty),
     #[serde(rename = "number")]
     Number(NumberProperty),
     #[serde(rename = "select")]
@@ -61,9 +147,60 @@ pub enum PropertyDefinition {
     LastEditedTime(LastEditedTimeProperty),
     #[serde(rename = "status")]
     Status(StatusProperty),
+    #[serde(rename = "unique_id")]
+    UniqueId(UniqueIdProperty),
+    #[serde(rename = "verification")]
+    Verification(VerificationProperty),
+    #[serde(rename = "button")]
+    Button(ButtonProperty),
     #[default]
     #[serde(skip_deserializing)]
-    Null
+    Null,
+    /// Property types added to Notion after this model
+    #[serde(other)]
+    Unsupported
+}
+
+impl PropertyDefinition {
+    /// Type of the property as named by the Notion API
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            PropertyDefinition::Title(_) => "title",
+            PropertyDefinition::RichText(_) => "rich_text",
+            PropertyDefinition::Date(_) => "date",
+            PropertyDefinition::Files(_) => "files",
+            PropertyDefinition::CheckBox(_) => "checkbox",
+            PropertyDefinition::Url(_) => "url",
+            PropertyDefinition::Email(_) => "email",
+            PropertyDefinition::PhoneNumber(_) => "phone_number",
+            PropertyDefinition::Formula(_) => "formula",
+            PropertyDefinition::Relation(_) => "relation",
+            PropertyDefinition::Rollup(_) => "rollup",
+            PropertyDefinition::Number(_) => "number",
+            PropertyDefinition::Select(_) => "select",
+            PropertyDefinition::MultiSelect(_) => "multi_select",
+            PropertyDefinition::People(_) => "people",
+            PropertyDefinition::CreatedTime(_) => "created_time",
+            PropertyDefinition::LastEditedBy(_) => "last_edited_by",
+            PropertyDefinition::CreatedBy(_) => "created_by",
+            PropertyDefinition::LastEditedTime(_) => "last_edited_time",
+            PropertyDefinition::Status(_) => "status",
+            PropertyDefinition::UniqueId(_) => "unique_id",
+            PropertyDefinition::Verification(_) => "verification",
+            PropertyDefinition::Button(_) => "button",
+            PropertyDefinition::Unsupported => "unsupported",
+            PropertyDefinition::Null => "null",
+        }
+    }
+
+    /// Options of a select or status property
+    pub fn option_names(&self) -> Vec<&str> {
+        match self {
+            PropertyDefinition::Select(v) => v.select.options.iter().map(|v| &v.name[..]).collect(),
+            PropertyDefinition::Status(v) => v.status.options.iter().map(|v| &v.name[..]).collect(),
+            _ => Vec::new()
+        }
+    }
 }
 
 
@@ -74,6 +211,26 @@ mod serialization {
     use super::DatabaseDefinition;
     use usiem::serde_json::{self, json};
 
+    #[test]
+    fn should_serialize_database_creation() {
+        use super::{DatabaseCreate, PropertySchema};
+        let database = DatabaseCreate::new("b55c9c91-384d-452b-81db-d1ef79372b75", "SIEM Alerts")
+            .property("Name", PropertySchema::Title {})
+            .property("Priority", PropertySchema::select(&[("Low", "blue"), ("High", "orange")]))
+            .property("Tags", PropertySchema::multi_select())
+            .property("Occurrences", PropertySchema::number());
+        assert_eq!(json!({
+            "parent": {"type": "page_id", "page_id": "b55c9c91-384d-452b-81db-d1ef79372b75"},
+            "title": [{"type": "text", "text": {"content": "SIEM Alerts"}}],
+            "properties": {
+                "Name": {"title": {}},
+                "Priority": {"select": {"options": [{"name": "Low", "color": "blue"}, {"name": "High", "color": "orange"}]}},
+                "Tags": {"multi_select": {"options": []}},
+                "Occurrences": {"number": {"format": "number"}}
+            }
+        }), serde_json::to_value(&database).unwrap());
+    }
+
     #[test]
     fn should_deserialize_database() {
         let _database_obj : DatabaseDefinition = serde_json::from_value(json!({
@@ -184,4 +341,95 @@ mod serialization {
           assert_ne!(hasher1.finish(), hasher2.finish());
     }
 
-}
\ No newline at end of file
+    #[test]
+    fn should_deserialize_property_configurations() {
+        use super::PropertyDefinition;
+        use super::properties::RelationType;
+        let database : DatabaseDefinition = serde_json::from_value(json!({
+            "object": "database",
+            "id" : "1234",
+            "created_time" : "1234",
+            "last_edited_time" : "1234",
+            "properties": {
+              "Priority": {"id": "pRio", "name": "Priority", "type": "select", "select": {"options": [
+                {"id": "1", "name": "High", "color": "orange", "description": "Review today"},
+                {"id": "2", "name": "Low", "color": "blue"}
+              ]}},
+              "Tags": {"id": "tAgs", "type": "multi_select", "multi_select": {"options": [{"id": "3", "name": "T1059", "color": "red"}]}},
+              "Score": {"id": "sCor", "type": "formula", "formula": {"expression": "prop(\"Occurrences\") * 2"}},
+              "Hosts": {"id": "hOst", "type": "relation", "relation": {
+                "database_id": "668d797c-76fa-4934-9b05-ad288df2d136",
+                "type": "dual_property",
+                "dual_property": {"synced_property_name": "Alerts", "synced_property_id": "aLrt"}
+              }},
+              "Owners": {"id": "oWnr", "type": "relation", "relation": {
+                "database_id": "5a3b0d8e-3c47-4b1e-8f5e-0c7b1f0a9c11",
+                "type": "single_property",
+                "single_property": {}
+              }},
+              "Host count": {"id": "cNt", "type": "rollup", "rollup": {
+                "relation_property_name": "Hosts", "relation_property_id": "hOst",
+                "rollup_property_name": "Name", "rollup_property_id": "title", "function": "count"
+              }},
+              "Id": {"id": "iD", "type": "unique_id", "unique_id": {"prefix": "ALERT"}},
+              "Verified": {"id": "vEr", "type": "verification", "verification": {}},
+              "Escalate": {"id": "bTn", "type": "button", "button": {}},
+              "Report": {"id": "uRl", "type": "url", "url": {}},
+              "Future": {"id": "fUt", "type": "some_new_type", "some_new_type": {}}
+            }
+          })).unwrap();
+        match &database.properties["Priority"] {
+            PropertyDefinition::Select(v) => {
+                assert_eq!("orange", v.select.options[0].color);
+                assert_eq!(Some("Review today"), v.select.options[0].description.as_deref());
+            },
+            _ => panic!("Priority must be a select")
+        }
+        assert_eq!(vec!["High", "Low"], database.properties["Priority"].option_names());
+        match &database.properties["Tags"] {
+            PropertyDefinition::MultiSelect(v) => assert_eq!("T1059", v.multi_select.options[0].name),
+            _ => panic!("Tags must be a multi_select")
+        }
+        match &database.properties["Score"] {
+            PropertyDefinition::Formula(v) => assert_eq!("prop(\"Occurrences\") * 2", v.formula.expression),
+            _ => panic!("Score must be a formula")
+        }
+        match &database.properties["Hosts"] {
+            PropertyDefinition::Relation(v) => match &v.relation.relation_type {
+                RelationType::DualProperty { dual_property } => assert_eq!("aLrt", dual_property.synced_property_id),
+                _ => panic!("Hosts must be a dual property relation")
+            },
+            _ => panic!("Hosts must be a relation")
+        }
+        assert!(matches!(&database.properties["Owners"], PropertyDefinition::Relation(v) if matches!(v.relation.relation_type, RelationType::SingleProperty { .. })));
+        match &database.properties["Host count"] {
+            PropertyDefinition::Rollup(v) => {
+                assert_eq!("hOst", v.rollup.relation_property_id);
+                assert_eq!("count", v.rollup.function);
+            },
+            _ => panic!("Host count must be a rollup")
+        }
+        match &database.properties["Id"] {
+            PropertyDefinition::UniqueId(v) => assert_eq!(Some("ALERT"), v.unique_id.prefix.as_deref()),
+            _ => panic!("Id must be a unique_id")
+        }
+        assert_eq!("verification", database.properties["Verified"].type_name());
+        assert_eq!("button", database.properties["Escalate"].type_name());
+        assert_eq!("url", database.properties["Report"].type_name());
+        assert_eq!("unsupported", database.properties["Future"].type_name());
+    }
+
+    #[test]
+    fn should_serialize_relation_config() {
+        use super::properties::{RelationInternal, RelationType, DualPropertyRelation};
+        let relation = RelationInternal {
+            database_id : "668d797c".to_owned(),
+            relation_type : RelationType::DualProperty { dual_property : DualPropertyRelation { synced_property_name : "Alerts".to_owned(), synced_property_id : "aLrt".to_owned() } }
+        };
+        assert_eq!(json!({
+            "database_id": "668d797c",
+            "type": "dual_property",
+            "dual_property": {"synced_property_name": "Alerts", "synced_property_id": "aLrt"}
+        }), serde_json::to_value(&relation).unwrap());
+    }
+}
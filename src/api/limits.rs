@@ -0,0 +1,151 @@
+//! Size limits of the Notion API: https://developers.notion.com/reference/request-limits
+//!
+//! Lengths are measured in UTF-16 code units, like Notion does.
+
+/// Characters in the content of a rich text object
+pub const MAX_TEXT_LENGTH : usize = 2000;
+/// Rich text objects in a rich text array
+pub const MAX_RICH_TEXT_ITEMS : usize = 100;
+/// Blocks in any array of blocks (page children, appended children)
+pub const MAX_CHILDREN : usize = 100;
+/// Options in a multi_select value
+pub const MAX_MULTI_SELECT_ITEMS : usize = 100;
+/// Characters in the name of a select or multi_select option
+pub const MAX_OPTION_NAME_LENGTH : usize = 100;
+/// Blocks in a request including the nested ones
+pub const MAX_PAYLOAD_BLOCKS : usize = 1000;
+/// Size in bytes of the JSON body of a request
+pub const MAX_PAYLOAD_SIZE : usize = 500_000;
+
+/// Length of the text as counted by Notion
+pub fn text_length(content : &str) -> usize {
+    content.chars().map(char::len_utf16).sum()
+}
+
+/// Splits the text in chunks of at most `max_length` without breaking characters.
+/// A chunk ends after a line break when there is one in its second half.
+pub fn split_text(content : &str, max_length : usize) -> Vec<&str> {
+    let mut chunks = Vec::new();
+    let mut rest = content;
+    while !rest.is_empty() {
+        let mut units = 0;
+        let mut end = rest.len();
+        let mut last_newline = None;
+        for (i, c) in rest.char_indices() {
+            if units + c.len_utf16() > max_length {
+                end = i;
+                break;
+            }
+            units += c.len_utf16();
+            if c == '\n' {
+                last_newline = Some(i + 1);
+            }
+        }
+        if end < rest.len() {
+            if let Some(newline) = last_newline {
+                if newline > end / 2 {
+                    end = newline;
+                }
+            }
+        }
+        if end == 0 {
+            // max_length is smaller than the first character
+            end = rest.chars().next().map(char::len_utf8).unwrap_or(rest.len());
+        }
+        chunks.push(&rest[..end]);
+        rest = &rest[end..];
+    }
+    chunks
+}
+
+/// Cuts the text to `max_length`, ending it with "…" if it was truncated
+pub fn truncate_text(content : &str, max_length : usize) -> String {
+    if text_length(content) <= max_length {
+        return content.to_owned();
+    }
+    let mut truncated = String::with_capacity(content.len().min(max_length * 4));
+    let mut units = 0;
+    for c in content.chars() {
+        if units + c.len_utf16() + 1 > max_length {
+            break;
+        }
+        units += c.len_utf16();
+        truncated.push(c);
+    }
+    truncated.push('…');
+    truncated
+}
+
+/// Select option names cannot contain commas and are limited to 100 characters
+pub fn option_name(name : &str) -> String {
+    truncate_text(&name.replace(',', " "), MAX_OPTION_NAME_LENGTH)
+}
+
+/// Removes duplicated and exceeding options of a multi_select value
+pub fn option_names<I, S>(names : I) -> Vec<String> where I : IntoIterator<Item = S>, S : AsRef<str> {
+    let mut options : Vec<String> = Vec::new();
+    for name in names {
+        let name = option_name(name.as_ref());
+        if options.len() >= MAX_MULTI_SELECT_ITEMS {
+            break;
+        }
+        if !name.is_empty() && !options.contains(&name) {
+            options.push(name);
+        }
+    }
+    options
+}
+
+#[cfg(test)]
+mod text {
+    use super::*;
+
+    #[test]
+    fn should_split_long_text() {
+        let content = "a".repeat(4500);
+        let chunks = split_text(&content, MAX_TEXT_LENGTH);
+        assert_eq!(vec![2000, 2000, 500], chunks.iter().map(|v| v.len()).collect::<Vec<usize>>());
+        assert_eq!(content, chunks.concat());
+        assert!(split_text("", MAX_TEXT_LENGTH).is_empty());
+    }
+
+    #[test]
+    fn should_not_break_characters() {
+        // 2 bytes in UTF-8, 1 unit in UTF-16
+        let content = "ñ".repeat(2001);
+        let chunks = split_text(&content, MAX_TEXT_LENGTH);
+        assert_eq!(vec![2000, 1], chunks.iter().map(|v| v.chars().count()).collect::<Vec<usize>>());
+        // 4 bytes in UTF-8, 2 units in UTF-16
+        let content = "🚨".repeat(1001);
+        let chunks = split_text(&content, MAX_TEXT_LENGTH);
+        assert_eq!(vec![1000, 1], chunks.iter().map(|v| v.chars().count()).collect::<Vec<usize>>());
+        assert_eq!(vec!["🚨", "🚨"], split_text("🚨🚨", 1));
+    }
+
+    #[test]
+    fn should_prefer_line_breaks() {
+        let content = format!("{}\n{}", "a".repeat(1500), "b".repeat(1000));
+        let chunks = split_text(&content, MAX_TEXT_LENGTH);
+        assert_eq!(1501, chunks[0].len());
+        assert_eq!("b".repeat(1000), chunks[1]);
+        let content = format!("{}\n{}", "a".repeat(500), "b".repeat(2000));
+        let chunks = split_text(&content, MAX_TEXT_LENGTH);
+        assert_eq!(2000, chunks[0].len());
+    }
+
+    #[test]
+    fn should_truncate_text() {
+        assert_eq!("abc", truncate_text("abc", 3));
+        assert_eq!("ab…", truncate_text("abcd", 3));
+        assert_eq!(MAX_TEXT_LENGTH, text_length(&truncate_text(&"🚨".repeat(2000), MAX_TEXT_LENGTH)) + 1);
+    }
+
+    #[test]
+    fn should_clean_options() {
+        assert_eq!("Brute force  SSH", option_name("Brute force, SSH"));
+        assert_eq!(MAX_OPTION_NAME_LENGTH, text_length(&option_name(&"a".repeat(200))));
+        let options = option_names((0..200).map(|v| format!("tag{}", v % 150)));
+        assert_eq!(MAX_MULTI_SELECT_ITEMS, options.len());
+        assert_eq!(vec!["a"], option_names(["a", "a", ""]));
+    }
+}
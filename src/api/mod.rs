@@ -1,3 +1,5 @@
 pub mod database;
 pub mod page;
-pub mod block;
\ No newline at end of file
+pub mod block;
+pub mod limits;
+pub mod query;
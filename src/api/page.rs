@@ -1,8 +1,9 @@
 use std::collections::BTreeMap;
 
-use serde::{Deserialize, Serialize};
+use serde::{de::Error, Deserialize, Deserializer, Serialize};
+use usiem::serde_json::{from_value, Value};
 
-use super::{database::properties::*, block::{BlockElement}};
+use super::{database::properties::*, block::{BlockElement, BlockObject, batch_blocks, markdown}};
 
 
 #[derive(Default, Debug, Deserialize, Serialize)]
@@ -12,58 +13,228 @@ pub struct PageElement {
     #[serde(skip_serializing_if = "Vec::is_empty")]
     pub children : Vec<BlockElement>
 }
+
+impl PageElement {
+    /// Keeps in the page the children that fit in the creation request and returns the others
+    /// grouped in batches to be appended in order with `PATCH /v1/blocks/{page_id}/children`
+    pub fn split_children(&mut self) -> Vec<Vec<BlockElement>> {
+        let children = std::mem::take(&mut self.children);
+        // The children field is not serialized while empty
+        let overhead = self.payload_size() + ",\"children\":[]".len();
+        let mut batches = batch_blocks(children, overhead);
+        if !batches.is_empty() {
+            self.children = batches.remove(0);
+        }
+        batches
+    }
+
+    /// Size in bytes of the request body
+    pub fn payload_size(&self) -> usize {
+        usiem::serde_json::to_vec(self).map(|v| v.len()).unwrap_or(0)
+    }
+}
+
 #[derive(Default, Debug, Deserialize, Serialize)]
 pub struct DatabaseParent {
     pub database_id : String
 }
 
+/// Body of `PATCH /v1/pages/{page_id}`. Only the given properties are modified.
 #[derive(Default, Debug, Deserialize, Serialize)]
+pub struct PageUpdate {
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub properties : BTreeMap<String, PropertyValue>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub archived : Option<bool>
+}
+
+impl PageUpdate {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn property(mut self, name : &str, value : PropertyValue) -> Self {
+        self.properties.insert(name.to_owned(), value);
+        self
+    }
+    pub fn archived(mut self, archived : bool) -> Self {
+        self.archived = Some(archived);
+        self
+    }
+}
+
+/// Page object returned by the Notion API
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(tag = "object", rename = "page")]
+pub struct Page {
+    pub id : String,
+    pub created_time : String,
+    pub last_edited_time : String,
+    #[serde(default)]
+    pub archived : bool,
+    #[serde(default)]
+    pub url : String,
+    pub parent : Parent,
+    pub properties : BTreeMap<String, Value>
+}
+
+impl Page {
+    /// Typed value of the property
+    pub fn property_value(&self, name : &str) -> Option<PropertyValue> {
+        self.properties.get(name).and_then(|v| from_value(v.clone()).ok())
+    }
+}
+
+/// Page with its blocks
+#[derive(Debug)]
+pub struct PageContent {
+    pub page : Page,
+    pub blocks : Vec<BlockObject>
+}
+
+impl PageContent {
+    /// Markdown of the page: its title as the first heading followed by its blocks
+    pub fn to_markdown(&self) -> String {
+        let title = self.page.properties.keys().find_map(|name| match self.page.property_value(name) {
+            Some(PropertyValue::Title(title)) => Some(markdown::rich_text_to_markdown(&title.title)),
+            _ => None
+        }).unwrap_or_default();
+        let blocks = markdown::blocks_to_markdown(&self.blocks);
+        match (title.is_empty(), blocks.is_empty()) {
+            (true, _) => blocks,
+            (false, true) => format!("# {}", title),
+            (false, false) => format!("# {}\n\n{}", title, blocks),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Parent {
+    DatabaseId { database_id : String },
+    PageId { page_id : String },
+    BlockId { block_id : String },
+    Workspace { workspace : bool },
+}
+
+/// Value of a page property. Values are serialized as Notion expects them in requests (`{"select": {"name": "High"}}`)
+/// and deserialized both from requests and from the responses, which also have the property id and type.
+#[derive(Default, Debug, Clone, PartialEq, Serialize)]
 #[serde(untagged)]
 #[non_exhaustive]
 pub enum PropertyValue {
-    #[serde(rename = "title")]
     Title(TitleValue),
-    #[serde(rename = "rich_text")]
     RichText(RichTextValue),
-    #[serde(rename = "date")]
     Date(DateValue),
-    #[serde(rename = "files")]
-    Files,
-    #[serde(rename = "checkbox")]
-    CheckBox,
-    #[serde(rename = "url")]
-    Url,
-    #[serde(rename = "email")]
-    Email,
-    #[serde(rename = "phone_number")]
-    PhoneNumber,
-    #[serde(rename = "formula")]
-    Formula,
-    #[serde(rename = "relation")]
-    Relation,
-    #[serde(rename = "Rollup")]
-    Rollup,
-    #[serde(rename = "number")]
+    Files(FilesValue),
+    CheckBox(CheckboxValue),
+    Url(UrlValue),
+    Email(EmailValue),
+    PhoneNumber(PhoneNumberValue),
+    Formula(FormulaValue),
+    Relation(RelationValue),
+    Rollup(RollupValue),
     Number(NumberValue),
-    #[serde(rename = "select")]
     Select(SelectValue),
-    #[serde(rename = "multi_select")]
     MultiSelect(MultiSelectValue),
-    #[serde(rename = "people")]
-    People(PeopleProperty),
-    #[serde(rename = "created_time")]
+    People(PeopleValue),
     CreatedTime(CreatedTimeValue),
-    #[serde(rename = "last_edited_by")]
-    LastEditedBy(LastEditedByProperty),
-    #[serde(rename = "created_by")]
-    CreatedBy(CreatedByProperty),
-    #[serde(rename = "last_edited_time")]
-    LastEditedTime(LastEditedTimeProperty),
-    #[serde(rename = "status")]
+    LastEditedBy(LastEditedByValue),
+    CreatedBy(CreatedByValue),
+    LastEditedTime(LastEditedTimeValue),
     Status(StatusValue),
+    UniqueId(UniqueIdValue),
+    Verification(VerificationValue),
+    Button(ButtonValue),
     #[default]
-    #[serde(skip_deserializing)]
-    Null
+    Null,
+    /// Value of a property type added to Notion after this model, as received
+    Unsupported(Value)
+}
+
+const PROPERTY_TYPES : [&str; 23] = ["title", "rich_text", "date", "files", "checkbox", "url", "email", "phone_number", "formula", "relation", "rollup", "number", "select", "multi_select", "people", "created_time", "last_edited_by", "created_by", "last_edited_time", "status", "unique_id", "verification", "button"];
+
+impl PropertyValue {
+    /// Type of the property as named by the Notion API
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            PropertyValue::Title(_) => "title",
+            PropertyValue::RichText(_) => "rich_text",
+            PropertyValue::Date(_) => "date",
+            PropertyValue::Files(_) => "files",
+            PropertyValue::CheckBox(_) => "checkbox",
+            PropertyValue::Url(_) => "url",
+            PropertyValue::Email(_) => "email",
+            PropertyValue::PhoneNumber(_) => "phone_number",
+            PropertyValue::Formula(_) => "formula",
+            PropertyValue::Relation(_) => "relation",
+            PropertyValue::Rollup(_) => "rollup",
+            PropertyValue::Number(_) => "number",
+            PropertyValue::Select(_) => "select",
+            PropertyValue::MultiSelect(_) => "multi_select",
+            PropertyValue::People(_) => "people",
+            PropertyValue::CreatedTime(_) => "created_time",
+            PropertyValue::LastEditedBy(_) => "last_edited_by",
+            PropertyValue::CreatedBy(_) => "created_by",
+            PropertyValue::LastEditedTime(_) => "last_edited_time",
+            PropertyValue::Status(_) => "status",
+            PropertyValue::UniqueId(_) => "unique_id",
+            PropertyValue::Verification(_) => "verification",
+            PropertyValue::Button(_) => "button",
+            PropertyValue::Null => "null",
+            PropertyValue::Unsupported(_) => "unsupported",
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for PropertyValue {
+    fn deserialize<D>(deserializer : D) -> Result<Self, D::Error> where D : Deserializer<'de> {
+        let value = Value::deserialize(deserializer)?;
+        let mut object = match value {
+            Value::Null => return Ok(PropertyValue::Null),
+            Value::Object(object) => object,
+            _ => return Err(D::Error::custom("a property value must be an object"))
+        };
+        // Responses tell the type, requests only have the key of the type
+        let property_type = match object.get("type").and_then(|v| v.as_str()) {
+            Some(property_type) => property_type.to_owned(),
+            None => match object.keys().find(|key| PROPERTY_TYPES.contains(&&key[..])) {
+                Some(property_type) => property_type.clone(),
+                None => return Err(D::Error::custom("the property value has no type"))
+            }
+        };
+        if !PROPERTY_TYPES.contains(&&property_type[..]) {
+            return Ok(PropertyValue::Unsupported(Value::Object(object)));
+        }
+        object.remove("id");
+        object.remove("type");
+        let value = Value::Object(object);
+        let property = match &property_type[..] {
+            "title" => from_value(value).map(PropertyValue::Title),
+            "rich_text" => from_value(value).map(PropertyValue::RichText),
+            "date" => from_value(value).map(PropertyValue::Date),
+            "files" => from_value(value).map(PropertyValue::Files),
+            "checkbox" => from_value(value).map(PropertyValue::CheckBox),
+            "url" => from_value(value).map(PropertyValue::Url),
+            "email" => from_value(value).map(PropertyValue::Email),
+            "phone_number" => from_value(value).map(PropertyValue::PhoneNumber),
+            "formula" => from_value(value).map(PropertyValue::Formula),
+            "relation" => from_value(value).map(PropertyValue::Relation),
+            "rollup" => from_value(value).map(PropertyValue::Rollup),
+            "number" => from_value(value).map(PropertyValue::Number),
+            "select" => from_value(value).map(PropertyValue::Select),
+            "multi_select" => from_value(value).map(PropertyValue::MultiSelect),
+            "people" => from_value(value).map(PropertyValue::People),
+            "created_time" => from_value(value).map(PropertyValue::CreatedTime),
+            "last_edited_by" => from_value(value).map(PropertyValue::LastEditedBy),
+            "created_by" => from_value(value).map(PropertyValue::CreatedBy),
+            "last_edited_time" => from_value(value).map(PropertyValue::LastEditedTime),
+            "status" => from_value(value).map(PropertyValue::Status),
+            "unique_id" => from_value(value).map(PropertyValue::UniqueId),
+            "verification" => from_value(value).map(PropertyValue::Verification),
+            _ => from_value(value).map(PropertyValue::Button),
+        };
+        property.map_err(|e| D::Error::custom(format!("invalid {} property value: {}", property_type, e)))
+    }
 }
 
 
@@ -112,16 +283,8 @@ mod serialization {
     fn check_serialization() {
         let mut properties = BTreeMap::new();
     properties.insert("Name".to_owned(), PropertyValue::Title(TitleValue::new("Testing Component")));
-    properties.insert("Priority".to_owned(), PropertyValue::Select(SelectValue{
-        select : SelectValueInternal {
-            name : "Critical".to_owned()
-        }
-    }));
-    properties.insert("MITRE".to_owned(), PropertyValue::MultiSelect(MultiSelectValue {
-        multi_select : vec![MultiSelectValueInternal {
-            name : "T1548.001 ".to_owned()
-        }]
-    }));
+    properties.insert("Priority".to_owned(), PropertyValue::Select(SelectValue::new("Critical")));
+    properties.insert("MITRE".to_owned(), PropertyValue::MultiSelect(MultiSelectValue::new(["T1548.001"])));
     let children = vec![
         BlockElement::HeadingOne(RichTextValue {
             rich_text: vec![RichTextObject::new("This is the content of an alert."), RichTextObject::new("In multiple lines?")]
@@ -137,4 +300,204 @@ mod serialization {
     let page_obj = serde_json::to_string(&new_page).unwrap();
     println!("{}", page_obj)
     }
+
+    #[test]
+    fn should_split_children_in_batches() {
+        let mut page = PageElement {
+            children : (0..250).map(|v| BlockElement::heading_three(&format!("Heading {}", v))).collect(),
+            ..Default::default()
+        };
+        let batches = page.split_children();
+        assert_eq!(100, page.children.len());
+        assert_eq!(vec![100, 50], batches.iter().map(|v| v.len()).collect::<Vec<usize>>());
+        assert!(page.payload_size() <= crate::api::limits::MAX_PAYLOAD_SIZE);
+    }
+
+    #[test]
+    fn should_deserialize_page() {
+        let page : Page = serde_json::from_value(json!({
+            "object": "page",
+            "id": "59833787-2cf9-4fdf-8782-e53db20768a5",
+            "created_time": "2022-03-01T19:05:00.000Z",
+            "last_edited_time": "2022-07-06T20:25:00.000Z",
+            "created_by": {"object": "user", "id": "ee5f0f84-409a-440f-983a-a5315961c6e4"},
+            "cover": null,
+            "icon": null,
+            "parent": {"type": "database_id", "database_id": "d9824bdc-8445-4327-be8b-5b47500af6ce"},
+            "archived": false,
+            "properties": {
+                "Name": {"id": "title", "type": "title", "title": [{"type": "text", "text": {"content": "Tuscan kale", "link": null}, "plain_text": "Tuscan kale", "href": null}]}
+            },
+            "url": "https://www.notion.so/Tuscan-kale-598337872cf94fdf8782e53db20768a5"
+        })).unwrap();
+        assert_eq!(Parent::DatabaseId { database_id : "d9824bdc-8445-4327-be8b-5b47500af6ce".to_owned() }, page.parent);
+        assert_eq!("https://www.notion.so/Tuscan-kale-598337872cf94fdf8782e53db20768a5", page.url);
+        assert!(page.properties.contains_key("Name"));
+    }
+
+    #[test]
+    fn should_serialize_page_update() {
+        let update = PageUpdate::new()
+            .property("Status", PropertyValue::Status(StatusValue::new("Done")))
+            .property("Priority", PropertyValue::Select(SelectValue::new("High")));
+        assert_eq!(json!({
+            "properties": {
+                "Priority": {"select": {"name": "High"}},
+                "Status": {"status": {"name": "Done"}}
+            }
+        }), serde_json::to_value(&update).unwrap());
+        assert_eq!(json!({"archived": true}), serde_json::to_value(PageUpdate::new().archived(true)).unwrap());
+    }
+
+    #[test]
+    fn should_round_trip_property_values() {
+        let values = vec![
+            PropertyValue::Title(TitleValue::new("Alert")),
+            PropertyValue::Number(NumberValue::new(2.5)),
+            PropertyValue::Number(NumberValue { number : None }),
+            PropertyValue::Select(SelectValue::new("High")),
+            PropertyValue::Select(SelectValue { select : None }),
+            PropertyValue::MultiSelect(MultiSelectValue::new(["T1059", "T1548"])),
+            PropertyValue::Status(StatusValue::new("Done")),
+            PropertyValue::Date(DateValue::new("2023-01-01T00:00:00.000Z".to_owned())),
+            PropertyValue::CheckBox(CheckboxValue { checkbox : true }),
+            PropertyValue::Url(UrlValue { url : Some("https://example.com".to_owned()) }),
+            PropertyValue::Email(EmailValue { email : None }),
+            PropertyValue::PhoneNumber(PhoneNumberValue { phone_number : Some("+34 600 000 000".to_owned()) }),
+            PropertyValue::Relation(RelationValue::new(["dd456007-6c66-4bba-957e-ea501dcda3a6"])),
+            PropertyValue::People(PeopleValue { people : vec![UserValue::new("ee5f0f84-409a-440f-983a-a5315961c6e4")] }),
+            PropertyValue::Files(FilesValue { files : vec![FileValue::external("capture.pcap", "https://example.com/capture.pcap")] }),
+        ];
+        for value in values {
+            let serialized = serde_json::to_value(&value).unwrap();
+            assert_eq!(1, serialized.as_object().unwrap().len(), "{}", serialized);
+            assert_eq!(value, serde_json::from_value::<PropertyValue>(serialized).unwrap());
+        }
+        assert_eq!(json!({"checkbox": false}), serde_json::to_value(PropertyValue::CheckBox(CheckboxValue::default())).unwrap());
+        assert_eq!(json!({"relation": [{"id": "dd456007"}]}), serde_json::to_value(PropertyValue::Relation(RelationValue::new(["dd456007"]))).unwrap());
+    }
+
+    #[test]
+    fn should_deserialize_response_property_values() {
+        let properties : BTreeMap<String, PropertyValue> = serde_json::from_value(json!({
+            "Done": {"id": "a%3Cql", "type": "checkbox", "checkbox": true},
+            "Empty": {"id": "b", "type": "rich_text", "rich_text": []},
+            "Priority": {"id": "c", "type": "select", "select": {"id": "1", "name": "High", "color": "orange"}},
+            "Hosts": {"id": "d", "type": "relation", "relation": [{"id": "dd456007-6c66-4bba-957e-ea501dcda3a6"}], "has_more": false},
+            "Score": {"id": "e", "type": "formula", "formula": {"type": "number", "number": 4}},
+            "Owner": {"id": "f", "type": "people", "people": [{"object": "user", "id": "ee5f0f84", "name": "Ana", "type": "person", "person": {"email": "ana@example.com"}}]},
+            "Host names": {"id": "g", "type": "rollup", "rollup": {"type": "array", "function": "show_original", "array": [
+                {"type": "title", "title": [{"type": "text", "text": {"content": "srv01", "link": null}, "plain_text": "srv01", "href": null}]}
+            ]}},
+            "Id": {"id": "h", "type": "unique_id", "unique_id": {"number": 12, "prefix": "ALERT"}},
+            "Created by": {"id": "i", "type": "created_by", "created_by": {"object": "user", "id": "ee5f0f84"}},
+            "Evidence": {"id": "j", "type": "files", "files": [{"name": "log.txt", "type": "file", "file": {"url": "https://s3.example.com/log.txt", "expiry_time": "2023-01-01T01:00:00.000Z"}}]},
+            "Verified": {"id": "k", "type": "verification", "verification": {"state": "unverified", "verified_by": null, "date": null}},
+            "Run": {"id": "l", "type": "button", "button": {}},
+            "Place": {"id": "m", "type": "place", "place": {"lat": 40.4}}
+        })).unwrap();
+        assert_eq!(PropertyValue::CheckBox(CheckboxValue { checkbox : true }), properties["Done"]);
+        assert_eq!(PropertyValue::RichText(RichTextValue { rich_text : vec![] }), properties["Empty"]);
+        match &properties["Priority"] {
+            PropertyValue::Select(SelectValue { select : Some(v) }) => {
+                assert_eq!("High", v.name);
+                assert_eq!(Some("orange"), v.color.as_deref());
+            },
+            v => panic!("Unexpected value {:?}", v)
+        }
+        assert_eq!(PropertyValue::Relation(RelationValue { has_more : Some(false), ..RelationValue::new(["dd456007-6c66-4bba-957e-ea501dcda3a6"]) }), properties["Hosts"]);
+        assert_eq!(PropertyValue::Formula(FormulaValue { formula : FormulaResult::Number { number : Some(4.0) } }), properties["Score"]);
+        match &properties["Owner"] {
+            PropertyValue::People(v) => assert_eq!(Some("ana@example.com"), v.people[0].person.as_ref().and_then(|v| v.email.as_deref())),
+            v => panic!("Unexpected value {:?}", v)
+        }
+        match &properties["Host names"] {
+            PropertyValue::Rollup(RollupValue { rollup : RollupResult { result : RollupResultValue::Array { array }, function } }) => {
+                assert_eq!("show_original", function);
+                assert!(matches!(&array[0], PropertyValue::Title(v) if v.title[0].text() == "srv01"));
+            },
+            v => panic!("Unexpected value {:?}", v)
+        }
+        assert_eq!(PropertyValue::UniqueId(UniqueIdValue { unique_id : UniqueIdValueInternal { number : Some(12), prefix : Some("ALERT".to_owned()) } }), properties["Id"]);
+        assert_eq!("created_by", properties["Created by"].type_name());
+        assert!(matches!(&properties["Evidence"], PropertyValue::Files(v) if matches!(&v.files[0].file, FileValueInternal::File { .. })));
+        assert!(matches!(&properties["Verified"], PropertyValue::Verification(VerificationValue { verification : Some(v) }) if v.state == "unverified"));
+        assert_eq!("button", properties["Run"].type_name());
+        assert_eq!("unsupported", properties["Place"].type_name());
+        for value in properties.values() {
+            assert_eq!(*value, serde_json::from_value::<PropertyValue>(serde_json::to_value(value).unwrap()).unwrap());
+        }
+    }
+
+    #[test]
+    fn should_serialize_rich_text() {
+        let text = RichTextValue { rich_text : vec![
+            RichTextObject::bold("Host "),
+            RichTextObject::link("srv01", "https://cmdb.example.com/hosts/srv01"),
+            RichTextObject::colored("192.168.1.10", Color::RedBackground),
+            RichTextObject::user_mention("ee5f0f84-409a-440f-983a-a5315961c6e4"),
+            RichTextObject::page_mention("59833787-2cf9-4fdf-8782-e53db20768a5"),
+            RichTextObject::date_mention("2023-01-01"),
+            RichTextObject::equation("e=mc^2"),
+        ]};
+        assert_eq!(json!({"rich_text": [
+            {"type": "text", "text": {"content": "Host "}, "annotations": {"bold": true}},
+            {"type": "text", "text": {"content": "srv01", "link": {"url": "https://cmdb.example.com/hosts/srv01"}}},
+            {"type": "text", "text": {"content": "192.168.1.10"}, "annotations": {"color": "red_background"}},
+            {"type": "mention", "mention": {"type": "user", "user": {"id": "ee5f0f84-409a-440f-983a-a5315961c6e4"}}},
+            {"type": "mention", "mention": {"type": "page", "page": {"id": "59833787-2cf9-4fdf-8782-e53db20768a5"}}},
+            {"type": "mention", "mention": {"type": "date", "date": {"start": "2023-01-01"}}},
+            {"type": "equation", "equation": {"expression": "e=mc^2"}}
+        ]}), serde_json::to_value(&text).unwrap());
+        assert_eq!(text, serde_json::from_value(serde_json::to_value(&text).unwrap()).unwrap());
+    }
+
+    #[test]
+    fn should_deserialize_rich_text_response() {
+        let text : RichTextValue = serde_json::from_value(json!({"rich_text": [
+            {
+                "type": "mention",
+                "mention": {"type": "database", "database": {"id": "d9824bdc-8445-4327-be8b-5b47500af6ce"}},
+                "annotations": {"bold": false, "italic": true, "strikethrough": false, "underline": false, "code": false, "color": "blue"},
+                "plain_text": "SIEM Alerts",
+                "href": "https://www.notion.so/d9824bdc84454327be8b5b47500af6ce"
+            },
+            {
+                "type": "text",
+                "text": {"content": "Tuscan kale", "link": null},
+                "annotations": {"bold": false, "italic": false, "strikethrough": false, "underline": false, "code": true, "color": "default"},
+                "plain_text": "Tuscan kale",
+                "href": null
+            },
+            {
+                "type": "mention",
+                "mention": {"type": "template_mention", "template_mention": {"type": "template_mention_date", "template_mention_date": "today"}},
+                "plain_text": "@Today",
+                "href": null
+            },
+            {
+                "type": "mention",
+                "mention": {"type": "link_mention", "link_mention": {"href": "https://github.com/u-siem/usiem-notioner", "title": "usiem-notioner"}},
+                "plain_text": "usiem-notioner",
+                "href": "https://github.com/u-siem/usiem-notioner"
+            },
+            {
+                "type": "mention",
+                "mention": {"type": "custom_emoji", "custom_emoji": {"id": "45ce454c-d427-4f53-9489-e5d0f3d1db6b", "name": "siem", "url": "https://example.com/siem.png"}},
+                "plain_text": ":siem:",
+                "href": null
+            }
+        ]})).unwrap();
+        assert_eq!("SIEM Alerts", text.rich_text[0].text());
+        assert_eq!(RichTextContent::Mention { mention : Mention::Database { database : MentionedObject { id : "d9824bdc-8445-4327-be8b-5b47500af6ce".to_owned() } } }, text.rich_text[0].content);
+        assert_eq!(Some(Color::Blue), text.rich_text[0].annotations.as_ref().and_then(|v| v.color));
+        assert_eq!(Some(true), text.rich_text[1].annotations.as_ref().and_then(|v| v.code));
+        assert_eq!(None, text.rich_text[1].href);
+        assert_eq!("@Today", text.rich_text[2].text());
+        match &text.rich_text[3].content {
+            RichTextContent::Mention { mention : Mention::Unsupported(mention) } => assert_eq!("usiem-notioner", mention["link_mention"]["title"]),
+            v => panic!("Unexpected content {:?}", v)
+        }
+        assert_eq!(json!({"type": "mention", "mention": {"type": "custom_emoji", "custom_emoji": {"id": "45ce454c-d427-4f53-9489-e5d0f3d1db6b", "name": "siem", "url": "https://example.com/siem.png"}}, "plain_text": ":siem:"}), serde_json::to_value(&text.rich_text[4]).unwrap());
+    }
 }
@@ -1,4 +1,7 @@
-use serde::{Deserialize, Serialize};
+use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+use usiem::serde_json::{from_value, Map, Value};
+
+use crate::api::limits::{self, MAX_TEXT_LENGTH};
 
 
 #[derive(Default, Debug, Deserialize, Serialize, Hash)]
@@ -12,16 +15,38 @@ pub struct StatusInternal {
     pub options : Vec<StatusOption>,
     pub groups : Vec<StatusGroup>
 }
-#[derive(Default, Debug, Deserialize, Serialize, Hash)]
+#[derive(Default, Debug, Clone, PartialEq, Deserialize, Serialize, Hash)]
 pub struct StatusValue {
+    pub status : Option<StatusValueInternal>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Deserialize, Serialize, Hash)]
+pub struct StatusValueInternal {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id : Option<String>,
     pub name : String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color : Option<String>
+}
+
+impl StatusValue {
+    pub fn new(name : &str) -> Self {
+        Self {
+            status : Some(StatusValueInternal {
+                name : name.to_owned(),
+                ..Default::default()
+            })
+        }
+    }
 }
 
 #[derive(Default, Debug, Deserialize, Serialize, Hash)]
 pub struct StatusOption {
     pub id : String,
     pub name : String,
-    pub color : String
+    pub color : String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description : Option<String>
 }
 
 #[derive(Default, Debug, Deserialize, Serialize, Hash)]
@@ -43,7 +68,7 @@ pub struct CreatedTimeInternal {
 
 }
 
-#[derive(Default, Debug, Deserialize, Serialize, Hash)]
+#[derive(Default, Debug, Clone, PartialEq, Deserialize, Serialize, Hash)]
 pub struct CreatedTimeValue {
     pub created_time : String
 }
@@ -90,17 +115,44 @@ pub struct SelectProperty {
 
 #[derive(Default, Debug, Deserialize, Serialize, Hash)]
 pub struct SelectInternal {
+    #[serde(default)]
+    pub options : Vec<SelectOption>
+}
 
+#[derive(Default, Debug, Deserialize, Serialize, Hash)]
+pub struct SelectOption {
+    #[serde(default)]
+    pub id : String,
+    pub name : String,
+    #[serde(default)]
+    pub color : String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description : Option<String>
 }
 
-#[derive(Default, Debug, Deserialize, Serialize)]
+#[derive(Default, Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub struct SelectValue {
-    pub select : SelectValueInternal
+    pub select : Option<SelectValueInternal>
 }
 
-#[derive(Default, Debug, Deserialize, Serialize)]
+#[derive(Default, Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub struct SelectValueInternal {
-    pub name : String
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id : Option<String>,
+    pub name : String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color : Option<String>
+}
+
+impl SelectValue {
+    pub fn new(name : &str) -> Self {
+        Self {
+            select : Some(SelectValueInternal {
+                name : limits::option_name(name),
+                ..Default::default()
+            })
+        }
+    }
 }
 
 
@@ -124,37 +176,53 @@ pub struct MultiSelectProperty {
 
 #[derive(Default, Debug, Deserialize, Serialize, Hash)]
 pub struct MultiSelectInternal {
-
+    #[serde(default)]
+    pub options : Vec<SelectOption>
 }
 
-#[derive(Default, Debug, Deserialize, Serialize)]
+#[derive(Default, Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub struct MultiSelectValue {
     pub multi_select : Vec<MultiSelectValueInternal>
 }
 
-#[derive(Default, Debug, Deserialize, Serialize)]
+#[derive(Default, Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub struct MultiSelectValueInternal {
-    pub name : String
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id : Option<String>,
+    pub name : String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color : Option<String>
+}
+
+impl MultiSelectValue {
+    /// Options are cleaned and limited with `limits::option_names`
+    pub fn new<I, S>(names : I) -> Self where I : IntoIterator<Item = S>, S : AsRef<str> {
+        Self {
+            multi_select : limits::option_names(names).into_iter().map(|name| MultiSelectValueInternal { name, ..Default::default() }).collect()
+        }
+    }
 }
 
-#[derive(Default, Debug, Deserialize, Serialize)]
+#[derive(Default, Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub struct DateValue {
-    pub date : DateValueInternal
+    pub date : Option<DateValueInternal>
 }
 
-#[derive(Default, Debug, Deserialize, Serialize)]
+#[derive(Default, Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub struct DateValueInternal {
     pub start : String,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub end : Option<String>
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end : Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub time_zone : Option<String>
 }
 impl DateValue {
     pub fn new(date : String) -> Self {
         Self {
-            date : DateValueInternal { 
+            date : Some(DateValueInternal {
                 start : date,
-                end : None
-            }
+                ..Default::default()
+            })
         }
     }
 }
@@ -170,9 +238,17 @@ pub struct NumberInternal {
     pub format : String
 }
 
-#[derive(Default, Debug, Deserialize, Serialize)]
+#[derive(Default, Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub struct NumberValue {
-    pub number : f64
+    pub number : Option<f64>
+}
+
+impl NumberValue {
+    pub fn new(number : f64) -> Self {
+        Self {
+            number : Some(number)
+        }
+    }
 }
 
 #[derive(Default, Debug, Deserialize, Serialize, Hash)]
@@ -185,7 +261,7 @@ pub struct TitleProperty {
 pub struct TitleInternal {
 }
 
-#[derive(Default, Debug, Deserialize, Serialize, Hash)]
+#[derive(Default, Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub struct TitleValue {
     pub title : Vec<RichTextObject>
 }
@@ -193,77 +269,279 @@ pub struct TitleValue {
 impl TitleValue {
     pub fn new(content : &str) -> Self  {
         Self {
-            title : vec![RichTextObject {
-                text : TextValueInternal { content: content.to_owned() },
-                anotations : None
-            }]
+            title : vec![RichTextObject::new_owned(limits::truncate_text(content, MAX_TEXT_LENGTH))]
         }
     }
 }
 
 
-#[derive(Default, Debug, Deserialize, Serialize, Hash)]
+#[derive(Default, Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub struct RichTextValue {
     pub rich_text : Vec<RichTextObject>
 }
 
+impl RichTextValue {
+    /// Text split in as many segments as needed. Segments over MAX_RICH_TEXT_ITEMS are discarded.
+    pub fn new(content : &str) -> Self {
+        Self {
+            rich_text : RichTextObject::chunks(content).into_iter().take(limits::MAX_RICH_TEXT_ITEMS).collect()
+        }
+    }
+}
+
 
-// We only support Text types
-#[derive(Default, Debug, Deserialize, Serialize, Hash)]
+/// Rich text object of titles, rich text values and blocks. Responses also have the plain text and the link of the object.
+#[derive(Default, Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub struct RichTextObject {
-    pub text : TextValueInternal,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub anotations : Option<RichTextAnotation>
+    #[serde(flatten)]
+    pub content : RichTextContent,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub annotations : Option<Annotations>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub plain_text : Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub href : Option<String>
+}
+
+/// The type can be omitted in requests, it is deduced from the key of the content
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum RichTextContent {
+    Text {
+        text : TextValueInternal
+    },
+    Mention {
+        mention : Mention
+    },
+    Equation {
+        equation : Equation
+    }
 }
 
-#[derive(Default, Debug, Deserialize, Serialize, Hash)]
-pub struct RichTextAnotation {
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub italic : Option<bool>,
+impl<'de> Deserialize<'de> for RichTextContent {
+    fn deserialize<D>(deserializer : D) -> Result<Self, D::Error> where D : Deserializer<'de> {
+        let mut object = Map::<String, Value>::deserialize(deserializer)?;
+        let content_type = match object.get("type").and_then(|v| v.as_str()) {
+            Some(content_type) => content_type.to_owned(),
+            None => match ["text", "mention", "equation"].into_iter().find(|key| object.contains_key(*key)) {
+                Some(content_type) => content_type.to_owned(),
+                None => return Err(D::Error::custom("the rich text object has no content"))
+            }
+        };
+        let content = object.remove(&content_type).unwrap_or(Value::Null);
+        let content = match &content_type[..] {
+            "text" => from_value(content).map(|text| RichTextContent::Text { text }),
+            "mention" => from_value(content).map(|mention| RichTextContent::Mention { mention }),
+            "equation" => from_value(content).map(|equation| RichTextContent::Equation { equation }),
+            _ => return Err(D::Error::custom(format!("unknown rich text type {}", content_type)))
+        };
+        content.map_err(|e| D::Error::custom(format!("invalid {} rich text: {}", content_type, e)))
+    }
+}
+
+impl Default for RichTextContent {
+    fn default() -> Self {
+        RichTextContent::Text { text : TextValueInternal::default() }
+    }
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct TextValueInternal {
+    pub content : String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub link : Option<Link>
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Link {
+    pub url : String
+}
+
+/// Mention types of Mention
+const MENTION_TYPES : [&str; 5] = ["user", "page", "database", "date", "link_preview"];
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case", remote = "Self")]
+pub enum Mention {
+    User {
+        user : UserValue
+    },
+    Page {
+        page : MentionedObject
+    },
+    Database {
+        database : MentionedObject
+    },
+    Date {
+        date : DateValueInternal
+    },
+    LinkPreview {
+        link_preview : Link
+    },
+    /// Mention of a type not modeled, like template mentions or custom emojis, as received
+    #[serde(skip)]
+    Unsupported(Value)
+}
+
+impl Serialize for Mention {
+    fn serialize<S>(&self, serializer : S) -> Result<S::Ok, S::Error> where S : Serializer {
+        match self {
+            Mention::Unsupported(mention) => mention.serialize(serializer),
+            _ => Mention::serialize(self, serializer)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Mention {
+    fn deserialize<D>(deserializer : D) -> Result<Self, D::Error> where D : Deserializer<'de> {
+        let mention = Value::deserialize(deserializer)?;
+        match mention.get("type").and_then(|v| v.as_str()) {
+            Some(mention_type) if MENTION_TYPES.contains(&mention_type) => Mention::deserialize(mention).map_err(D::Error::custom),
+            Some(_) => Ok(Mention::Unsupported(mention)),
+            None => Err(D::Error::custom("the mention has no type"))
+        }
+    }
+}
+
+/// Page or database referenced by a mention
+#[derive(Default, Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct MentionedObject {
+    pub id : String
+}
+
+/// KaTeX expression
+#[derive(Default, Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Equation {
+    pub expression : String
+}
+
+/// Styles of a rich text object. Notion applies the default value to the fields not in the request.
+#[derive(Default, Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Annotations {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub bold : Option<bool>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub color : Option<String>,
+    pub italic : Option<bool>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub strikethrough : Option<bool>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub underline : Option<bool>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub code : Option<bool>
+    pub code : Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub color : Option<Color>
+}
+
+/// Text and background colors of rich text and blocks
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Color {
+    #[default]
+    Default,
+    Gray,
+    Brown,
+    Orange,
+    Yellow,
+    Green,
+    Blue,
+    Purple,
+    Pink,
+    Red,
+    GrayBackground,
+    BrownBackground,
+    OrangeBackground,
+    YellowBackground,
+    GreenBackground,
+    BlueBackground,
+    PurpleBackground,
+    PinkBackground,
+    RedBackground,
 }
 
 impl RichTextObject {
     pub fn new(content : &str) -> Self {
-        let mut slf = Self::default();
-        slf.text.content = content.to_owned();
-        slf
+        Self::new_owned(content.to_owned())
     }
     pub fn new_owned(content : String) -> Self {
-        let mut slf = Self::default();
-        slf.text.content = content;
-        slf
+        Self {
+            content : RichTextContent::Text { text : TextValueInternal { content, link : None } },
+            ..Default::default()
+        }
+    }
+    /// Splits the content in objects that respect the Notion length limit
+    pub fn chunks(content : &str) -> Vec<Self> {
+        limits::split_text(content, MAX_TEXT_LENGTH).into_iter().map(Self::new).collect()
     }
     pub fn bold(content : &str) -> Self {
-        let mut slf = Self::default();
-        slf.text.content = content.to_owned();
-        let mut anotation = RichTextAnotation::default();
-        anotation.bold = Some(true);
-        slf.anotations = Some(anotation);
-        slf
+        Self::new(content).annotated(Annotations {
+            bold : Some(true),
+            ..Default::default()
+        })
     }
     pub fn code(content : &str) -> Self {
-        let mut slf = Self::default();
-        slf.text.content = content.to_owned();
-        let mut anotation = RichTextAnotation::default();
-        anotation.code = Some(true);
-        slf.anotations = Some(anotation);
-        slf
+        Self::new(content).annotated(Annotations {
+            code : Some(true),
+            ..Default::default()
+        })
+    }
+    /// Text with the color or, with the `*_background` colors, highlighted
+    pub fn colored(content : &str, color : Color) -> Self {
+        Self::new(content).annotated(Annotations {
+            color : Some(color),
+            ..Default::default()
+        })
+    }
+    /// Text linking to the url. The content is truncated to the Notion length limit.
+    pub fn link(content : &str, url : &str) -> Self {
+        Self {
+            content : RichTextContent::Text { text : TextValueInternal {
+                content : limits::truncate_text(content, MAX_TEXT_LENGTH),
+                link : Some(Link { url : url.to_owned() })
+            }},
+            ..Default::default()
+        }
+    }
+    /// Mention of a Notion user, who gets notified
+    pub fn user_mention(user_id : &str) -> Self {
+        Self::mention(Mention::User { user : UserValue::new(user_id) })
+    }
+    pub fn page_mention(page_id : &str) -> Self {
+        Self::mention(Mention::Page { page : MentionedObject { id : page_id.to_owned() } })
+    }
+    pub fn database_mention(database_id : &str) -> Self {
+        Self::mention(Mention::Database { database : MentionedObject { id : database_id.to_owned() } })
+    }
+    pub fn date_mention(start : &str) -> Self {
+        Self::mention(Mention::Date { date : DateValueInternal { start : start.to_owned(), ..Default::default() } })
+    }
+    pub fn mention(mention : Mention) -> Self {
+        Self {
+            content : RichTextContent::Mention { mention },
+            ..Default::default()
+        }
+    }
+    pub fn equation(expression : &str) -> Self {
+        Self {
+            content : RichTextContent::Equation { equation : Equation { expression : expression.to_owned() } },
+            ..Default::default()
+        }
+    }
+    /// Replaces the styles of the object
+    pub fn annotated(mut self, annotations : Annotations) -> Self {
+        self.annotations = Some(annotations);
+        self
+    }
+    /// Text shown by Notion: the plain text of responses, else the text content or the equation expression
+    pub fn text(&self) -> &str {
+        if let Some(plain_text) = &self.plain_text {
+            return plain_text;
+        }
+        match &self.content {
+            RichTextContent::Text { text } => &text.content,
+            RichTextContent::Equation { equation } => &equation.expression,
+            RichTextContent::Mention { .. } => ""
+        }
     }
-}
-
-#[derive(Default, Debug, Deserialize, Serialize, Hash)]
-pub struct TextValueInternal {
-    pub content : String
 }
 
 
@@ -275,4 +553,401 @@ pub struct DateProperty {
 
 #[derive(Default, Debug, Deserialize, Serialize, Hash)]
 pub struct DateInternal {
-}
\ No newline at end of file
+}
+#[derive(Default, Debug, Deserialize, Serialize, Hash)]
+pub struct RichTextProperty {
+    pub id : String,
+    pub rich_text : RichTextInternal
+}
+
+#[derive(Default, Debug, Deserialize, Serialize, Hash)]
+pub struct RichTextInternal {
+}
+
+#[derive(Default, Debug, Deserialize, Serialize, Hash)]
+pub struct FilesProperty {
+    pub id : String,
+    pub files : FilesInternal
+}
+
+#[derive(Default, Debug, Deserialize, Serialize, Hash)]
+pub struct FilesInternal {
+}
+
+#[derive(Default, Debug, Deserialize, Serialize, Hash)]
+pub struct CheckboxProperty {
+    pub id : String,
+    pub checkbox : CheckboxInternal
+}
+
+#[derive(Default, Debug, Deserialize, Serialize, Hash)]
+pub struct CheckboxInternal {
+}
+
+#[derive(Default, Debug, Deserialize, Serialize, Hash)]
+pub struct UrlProperty {
+    pub id : String,
+    pub url : UrlInternal
+}
+
+#[derive(Default, Debug, Deserialize, Serialize, Hash)]
+pub struct UrlInternal {
+}
+
+#[derive(Default, Debug, Deserialize, Serialize, Hash)]
+pub struct EmailProperty {
+    pub id : String,
+    pub email : EmailInternal
+}
+
+#[derive(Default, Debug, Deserialize, Serialize, Hash)]
+pub struct EmailInternal {
+}
+
+#[derive(Default, Debug, Deserialize, Serialize, Hash)]
+pub struct PhoneNumberProperty {
+    pub id : String,
+    pub phone_number : PhoneNumberInternal
+}
+
+#[derive(Default, Debug, Deserialize, Serialize, Hash)]
+pub struct PhoneNumberInternal {
+}
+
+#[derive(Default, Debug, Deserialize, Serialize, Hash)]
+pub struct FormulaProperty {
+    pub id : String,
+    pub formula : FormulaInternal
+}
+
+#[derive(Default, Debug, Deserialize, Serialize, Hash)]
+pub struct FormulaInternal {
+    /// Formula as written in Notion, with the properties referenced by id
+    #[serde(default)]
+    pub expression : String
+}
+
+#[derive(Default, Debug, Deserialize, Serialize, Hash)]
+pub struct RelationProperty {
+    pub id : String,
+    pub relation : RelationInternal
+}
+
+#[derive(Default, Debug, Deserialize, Serialize, Hash)]
+pub struct RelationInternal {
+    /// Related database
+    pub database_id : String,
+    #[serde(flatten)]
+    pub relation_type : RelationType
+}
+
+/// A single property relation is only visible in this database. A dual property relation
+/// has a synced property in the related database.
+#[derive(Debug, Deserialize, Serialize, Hash)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum RelationType {
+    SingleProperty {
+        single_property : SinglePropertyRelation
+    },
+    DualProperty {
+        dual_property : DualPropertyRelation
+    }
+}
+
+impl Default for RelationType {
+    fn default() -> Self {
+        RelationType::SingleProperty { single_property : SinglePropertyRelation {} }
+    }
+}
+
+#[derive(Default, Debug, Deserialize, Serialize, Hash)]
+pub struct SinglePropertyRelation {
+}
+
+#[derive(Default, Debug, Deserialize, Serialize, Hash)]
+pub struct DualPropertyRelation {
+    #[serde(default)]
+    pub synced_property_name : String,
+    #[serde(default)]
+    pub synced_property_id : String
+}
+
+#[derive(Default, Debug, Deserialize, Serialize, Hash)]
+pub struct RollupProperty {
+    pub id : String,
+    pub rollup : RollupInternal
+}
+
+#[derive(Default, Debug, Deserialize, Serialize, Hash)]
+pub struct RollupInternal {
+    /// Relation property of this database
+    pub relation_property_name : String,
+    pub relation_property_id : String,
+    /// Property of the related database that is aggregated
+    pub rollup_property_name : String,
+    pub rollup_property_id : String,
+    /// Aggregation function, like "count", "sum" or "show_original"
+    pub function : String
+}
+
+#[derive(Default, Debug, Deserialize, Serialize, Hash)]
+pub struct UniqueIdProperty {
+    pub id : String,
+    pub unique_id : UniqueIdInternal
+}
+
+#[derive(Default, Debug, Deserialize, Serialize, Hash)]
+pub struct UniqueIdInternal {
+    /// Prefix of the ids, like "ALERT" in "ALERT-12"
+    #[serde(default)]
+    pub prefix : Option<String>
+}
+
+#[derive(Default, Debug, Deserialize, Serialize, Hash)]
+pub struct VerificationProperty {
+    pub id : String,
+    pub verification : VerificationInternal
+}
+
+#[derive(Default, Debug, Deserialize, Serialize, Hash)]
+pub struct VerificationInternal {
+}
+
+#[derive(Default, Debug, Deserialize, Serialize, Hash)]
+pub struct ButtonProperty {
+    pub id : String,
+    pub button : ButtonInternal
+}
+
+#[derive(Default, Debug, Deserialize, Serialize, Hash)]
+pub struct ButtonInternal {
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct LastEditedTimeValue {
+    pub last_edited_time : String
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct CreatedByValue {
+    pub created_by : UserValue
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct LastEditedByValue {
+    pub last_edited_by : UserValue
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct PeopleValue {
+    pub people : Vec<UserValue>
+}
+
+/// User as returned in people, created_by and last_edited_by values. Only the id is needed in requests.
+#[derive(Default, Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct UserValue {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub object : Option<String>,
+    pub id : String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name : Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub avatar_url : Option<String>,
+    /// "person" or "bot"
+    #[serde(default, rename = "type", skip_serializing_if = "Option::is_none")]
+    pub user_type : Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub person : Option<PersonValue>
+}
+
+impl UserValue {
+    pub fn new(id : &str) -> Self {
+        Self {
+            id : id.to_owned(),
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct PersonValue {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub email : Option<String>
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct CheckboxValue {
+    pub checkbox : bool
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct UrlValue {
+    pub url : Option<String>
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct EmailValue {
+    pub email : Option<String>
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct PhoneNumberValue {
+    pub phone_number : Option<String>
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct RelationValue {
+    pub relation : Vec<RelationValueInternal>,
+    /// Responses list at most 25 related pages
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub has_more : Option<bool>
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct RelationValueInternal {
+    /// Id of the related page
+    pub id : String
+}
+
+impl RelationValue {
+    pub fn new<I, S>(page_ids : I) -> Self where I : IntoIterator<Item = S>, S : AsRef<str> {
+        Self {
+            relation : page_ids.into_iter().map(|id| RelationValueInternal { id : id.as_ref().to_owned() }).collect(),
+            has_more : None
+        }
+    }
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct FilesValue {
+    pub files : Vec<FileValue>
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct FileValue {
+    pub name : String,
+    #[serde(flatten)]
+    pub file : FileValueInternal
+}
+
+/// Files uploaded to Notion have an url that expires. Only external files can be set by the API.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum FileValueInternal {
+    External {
+        external : ExternalFile
+    },
+    File {
+        file : HostedFile
+    }
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct ExternalFile {
+    pub url : String
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct HostedFile {
+    pub url : String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expiry_time : Option<String>
+}
+
+impl FileValue {
+    pub fn external(name : &str, url : &str) -> Self {
+        Self {
+            name : name.to_owned(),
+            file : FileValueInternal::External { external : ExternalFile { url : url.to_owned() } }
+        }
+    }
+}
+
+/// Result of a formula. Formulas cannot be set by the API.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct FormulaValue {
+    pub formula : FormulaResult
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum FormulaResult {
+    String {
+        string : Option<String>
+    },
+    Number {
+        number : Option<f64>
+    },
+    Boolean {
+        boolean : Option<bool>
+    },
+    Date {
+        date : Option<DateValueInternal>
+    }
+}
+
+/// Result of a rollup. Rollups cannot be set by the API.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct RollupValue {
+    pub rollup : RollupResult
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct RollupResult {
+    #[serde(flatten)]
+    pub result : RollupResultValue,
+    /// Aggregation function of the rollup
+    pub function : String
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum RollupResultValue {
+    Number {
+        number : Option<f64>
+    },
+    Date {
+        date : Option<DateValueInternal>
+    },
+    /// Values of the related pages, for functions like "show_original"
+    Array {
+        array : Vec<crate::api::page::PropertyValue>
+    },
+    Unsupported {},
+    Incomplete {}
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct UniqueIdValue {
+    pub unique_id : UniqueIdValueInternal
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct UniqueIdValueInternal {
+    pub number : Option<u64>,
+    pub prefix : Option<String>
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct VerificationValue {
+    pub verification : Option<VerificationValueInternal>
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct VerificationValueInternal {
+    /// "verified", "unverified" or "expired"
+    pub state : String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub verified_by : Option<UserValue>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub date : Option<DateValueInternal>
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct ButtonValue {
+    pub button : ButtonValueInternal
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct ButtonValueInternal {
+}
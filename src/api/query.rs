@@ -0,0 +1,272 @@
+use serde::{Deserialize, Serialize};
+
+/// Body of `POST /v1/databases/{database_id}/query`
+#[derive(Default, Debug, Clone, Serialize)]
+pub struct DatabaseQuery {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub filter : Option<Filter>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub sorts : Vec<Sort>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start_cursor : Option<String>,
+    /// Results per request, at most 100
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub page_size : Option<u32>,
+}
+
+impl DatabaseQuery {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn filter(mut self, filter : Filter) -> Self {
+        self.filter = Some(filter);
+        self
+    }
+    pub fn sort(mut self, sort : Sort) -> Self {
+        self.sorts.push(sort);
+        self
+    }
+    pub fn page_size(mut self, page_size : u32) -> Self {
+        self.page_size = Some(page_size);
+        self
+    }
+}
+
+/// Paginated response of the Notion API
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(tag = "object", rename = "list")]
+pub struct PaginatedList<T> {
+    pub results : Vec<T>,
+    #[serde(default)]
+    pub next_cursor : Option<String>,
+    #[serde(default)]
+    pub has_more : bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(untagged)]
+pub enum Filter {
+    Property(PropertyFilter),
+    Timestamp(TimestampFilter),
+    And { and : Vec<Filter> },
+    Or { or : Vec<Filter> },
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PropertyFilter {
+    /// Name or id of the property
+    pub property : String,
+    #[serde(flatten)]
+    pub condition : PropertyCondition,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PropertyCondition {
+    Title(TextCondition),
+    RichText(TextCondition),
+    Number(NumberCondition),
+    Checkbox(CheckboxCondition),
+    Select(SelectCondition),
+    MultiSelect(MultiSelectCondition),
+    Status(SelectCondition),
+    Date(DateCondition),
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TimestampFilter {
+    pub timestamp : Timestamp,
+    #[serde(flatten)]
+    pub condition : TimestampCondition,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TimestampCondition {
+    CreatedTime(DateCondition),
+    LastEditedTime(DateCondition),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Timestamp {
+    CreatedTime,
+    LastEditedTime,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TextCondition {
+    Equals(String),
+    DoesNotEqual(String),
+    Contains(String),
+    DoesNotContain(String),
+    StartsWith(String),
+    EndsWith(String),
+    IsEmpty(bool),
+    IsNotEmpty(bool),
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NumberCondition {
+    Equals(f64),
+    DoesNotEqual(f64),
+    GreaterThan(f64),
+    LessThan(f64),
+    GreaterThanOrEqualTo(f64),
+    LessThanOrEqualTo(f64),
+    IsEmpty(bool),
+    IsNotEmpty(bool),
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckboxCondition {
+    Equals(bool),
+    DoesNotEqual(bool),
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SelectCondition {
+    Equals(String),
+    DoesNotEqual(String),
+    IsEmpty(bool),
+    IsNotEmpty(bool),
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MultiSelectCondition {
+    Contains(String),
+    DoesNotContain(String),
+    IsEmpty(bool),
+    IsNotEmpty(bool),
+}
+
+/// Dates in ISO 8601 format
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DateCondition {
+    Equals(String),
+    Before(String),
+    After(String),
+    OnOrBefore(String),
+    OnOrAfter(String),
+    IsEmpty(bool),
+    IsNotEmpty(bool),
+}
+
+impl Filter {
+    pub fn property(property : &str, condition : PropertyCondition) -> Self {
+        Filter::Property(PropertyFilter {
+            property : property.to_owned(),
+            condition
+        })
+    }
+    pub fn select_equals(property : &str, value : &str) -> Self {
+        Self::property(property, PropertyCondition::Select(SelectCondition::Equals(value.to_owned())))
+    }
+    pub fn multi_select_contains(property : &str, value : &str) -> Self {
+        Self::property(property, PropertyCondition::MultiSelect(MultiSelectCondition::Contains(value.to_owned())))
+    }
+    pub fn status_equals(property : &str, value : &str) -> Self {
+        Self::property(property, PropertyCondition::Status(SelectCondition::Equals(value.to_owned())))
+    }
+    pub fn status_does_not_equal(property : &str, value : &str) -> Self {
+        Self::property(property, PropertyCondition::Status(SelectCondition::DoesNotEqual(value.to_owned())))
+    }
+    pub fn date_after(property : &str, date : &str) -> Self {
+        Self::property(property, PropertyCondition::Date(DateCondition::After(date.to_owned())))
+    }
+    pub fn date_before(property : &str, date : &str) -> Self {
+        Self::property(property, PropertyCondition::Date(DateCondition::Before(date.to_owned())))
+    }
+    pub fn title_equals(property : &str, value : &str) -> Self {
+        Self::property(property, PropertyCondition::Title(TextCondition::Equals(value.to_owned())))
+    }
+    pub fn rich_text_equals(property : &str, value : &str) -> Self {
+        Self::property(property, PropertyCondition::RichText(TextCondition::Equals(value.to_owned())))
+    }
+    pub fn created_after(date : &str) -> Self {
+        Filter::Timestamp(TimestampFilter {
+            timestamp : Timestamp::CreatedTime,
+            condition : TimestampCondition::CreatedTime(DateCondition::After(date.to_owned()))
+        })
+    }
+    pub fn and(filters : Vec<Filter>) -> Self {
+        Filter::And { and : filters }
+    }
+    pub fn or(filters : Vec<Filter>) -> Self {
+        Filter::Or { or : filters }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(untagged)]
+pub enum Sort {
+    Property { property : String, direction : SortDirection },
+    Timestamp { timestamp : Timestamp, direction : SortDirection },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortDirection {
+    Ascending,
+    Descending,
+}
+
+impl Sort {
+    pub fn ascending(property : &str) -> Self {
+        Sort::Property { property : property.to_owned(), direction : SortDirection::Ascending }
+    }
+    pub fn descending(property : &str) -> Self {
+        Sort::Property { property : property.to_owned(), direction : SortDirection::Descending }
+    }
+    pub fn created_time(direction : SortDirection) -> Self {
+        Sort::Timestamp { timestamp : Timestamp::CreatedTime, direction }
+    }
+}
+
+#[cfg(test)]
+mod serialization {
+    use super::*;
+    use usiem::serde_json::{self, json};
+
+    #[test]
+    fn should_serialize_filters() {
+        let query = DatabaseQuery::new().filter(Filter::and(vec![
+            Filter::select_equals("Priority", "High"),
+            Filter::multi_select_contains("Tags", "bruteforce"),
+            Filter::or(vec![
+                Filter::status_equals("Status", "Not started"),
+                Filter::date_after("Fired", "2022-10-01T00:00:00.000Z"),
+            ]),
+            Filter::created_after("2022-09-01T00:00:00.000Z"),
+        ])).sort(Sort::descending("Fired")).sort(Sort::created_time(SortDirection::Ascending)).page_size(10);
+        assert_eq!(json!({
+            "filter": {"and": [
+                {"property": "Priority", "select": {"equals": "High"}},
+                {"property": "Tags", "multi_select": {"contains": "bruteforce"}},
+                {"or": [
+                    {"property": "Status", "status": {"equals": "Not started"}},
+                    {"property": "Fired", "date": {"after": "2022-10-01T00:00:00.000Z"}}
+                ]},
+                {"timestamp": "created_time", "created_time": {"after": "2022-09-01T00:00:00.000Z"}}
+            ]},
+            "sorts": [
+                {"property": "Fired", "direction": "descending"},
+                {"timestamp": "created_time", "direction": "ascending"}
+            ],
+            "page_size": 10
+        }), serde_json::to_value(&query).unwrap());
+    }
+
+    #[test]
+    fn should_serialize_empty_query() {
+        assert_eq!(json!({}), serde_json::to_value(DatabaseQuery::new()).unwrap());
+        let query = DatabaseQuery::new().filter(Filter::property("Resolved", PropertyCondition::Checkbox(CheckboxCondition::Equals(true))));
+        assert_eq!(json!({"filter": {"property": "Resolved", "checkbox": {"equals": true}}}), serde_json::to_value(&query).unwrap());
+    }
+}
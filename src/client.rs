@@ -1,140 +1,451 @@
-
 use std::collections::BTreeMap;
 use usiem::chrono::LocalResult;
 use usiem::chrono::prelude::{TimeZone, Utc};
 use reqwest::header::{HeaderValue};
-use reqwest::blocking::{Client, ClientBuilder};
+use reqwest::blocking::{Client, ClientBuilder, RequestBuilder, Response};
 use usiem::prelude::alert::{SiemAlert, AlertSeverity};
 
 use crate::api::block::*;
 use crate::api::database::*;
 use crate::api::database::properties::*;
 use crate::api::page::*;
+use crate::api::query::*;
+use crate::mapping::{AlertField, PropertyMapping};
+use crate::retry::RetryPolicy;
+use crate::schema::{SchemaMigration, SchemaReport, PRIORITY_OPTIONS, STATUS_OPTIONS};
+use crate::template::PageTemplate;
+use crate::aggregation::is_aggregable;
+pub use crate::error::{IncompletePage, NotionError, NotionErrorCode, NotionApiError, NotionResult};
+
+pub const NOTION_API_URL : &str = "https://api.notion.com/v1";
+pub const NOTION_VERSION : &str = "2022-06-28";
 
+pub struct NotionClientBuilder {
+    api_key : String,
+    database_id : String,
+    base_url : String,
+    retry : RetryPolicy,
+    mapping : PropertyMapping
+}
 
+impl NotionClientBuilder {
+    pub fn new(api_key : &str, database_id : &str) -> Self {
+        Self {
+            api_key : api_key.to_owned(),
+            database_id : database_id.to_owned(),
+            base_url : NOTION_API_URL.to_owned(),
+            retry : RetryPolicy::default(),
+            mapping : PropertyMapping::default()
+        }
+    }
 
-pub type NotionResult<T> = Result<T, NotionError>;
+    /// Overrides the Notion API endpoint (ex: a proxy or a mock server). Defaults to `https://api.notion.com/v1`
+    pub fn base_url(mut self, base_url : &str) -> Self {
+        self.base_url = base_url.trim_end_matches('/').to_owned();
+        self
+    }
 
-#[derive(Debug)]
-pub enum NotionError {
-    Connection(reqwest::Error),
-    Serialization(usiem::serde_json::Error),
-    Server(String)
-}
+    pub fn retry_policy(mut self, retry : RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
 
-impl From<reqwest::Error> for NotionError {
-    fn from(e: reqwest::Error) -> Self {
-        NotionError::Connection(e)
+    /// Properties of the database where the alert fields are stored
+    pub fn property_mapping(mut self, mapping : PropertyMapping) -> Self {
+        self.mapping = mapping;
+        self
     }
-}
 
-impl From<usiem::serde_json::Error> for NotionError {
-    fn from(e: usiem::serde_json::Error) -> Self {
-        NotionError::Serialization(e)
+    /// Fails if the API key cannot be sent in a header
+    pub fn build(self) -> NotionResult<NotionClient> {
+        let mut headers = reqwest::header::HeaderMap::new();
+        let bearer_key = format!("Bearer {}", self.api_key);
+        headers.insert("Authorization", HeaderValue::from_str(&bearer_key).map_err(|_| NotionError::InvalidApiKey)?);
+        headers.insert("Notion-Version", HeaderValue::from_static(NOTION_VERSION));
+        headers.insert("Content-Type", HeaderValue::from_static("application/json"));
+        let client = ClientBuilder::new().default_headers(headers).build().map_err(NotionError::Connection)?;
+        Ok(NotionClient {
+            database_id : self.database_id,
+            base_url : self.base_url,
+            retry : self.retry,
+            properties : self.mapping.clone(),
+            mapping : self.mapping,
+            aggregation_properties : false,
+            status_select : false,
+            client
+        })
     }
 }
 
 pub struct NotionClient {
     database_id : String,
+    base_url : String,
+    retry : RetryPolicy,
+    /// Configured properties, by name or id
+    mapping : PropertyMapping,
+    /// Names of the properties once resolved with the database definition
+    properties : PropertyMapping,
+    /// The database has the optional Occurrences and Last seen properties
+    aggregation_properties : bool,
+    /// Status is a select property
+    status_select : bool,
     client : Client
 }
 
 impl NotionClient {
-    pub fn new(api_key : &str, database_id : &str) -> Self {
-        let mut headers = reqwest::header::HeaderMap::new();
-        let bearer_key = format!("Bearer {}", api_key);
-        headers.insert("Authorization", HeaderValue::from_str(&bearer_key).unwrap());
-        headers.insert("Notion-Version", HeaderValue::from_static("2022-06-28"));
-        headers.insert("Content-Type", HeaderValue::from_static("application/json"));
-        let client = ClientBuilder::new().default_headers(headers).build().unwrap();
-        Self {
-            database_id : database_id.to_owned(),
-            client
+    pub fn new(api_key : &str, database_id : &str) -> NotionResult<Self> {
+        NotionClientBuilder::new(api_key, database_id).build()
+    }
+
+    pub fn builder(api_key : &str, database_id : &str) -> NotionClientBuilder {
+        NotionClientBuilder::new(api_key, database_id)
+    }
+
+    /// Name of the property where the field is stored
+    pub fn property(&self, field : AlertField) -> &str {
+        self.properties.property(field)
+    }
+
+    fn url(&self, path : &str) -> String {
+        format!("{}/{}", self.base_url, path)
+    }
+
+    /// Sends the request retrying it as stated in the RetryPolicy. Unsuccessful responses are returned as errors.
+    fn send(&self, request : RequestBuilder) -> NotionResult<Response> {
+        let mut attempt = 0;
+        loop {
+            // Requests with a JSON body or without body can always be cloned
+            let current = request.try_clone().expect("Notion requests must be clonable");
+            let error = match current.send() {
+                Ok(response) if response.status().is_success() => return Ok(response),
+                Ok(response) => NotionError::from_response(response),
+                Err(e) => NotionError::Connection(e)
+            };
+            let wait = match self.retry.delay_for(&error, attempt) {
+                Some(wait) => wait,
+                None => return Err(error)
+            };
+            std::thread::sleep(wait);
+            attempt += 1;
         }
     }
 
-    pub fn check_valid_siem_database(&self) -> NotionResult<bool>{
-        let response = self.client.get(&format!("https://api.notion.com/v1/databases/{}",self.database_id)).send()?;
-        let response = response.error_for_status()?;
+    /// Checks the database has the properties needed by the alerter and detects the optional ones
+    pub fn check_valid_siem_database(&mut self) -> NotionResult<SchemaReport>{
+        let (database_obj, body) = self.fetch_database()?;
+        self.apply_schema(&database_obj, &body)
+    }
+
+    /// Creates a database for the alerts in the page and uses it for the following requests. It has the
+    /// properties of the mapping, including the optional Occurrences and Last seen. The integration must have
+    /// access to the page.
+    ///
+    /// The Notion API cannot create status properties, so Status is created as a select.
+    pub fn create_siem_database(&mut self, parent_page_id : &str, title : &str) -> NotionResult<DatabaseDefinition> {
+        let database = DatabaseCreate::new(parent_page_id, title)
+            .property(self.mapping.property(AlertField::Name), PropertySchema::Title {})
+            .property(self.mapping.property(AlertField::Priority), PropertySchema::select(&PRIORITY_OPTIONS))
+            .property(self.mapping.property(AlertField::Mitre), PropertySchema::multi_select())
+            .property(self.mapping.property(AlertField::Tags), PropertySchema::multi_select())
+            .property(self.mapping.property(AlertField::Status), PropertySchema::select(&STATUS_OPTIONS))
+            .property(self.mapping.property(AlertField::Fired), PropertySchema::Date {})
+            .property(self.mapping.property(AlertField::Occurrences), PropertySchema::number())
+            .property(self.mapping.property(AlertField::LastSeen), PropertySchema::Date {});
+        let response = self.send(self.client.post(self.url("databases")).json(&database))?;
+        let body = response.text()?;
+        let database_obj : DatabaseDefinition = usiem::serde_json::from_str(&body)?;
+        self.database_id = database_obj.id.clone();
+        self.apply_schema(&database_obj, &body)?;
+        Ok(database_obj)
+    }
+
+    /// Adds the missing properties and select options to the database, without deleting anything.
+    /// With `dry_run` the planned changes are returned but not applied.
+    pub fn migrate_siem_database(&mut self, dry_run : bool) -> NotionResult<SchemaMigration> {
+        let (database_obj, body) = self.fetch_database()?;
+        let report = self.apply_schema(&database_obj, &body)?;
+        let migration = SchemaMigration::new(&database_obj, &report);
+        if dry_run || migration.is_empty() {
+            return Ok(migration);
+        }
+        let response = self.send(self.client.patch(self.url(&format!("databases/{}", self.database_id))).json(&migration.update()))?;
         let body = response.text()?;
         let database_obj : DatabaseDefinition = usiem::serde_json::from_str(&body)?;
-        Ok(Self::check_properties(&database_obj))
+        self.apply_schema(&database_obj, &body)?;
+        Ok(migration)
+    }
+
+    pub fn retrieve_database(&self) -> NotionResult<DatabaseDefinition> {
+        Ok(self.fetch_database()?.0)
+    }
+
+    /// Database definition and the response body, that also has the property ids
+    fn fetch_database(&self) -> NotionResult<(DatabaseDefinition, String)> {
+        let response = self.send(self.client.get(self.url(&format!("databases/{}", self.database_id))))?;
+        let body = response.text()?;
+        let database_obj : DatabaseDefinition = usiem::serde_json::from_str(&body)?;
+        Ok((database_obj, body))
+    }
+
+    /// Id of the database where the alerts are stored
+    pub fn database_id(&self) -> &str {
+        &self.database_id
+    }
+
+    fn apply_schema(&mut self, database : &DatabaseDefinition, body : &str) -> NotionResult<SchemaReport> {
+        let property_ids : DatabasePropertyIds = usiem::serde_json::from_str(body)?;
+        self.properties = self.mapping.resolve(&property_ids);
+        let report = SchemaReport::new(database, &self.properties);
+        self.aggregation_properties = report.aggregation_properties;
+        self.status_select = report.status_select;
+        Ok(report)
+    }
+
+    /// Creates a page for the alert in the SIEM database
+    pub fn send_alert(&self, alert : &SiemAlert) -> NotionResult<Page>{
+        self.create_page(self.alert_page(alert))
+    }
+
+    /// Creates a page for the alert with the layout of the template
+    pub fn send_alert_with_template(&self, alert : &SiemAlert, template : &PageTemplate) -> NotionResult<Page>{
+        self.create_page(self.alert_page_with_template(alert, template))
+    }
+
+    /// Page of the database that represents the alert
+    pub fn alert_page(&self, alert : &SiemAlert) -> PageElement {
+        self.alert_page_with_template(alert, &PageTemplate::default())
     }
 
-    pub fn send_alert(&self, alert : &SiemAlert) -> NotionResult<()>{
+    /// Page of the alert with the properties and blocks of the template. The properties of the template
+    /// are set after the alert fields, so they can replace them.
+    pub fn alert_page_with_template(&self, alert : &SiemAlert, template : &PageTemplate) -> PageElement {
         let mut properties = BTreeMap::new();
-        properties.insert("Name".to_owned(), PropertyValue::Title(TitleValue::new(&alert.title)));
-        properties.insert("Priority".to_owned(), PropertyValue::Select(SelectValue{
-            select : SelectValueInternal {
-                name : alert_severity(&alert.severity)
+        properties.insert(self.property(AlertField::Name).to_owned(), PropertyValue::Title(TitleValue::new(&alert.title)));
+        properties.insert(self.property(AlertField::Priority).to_owned(), PropertyValue::Select(SelectValue::new(&alert_severity(&alert.severity))));
+        properties.insert(self.property(AlertField::Mitre).to_owned(), PropertyValue::MultiSelect(MultiSelectValue::new(alert.techniques.iter().map(|v| format!("{:?}",v)))));
+        properties.insert(self.property(AlertField::Tags).to_owned(), PropertyValue::MultiSelect(MultiSelectValue::new(&alert.tags)));
+        properties.insert(self.property(AlertField::Fired).to_owned(), PropertyValue::Date(DateValue::new(alert_date(alert))));
+        if self.status_select {
+            properties.insert(self.property(AlertField::Status).to_owned(), PropertyValue::Select(SelectValue::new(STATUS_OPTIONS[0].0)));
+        }
+        if self.aggregation_properties && is_aggregable(alert) {
+            properties.insert(self.property(AlertField::Occurrences).to_owned(), PropertyValue::Number(NumberValue::new(1.0)));
+            properties.insert(self.property(AlertField::LastSeen).to_owned(), PropertyValue::Date(DateValue::new(alert_date(alert))));
+        }
+        properties.extend(template.properties(alert));
+        // Template children can nest Markdown blocks deeper than Notion accepts
+        PageElement { parent: DatabaseParent {
+            database_id : self.database_id.clone()
+        }, properties, children : markdown::limit_nesting(template.blocks(alert), 0)}
+    }
+
+    /// Adds a new occurrence of an alert to its open page: appends the log and, if the database has them,
+    /// updates the Occurrences and Last seen properties
+    pub fn aggregate_alert(&self, page_id : &str, alert : &SiemAlert, occurrences : u64) -> NotionResult<()> {
+        for batch in batch_blocks(self.occurrence_blocks(alert, occurrences), CHILDREN_OVERHEAD) {
+            self.append_block_children(page_id, batch)?;
+        }
+        self.update_occurrences(page_id, &alert_date(alert), occurrences)
+    }
+
+    /// Blocks appended to the open page of an alert for each new occurrence
+    pub fn occurrence_blocks(&self, alert : &SiemAlert, occurrences : u64) -> Vec<BlockElement> {
+        let mut children = vec![BlockElement::heading_three(&format!("Occurrence {} - {}", occurrences, alert_date(alert)))];
+        children.extend(BlockElement::code_blocks(alert.log.message(), None));
+        children
+    }
+
+    /// Updates the Occurrences and Last seen properties, if the database has them
+    pub fn update_occurrences(&self, page_id : &str, last_seen : &str, occurrences : u64) -> NotionResult<()> {
+        if self.aggregation_properties {
+            self.update_page(page_id, &PageUpdate::new()
+                .property(self.property(AlertField::Occurrences), PropertyValue::Number(NumberValue::new(occurrences as f64)))
+                .property(self.property(AlertField::LastSeen), PropertyValue::Date(DateValue::new(last_seen.to_owned()))))?;
+        }
+        Ok(())
+    }
+
+    /// Creates the page with the first blocks that fit in the request and appends the rest in ordered batches.
+    /// If a batch fails, the error is `NotionError::Incomplete` with the blocks that were not appended.
+    pub fn create_page(&self, mut page : PageElement) -> NotionResult<Page> {
+        let batches = page.split_children();
+        let response = self.send(self.client.post(self.url("pages")).json(&page))?;
+        let created : Page = usiem::serde_json::from_str(&response.text()?)?;
+        self.append_batches(&created.id, &created.url, batches)?;
+        Ok(created)
+    }
+
+    /// Appends the blocks to the page in ordered batches, like the blocks that `create_page` could not append.
+    /// If a batch fails, the error is `NotionError::Incomplete` with the blocks that were not appended.
+    pub fn append_blocks(&self, page_id : &str, url : &str, blocks : Vec<BlockElement>) -> NotionResult<()> {
+        self.append_batches(page_id, url, batch_blocks(blocks, CHILDREN_OVERHEAD))
+    }
+
+    fn append_batches(&self, page_id : &str, url : &str, batches : Vec<Vec<BlockElement>>) -> NotionResult<()> {
+        let mut batches = batches.into_iter();
+        while let Some(children) = batches.next() {
+            let body = AppendBlockChildren { children };
+            if let Err(error) = self.send(self.client.patch(self.url(&format!("blocks/{}/children", page_id))).json(&body)) {
+                return Err(NotionError::Incomplete(Box::new(IncompletePage {
+                    page_id : page_id.to_owned(),
+                    url : url.to_owned(),
+                    remaining : body.children.into_iter().chain(batches.flatten()).collect(),
+                    error
+                })));
             }
-        }));
-        
-        properties.insert("MITRE".to_owned(), PropertyValue::MultiSelect(MultiSelectValue {
-            multi_select : alert.techniques.iter().map(|v| {
-                MultiSelectValueInternal {
-                    name : format!("{:?}",v)
-                }
-            }).collect()
-        }));
-        properties.insert("Tags".to_owned(), PropertyValue::MultiSelect(MultiSelectValue {
-            multi_select : alert.tags.iter().map(|v| {
-                MultiSelectValueInternal {
-                    name : v.to_string()
-                }
-            }).collect()
-        }));
-        let fired = match Utc.timestamp_millis_opt(alert.date) {
-            LocalResult::Single(v) => v,
-            _ => Utc::now()
+        }
+        Ok(())
+    }
+
+    pub fn retrieve_page(&self, page_id : &str) -> NotionResult<Page> {
+        let response = self.send(self.client.get(self.url(&format!("pages/{}", page_id))))?;
+        Ok(usiem::serde_json::from_str(&response.text()?)?)
+    }
+
+    /// Updates the given properties of a page, and archives or restores it
+    pub fn update_page(&self, page_id : &str, update : &PageUpdate) -> NotionResult<Page> {
+        let response = self.send(self.client.patch(self.url(&format!("pages/{}", page_id))).json(update))?;
+        Ok(usiem::serde_json::from_str(&response.text()?)?)
+    }
+
+    pub fn archive_page(&self, page_id : &str) -> NotionResult<Page> {
+        self.update_page(page_id, &PageUpdate::new().archived(true))
+    }
+
+    pub fn unarchive_page(&self, page_id : &str) -> NotionResult<Page> {
+        self.update_page(page_id, &PageUpdate::new().archived(false))
+    }
+
+    /// Changes the Status of an alert, ex: "Done" to close it
+    pub fn set_alert_status(&self, page_id : &str, status : &str) -> NotionResult<Page> {
+        let value = if self.status_select {
+            PropertyValue::Select(SelectValue::new(status))
+        } else {
+            PropertyValue::Status(StatusValue::new(status))
         };
-        let fired = format!("{:?}",fired);
-        properties.insert("Fired".to_owned(), PropertyValue::Date(DateValue::new(fired)));
+        self.update_page(page_id, &PageUpdate::new().property(self.property(AlertField::Status), value))
+    }
 
-        let children = vec![
-            BlockElement::HeadingOne(RichTextValue {
-                rich_text: vec![RichTextObject::new(&alert.title)]
-            }),
-            BlockElement::HeadingThree(RichTextValue {
-                rich_text: vec![RichTextObject::new(&alert.rule)]
-            }),
-            BlockElement::Paragraph(RichTextValue {
-                rich_text: vec![RichTextObject::new(&alert.description)]
-            }),
-            BlockElement::code(alert.log.message(), None),
-            BlockElement::code_owned(usiem::serde_json::to_string_pretty(&alert.log).unwrap_or("Cannot show the log".to_owned()), Some(format!("json")))
-        ];
-        let new_page : PageElement = PageElement { parent: DatabaseParent {
-            database_id : self.database_id.clone()
-        }, properties, children};
-        let response = self.client.post("https://api.notion.com/v1/pages").json(&new_page).send()?;
-        if !response.status().is_success() {
-            return Err(NotionError::Server(response.text()?))
+    pub fn set_alert_priority(&self, page_id : &str, severity : &AlertSeverity) -> NotionResult<Page> {
+        self.update_page(page_id, &PageUpdate::new().property(self.property(AlertField::Priority), PropertyValue::Select(SelectValue::new(&alert_severity(severity)))))
+    }
+
+    /// Adds tags to the ones already in the alert
+    pub fn add_alert_tags(&self, page_id : &str, tags : &[String]) -> NotionResult<Page> {
+        let page = self.retrieve_page(page_id)?;
+        let mut names : Vec<String> = match page.property_value(self.property(AlertField::Tags)) {
+            Some(PropertyValue::MultiSelect(tags)) => tags.multi_select.into_iter().map(|v| v.name).collect(),
+            _ => Vec::new()
+        };
+        names.extend(tags.iter().cloned());
+        self.update_page(page_id, &PageUpdate::new().property(self.property(AlertField::Tags), PropertyValue::MultiSelect(MultiSelectValue::new(&names))))
+    }
+
+    /// Single request of a database query, use `query_database` to iterate over all the results
+    pub fn query_database_page(&self, query : &DatabaseQuery) -> NotionResult<PaginatedList<Page>> {
+        let response = self.send(self.client.post(self.url(&format!("databases/{}/query", self.database_id))).json(query))?;
+        Ok(usiem::serde_json::from_str(&response.text()?)?)
+    }
+
+    /// Iterates over the pages of the database that match the query, requesting the next results as needed
+    pub fn query_database(&self, query : DatabaseQuery) -> QueryPages<'_> {
+        QueryPages {
+            client : self,
+            query,
+            buffer : Vec::new().into_iter(),
+            finished : false
+        }
+    }
+
+    /// Page with its blocks, see `retrieve_block_children`
+    pub fn retrieve_page_content(&self, page_id : &str) -> NotionResult<PageContent> {
+        let page = self.retrieve_page(page_id)?;
+        let blocks = self.retrieve_block_children(page_id)?;
+        Ok(PageContent { page, blocks })
+    }
+
+    /// Single request of the children of a page or block, use `retrieve_block_children` to get all of them
+    pub fn retrieve_block_children_page(&self, block_id : &str, start_cursor : Option<&str>) -> NotionResult<PaginatedList<BlockObject>> {
+        let mut request = self.client.get(self.url(&format!("blocks/{}/children", block_id))).query(&[("page_size", "100")]);
+        if let Some(cursor) = start_cursor {
+            request = request.query(&[("start_cursor", cursor)]);
+        }
+        let response = self.send(request)?;
+        Ok(usiem::serde_json::from_str(&response.text()?)?)
+    }
+
+    /// All the children of a page or block, with their nested blocks. The content of child pages and
+    /// databases is not retrieved.
+    pub fn retrieve_block_children(&self, block_id : &str) -> NotionResult<Vec<BlockObject>> {
+        let mut blocks = Vec::new();
+        let mut cursor : Option<String> = None;
+        loop {
+            let list = self.retrieve_block_children_page(block_id, cursor.as_deref())?;
+            blocks.extend(list.results);
+            match list.next_cursor {
+                Some(next) if list.has_more => cursor = Some(next),
+                _ => break
+            }
+        }
+        for block in blocks.iter_mut() {
+            if block.has_children && !matches!(block.block.type_name(), "child_page" | "child_database") {
+                block.children = self.retrieve_block_children(&block.id)?;
+            }
         }
+        Ok(blocks)
+    }
+
+    /// Appends blocks to a page or block. At most MAX_CHILDREN blocks per call.
+    pub fn append_block_children(&self, block_id : &str, children : Vec<BlockElement>) -> NotionResult<()> {
+        let body = AppendBlockChildren { children };
+        self.send(self.client.patch(self.url(&format!("blocks/{}/children", block_id))).json(&body))?;
         Ok(())
     }
 
-    fn check_properties(properties : &DatabaseDefinition) -> bool {
-        let name = properties.properties.get("Name");
-        let priority = properties.properties.get("Priority");
-        let mitre = properties.properties.get("MITRE");
-        let tags = properties.properties.get("Tags");
-        let status = properties.properties.get("Status");
-        let fired = properties.properties.get("Fired");
+}
 
-        if let (Some(name), Some(priority),Some(mitre),Some(tags),Some(status), Some(fired)) = (name, priority, mitre, tags, status, fired) {
-            if let (PropertyDefinition::Title(_),PropertyDefinition::Select(_),PropertyDefinition::MultiSelect(_),PropertyDefinition::MultiSelect(_),PropertyDefinition::Status(_), PropertyDefinition::Date(_)) = (name, priority, mitre, tags, status, fired) {
-                return true;
-            }else {
-                return false
+pub struct QueryPages<'a> {
+    client : &'a NotionClient,
+    query : DatabaseQuery,
+    buffer : std::vec::IntoIter<Page>,
+    finished : bool
+}
+
+impl<'a> Iterator for QueryPages<'a> {
+    type Item = NotionResult<Page>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(page) = self.buffer.next() {
+                return Some(Ok(page))
+            }
+            if self.finished {
+                return None
+            }
+            match self.client.query_database_page(&self.query) {
+                Ok(list) => {
+                    self.finished = !list.has_more || list.next_cursor.is_none();
+                    self.query.start_cursor = list.next_cursor;
+                    self.buffer = list.results.into_iter();
+                },
+                Err(e) => {
+                    self.finished = true;
+                    return Some(Err(e))
+                }
             }
-        }else{
-            return false;
         }
     }
 }
 
-fn alert_severity(severity : &AlertSeverity) -> String {
+pub(crate) fn alert_date(alert : &SiemAlert) -> String {
+    let date = match Utc.timestamp_millis_opt(alert.date) {
+        LocalResult::Single(v) => v,
+        _ => Utc::now()
+    };
+    format!("{:?}", date)
+}
+
+pub(crate) fn alert_severity(severity : &AlertSeverity) -> String {
     match severity {
         AlertSeverity::INFORMATIONAL => "Informational".to_string(),
         AlertSeverity::LOW => "Low".to_string(),
@@ -145,45 +456,24 @@ fn alert_severity(severity : &AlertSeverity) -> String {
 }
 
 #[cfg(test)]
-mod client {
-    use std::{borrow::Cow, time::UNIX_EPOCH};
-    use reqwest::header::{HeaderValue};
+mod api_calls {
+    use std::borrow::Cow;
     use usiem::prelude::{alert::{SiemAlert, AlertSeverity}, mitre::MitreTechniques, SiemLog, SiemEvent, auth::{AuthEvent, AuthLoginType, LoginOutcome, RemoteLogin}};
-    use crate::api::{database::{DatabaseDefinition}};
+    use std::time::{Duration, Instant};
+    use crate::mock::{MockNotionServer, MockResponse};
+    use crate::retry::RetryPolicy;
+    use std::collections::BTreeMap;
+    use crate::api::{block::{BlockElement, Icon}, page::{PageElement, DatabaseParent, PropertyValue}, database::properties::TitleValue};
+    use crate::api::query::{DatabaseQuery, Filter, Sort, SortDirection};
+    use crate::api::page::{Page, PageUpdate, Parent};
+    use usiem::serde_json::json;
+    use crate::mapping::{AlertField, PropertyMapping};
+    use super::{NotionClient, NotionError, NotionErrorCode};
 
-    #[test]
-    fn test_connection(){
-        let db_id : String = match std::env::var("USIEM_NOTION_DB") {
-            Ok(v) => v,
-            Err(_) => return
-        };
-        let api_key = std::env::var("USIEM_NOTION_APIKEY").expect("USIEM_NOTION_DB is defined but not USIEM_NOTION_APIKEY");
-        
-        let api_key = format!("Bearer {}", api_key);
-        let mut headers = reqwest::header::HeaderMap::new();
-        headers.insert("Authorization", HeaderValue::from_str(&api_key).unwrap());
-        headers.insert("Notion-Version", HeaderValue::from_static("2022-06-28"));
-        let client = reqwest::blocking::ClientBuilder::new().default_headers(headers).build().unwrap();
-        let response = client.get(&format!("https://api.notion.com/v1/databases/{}",db_id)).send().unwrap();
-        assert!(response.status().is_success());
-        let body = response.text().unwrap();
-        let database_obj : DatabaseDefinition = usiem::serde_json::from_str(&body).unwrap();
-    
-        assert!(database_obj.properties.get("Name").is_some());
-        assert!(database_obj.properties.get("Priority").is_some());
-        assert!(database_obj.properties.get("MITRE").is_some());
-        assert!(database_obj.properties.get("Tags").is_some());
-        assert!(database_obj.properties.get("Status").is_some());
-    }
+    const API_KEY : &str = "secret_test";
+    const DATABASE_ID : &str = "d9824bdc-8445-4327-be8b-5b47500af6ce";
 
-    #[test]
-    fn test_client_alert() {
-        let db_id : String = match std::env::var("USIEM_NOTION_DB") {
-            Ok(v) => v,
-            Err(_) => return
-        };
-        let api_key = std::env::var("USIEM_NOTION_APIKEY").expect("USIEM_NOTION_DB is defined but not USIEM_NOTION_APIKEY");
-        let client = super::NotionClient::new(&api_key, &db_id);
+    fn test_alert() -> SiemAlert {
         let mut log = SiemLog::new(String::from("This is a log example"), 0, "localhost");
         log.set_tenant(Cow::Borrowed("Contoso"));
         log.set_event(SiemEvent::Auth(AuthEvent {
@@ -195,18 +485,577 @@ mod client {
                 user_name: Cow::Borrowed("cancamusa"),
             }),
         }));
-        let alert = SiemAlert {
-            title: format!("(TEST) test_client_alert {:?}", std::time::SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs()),
+        SiemAlert {
+            title: String::from("(TEST) test_client_alert"),
             description: String::from("This is a test of the NotionAlerter component"),
             severity: AlertSeverity::CRITICAL,
             date: usiem::chrono::Utc::now().timestamp_millis(),
             tags: vec![String::from("Critical")],
             techniques : vec![MitreTechniques::T1001, MitreTechniques::T1003_001],
             rule: String::from("ruleset::example::rule1"),
-            log: log.clone(),
+            log,
             aggr_limit: 0,
             aggr_key: String::from("example::rule"),
+        }
+    }
+
+    fn mock_client(server : &MockNotionServer, api_key : &str, database_id : &str) -> NotionClient {
+        NotionClient::builder(api_key, database_id).base_url(&server.base_url()).retry_policy(RetryPolicy {
+            max_retries : 3,
+            base_delay : Duration::from_millis(1),
+            max_delay : Duration::from_millis(10)
+        }).build().unwrap()
+    }
+
+    fn page_requests(server : &MockNotionServer) -> usize {
+        server.requests().iter().filter(|r| r.path == "/v1/pages").count()
+    }
+
+    #[test]
+    fn test_connection(){
+        let server = MockNotionServer::start(API_KEY);
+        server.add_siem_database(DATABASE_ID);
+        let mut client = mock_client(&server, API_KEY, DATABASE_ID);
+        assert!(client.check_valid_siem_database().unwrap().is_valid());
+    }
+
+    #[test]
+    fn should_reject_incomplete_database() {
+        let server = MockNotionServer::start(API_KEY);
+        let mut database = crate::mock::siem_database(DATABASE_ID);
+        database["properties"].as_object_mut().unwrap().remove("Status");
+        server.add_database(database);
+        let mut client = mock_client(&server, API_KEY, DATABASE_ID);
+        let report = client.check_valid_siem_database().unwrap();
+        assert!(!report.is_valid());
+        assert_eq!(AlertField::Status, report.missing_properties[0].field);
+    }
+
+    #[test]
+    fn should_fail_with_unknown_database() {
+        let server = MockNotionServer::start(API_KEY);
+        let mut client = mock_client(&server, API_KEY, DATABASE_ID);
+        let error = client.check_valid_siem_database().unwrap_err();
+        assert_eq!(Some(&NotionErrorCode::ObjectNotFound), error.code());
+        assert_eq!(Some(404), error.status());
+        let error = client.send_alert(&test_alert()).unwrap_err();
+        assert_eq!(Some(&NotionErrorCode::ObjectNotFound), error.code());
+        assert!(server.pages().is_empty());
+    }
+
+    #[test]
+    fn should_fail_with_invalid_api_key() {
+        let server = MockNotionServer::start(API_KEY);
+        server.add_siem_database(DATABASE_ID);
+        let mut client = mock_client(&server, "secret_invalid", DATABASE_ID);
+        let error = client.check_valid_siem_database().unwrap_err();
+        assert_eq!(Some(&NotionErrorCode::Unauthorized), error.code());
+        assert!(error.is_permanent());
+        assert_eq!(1, server.requests().len());
+    }
+
+    #[test]
+    fn test_client_alert() {
+        let server = MockNotionServer::start(API_KEY);
+        server.add_siem_database(DATABASE_ID);
+        let client = mock_client(&server, API_KEY, DATABASE_ID);
+        let page = client.send_alert(&test_alert()).unwrap();
+        let pages = server.pages();
+        assert_eq!(1, pages.len());
+        assert_eq!(pages[0]["id"], page.id);
+        assert_eq!(pages[0]["url"], page.url);
+        assert_eq!(pages[0]["created_time"], page.created_time);
+        assert_eq!(Parent::DatabaseId { database_id : DATABASE_ID.to_owned() }, page.parent);
+        assert_eq!("Critical", page.properties["Priority"]["select"]["name"]);
+        assert_eq!("(TEST) test_client_alert", pages[0]["properties"]["Name"]["title"][0]["plain_text"]);
+        assert_eq!("Critical", pages[0]["properties"]["Priority"]["select"]["name"]);
+        let page_id = pages[0]["id"].as_str().unwrap();
+        let blocks = server.children(page_id);
+        assert_eq!(9, blocks.len());
+        assert_eq!("heading_1", blocks[0]["type"]);
+        assert_eq!("Authentication event", blocks[3]["heading_3"]["rich_text"][0]["plain_text"]);
+        assert_eq!("table", blocks[4]["type"]);
+        assert_eq!("Log fields", blocks[5]["heading_3"]["rich_text"][0]["plain_text"]);
+        assert_eq!("table", blocks[6]["type"]);
+        assert_eq!("code", blocks[8]["type"]);
+        assert_eq!("json", blocks[8]["code"]["language"]);
+    }
+
+    #[test]
+    fn should_wait_for_rate_limit() {
+        let server = MockNotionServer::start(API_KEY);
+        server.add_siem_database(DATABASE_ID);
+        server.push_response(MockResponse::error(429, "rate_limited", "You have been rate limited. Please try again in a few minutes.").with_header("Retry-After", "1"));
+        let client = NotionClient::builder(API_KEY, DATABASE_ID).base_url(&server.base_url()).retry_policy(RetryPolicy {
+            max_retries : 3,
+            base_delay : Duration::from_millis(1),
+            max_delay : Duration::from_secs(2)
+        }).build().unwrap();
+        let start = Instant::now();
+        client.send_alert(&test_alert()).unwrap();
+        assert!(start.elapsed() >= Duration::from_secs(1));
+        assert_eq!(2, page_requests(&server));
+        assert_eq!(1, server.pages().len());
+    }
+
+    #[test]
+    fn should_retry_server_errors() {
+        let server = MockNotionServer::start(API_KEY);
+        server.add_siem_database(DATABASE_ID);
+        server.push_response(MockResponse::error(502, "internal_server_error", "Unexpected error occurred."));
+        server.push_response(MockResponse::error(503, "service_unavailable", "Notion is unavailable, try again later."));
+        let client = mock_client(&server, API_KEY, DATABASE_ID);
+        client.send_alert(&test_alert()).unwrap();
+        assert_eq!(3, page_requests(&server));
+        assert_eq!(1, server.pages().len());
+    }
+
+    #[test]
+    fn should_give_up_after_max_retries() {
+        let server = MockNotionServer::start(API_KEY);
+        server.add_siem_database(DATABASE_ID);
+        for _ in 0..4 {
+            server.push_response(MockResponse::error(500, "internal_server_error", "Unexpected error occurred."));
+        }
+        let client = mock_client(&server, API_KEY, DATABASE_ID);
+        let error = client.send_alert(&test_alert()).unwrap_err();
+        assert_eq!(Some(&NotionErrorCode::InternalServerError), error.code());
+        assert!(error.is_retriable());
+        assert_eq!(4, page_requests(&server));
+        assert!(server.pages().is_empty());
+    }
+
+    #[test]
+    fn should_not_retry_validation_errors() {
+        let server = MockNotionServer::start(API_KEY);
+        server.add_siem_database(DATABASE_ID);
+        server.push_response(MockResponse::error(400, "validation_error", "body failed validation: body.properties.Name.title should be defined, instead was `undefined`."));
+        let client = mock_client(&server, API_KEY, DATABASE_ID);
+        let error = client.send_alert(&test_alert()).unwrap_err();
+        assert_eq!(Some(&NotionErrorCode::ValidationError), error.code());
+        assert_eq!(1, page_requests(&server));
+    }
+
+    #[test]
+    fn should_retry_conflicts() {
+        let server = MockNotionServer::start(API_KEY);
+        server.add_siem_database(DATABASE_ID);
+        server.push_response(MockResponse::error(409, "conflict_error", "Conflict occurred while saving. Please try again."));
+        let client = mock_client(&server, API_KEY, DATABASE_ID);
+        client.send_alert(&test_alert()).unwrap();
+        assert_eq!(2, page_requests(&server));
+    }
+
+    #[test]
+    fn should_retry_connection_errors() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        drop(listener);
+        let mut client = NotionClient::builder(API_KEY, DATABASE_ID).base_url(&format!("http://{}/v1", address)).retry_policy(RetryPolicy {
+            max_retries : 2,
+            base_delay : Duration::from_millis(50),
+            max_delay : Duration::from_millis(50)
+        }).build().unwrap();
+        let start = Instant::now();
+        assert!(client.check_valid_siem_database().is_err());
+        assert!(start.elapsed() >= Duration::from_millis(50));
+    }
+
+    #[test]
+    fn should_split_big_alerts() {
+        let server = MockNotionServer::start(API_KEY);
+        server.add_siem_database(DATABASE_ID);
+        let client = mock_client(&server, API_KEY, DATABASE_ID);
+        let mut alert = test_alert();
+        alert.title = "T".repeat(3000);
+        alert.description = "Descripción ".repeat(1000);
+        alert.tags = (0..150).map(|v| format!("tag,{}", v)).collect();
+        alert.log = SiemLog::new("🚨".repeat(300_000), 0, "localhost");
+        client.send_alert(&alert).unwrap();
+        let pages = server.pages();
+        assert_eq!(1, pages.len());
+        assert_eq!(100, pages[0]["properties"]["Tags"]["multi_select"].as_array().unwrap().len());
+        assert_eq!("tag 0", pages[0]["properties"]["Tags"]["multi_select"][0]["name"]);
+        assert_eq!(2000, pages[0]["properties"]["Name"]["title"][0]["plain_text"].as_str().unwrap().chars().count());
+        let blocks = server.children(pages[0]["id"].as_str().unwrap());
+        assert_eq!("paragraph", blocks[2]["type"]);
+        assert_eq!(6, blocks[2]["paragraph"]["rich_text"].as_array().unwrap().len());
+        assert_eq!("table", blocks[4]["type"]);
+        assert_eq!("code", blocks[5]["type"]);
+        assert_eq!(100, blocks[5]["code"]["rich_text"].as_array().unwrap().len());
+        let message : String = blocks.iter().filter(|v| v["code"]["language"] == "plain text").flat_map(|v| v["code"]["rich_text"].as_array().unwrap().iter().map(|t| t["plain_text"].as_str().unwrap().to_owned())).collect();
+        assert_eq!(alert.log.message(), message);
+        assert!(blocks.iter().all(|v| !v.to_string().contains("omitted")));
+        let requests = server.requests();
+        assert!(requests.iter().any(|r| r.method == "PATCH"));
+        assert!(requests.iter().all(|r| r.body.to_string().len() <= crate::api::limits::MAX_PAYLOAD_SIZE));
+    }
+
+    #[test]
+    fn should_append_blocks_in_order() {
+        let server = MockNotionServer::start(API_KEY);
+        server.add_siem_database(DATABASE_ID);
+        let client = mock_client(&server, API_KEY, DATABASE_ID);
+        let mut properties = BTreeMap::new();
+        properties.insert("Name".to_owned(), PropertyValue::Title(TitleValue::new("Evidences")));
+        let page = PageElement {
+            parent : DatabaseParent { database_id : DATABASE_ID.to_owned() },
+            properties,
+            children : (0..250).map(|v| BlockElement::heading_three(&format!("Evidence {}", v))).collect()
+        };
+        let page_id = client.create_page(page).unwrap().id;
+        let blocks = server.children(&page_id);
+        assert_eq!(250, blocks.len());
+        for (i, block) in blocks.iter().enumerate() {
+            assert_eq!(format!("Evidence {}", i), block["heading_3"]["rich_text"][0]["plain_text"]);
+        }
+        let appends : Vec<usize> = server.requests().iter().filter(|r| r.method == "PATCH").map(|r| r.body["children"].as_array().unwrap().len()).collect();
+        assert_eq!(vec![100, 50], appends);
+    }
+
+    #[test]
+    fn should_limit_nesting_of_template_blocks() {
+        let server = MockNotionServer::start(API_KEY);
+        server.add_siem_database(DATABASE_ID);
+        let client = mock_client(&server, API_KEY, DATABASE_ID);
+        let template : crate::template::PageTemplate = usiem::serde_json::from_value(json!({
+            "blocks": [{"toggle": "Evidences", "children": [{"toggle": "Processes", "children": [{"markdown": "- item\n  - nested\n    - deep"}]}]}]
+        })).unwrap();
+        let page = client.send_alert_with_template(&test_alert(), &template).unwrap();
+        let blocks = server.children(&page.id);
+        assert_eq!(1, blocks.len());
+        let toggles = server.children(blocks[0]["id"].as_str().unwrap());
+        assert_eq!(1, toggles.len());
+        // The list items deeper than two levels are moved after their ancestor
+        let items : Vec<String> = server.children(toggles[0]["id"].as_str().unwrap()).iter().map(|v| v["bulleted_list_item"]["rich_text"][0]["plain_text"].as_str().unwrap().to_owned()).collect();
+        assert_eq!(vec!["item", "nested", "deep"], items);
+    }
+
+    #[test]
+    fn should_resume_incomplete_page() {
+        let server = MockNotionServer::start(API_KEY);
+        server.add_siem_database(DATABASE_ID);
+        server.push_response_for("PATCH", "/children", MockResponse::error(503, "service_unavailable", "Notion is unavailable"));
+        let client = NotionClient::builder(API_KEY, DATABASE_ID).base_url(&server.base_url()).retry_policy(RetryPolicy::none()).build().unwrap();
+        let page = PageElement {
+            parent : DatabaseParent { database_id : DATABASE_ID.to_owned() },
+            properties : BTreeMap::new(),
+            children : (0..250).map(|v| BlockElement::heading_three(&format!("Evidence {}", v))).collect()
         };
+        let incomplete = match client.create_page(page) {
+            Err(NotionError::Incomplete(incomplete)) => incomplete,
+            other => panic!("Expected an incomplete page: {:?}", other)
+        };
+        assert!(incomplete.error.is_retriable());
+        assert_eq!(150, incomplete.remaining.len());
+        assert_eq!(100, server.children(&incomplete.page_id).len());
+        client.append_blocks(&incomplete.page_id, &incomplete.url, incomplete.remaining).unwrap();
+        let blocks = server.children(&incomplete.page_id);
+        assert_eq!(250, blocks.len());
+        for (i, block) in blocks.iter().enumerate() {
+            assert_eq!(format!("Evidence {}", i), block["heading_3"]["rich_text"][0]["plain_text"]);
+        }
+        assert_eq!(1, server.pages().len());
+    }
+
+    #[test]
+    fn should_retrieve_page_content() {
+        let server = MockNotionServer::start(API_KEY);
+        server.add_siem_database(DATABASE_ID);
+        let client = mock_client(&server, API_KEY, DATABASE_ID);
+        let mut properties = BTreeMap::new();
+        properties.insert("Name".to_owned(), PropertyValue::Title(TitleValue::new("Notes")));
+        let mut children : Vec<BlockElement> = (0..150).map(|v| BlockElement::bulleted_list_item(&format!("Note {}", v))).collect();
+        children.push(BlockElement::toggle("Checklist", vec![BlockElement::to_do("Isolate the host", true), BlockElement::to_do("Reset the credentials", false)]));
+        let page = PageElement {
+            parent : DatabaseParent { database_id : DATABASE_ID.to_owned() },
+            properties,
+            children
+        };
+        let page_id = client.create_page(page).unwrap().id;
+        let content = client.retrieve_page_content(&page_id).unwrap();
+        assert_eq!(page_id, content.page.id);
+        assert_eq!(151, content.blocks.len());
+        match &content.blocks[149].block {
+            BlockElement::BulletedListItem(item) => assert_eq!("Note 149", item.rich_text[0].text()),
+            v => panic!("Unexpected block {:?}", v)
+        }
+        let toggle = &content.blocks[150];
+        assert!(toggle.has_children);
+        let checked : Vec<bool> = toggle.children.iter().map(|v| matches!(&v.block, BlockElement::ToDo(todo) if todo.checked)).collect();
+        assert_eq!(vec![true, false], checked);
+        let listings = server.requests().iter().filter(|r| r.method == "GET" && r.path.contains(&format!("{}/children", page_id))).count();
+        assert_eq!(2, listings);
+        let markdown = content.to_markdown();
+        assert!(markdown.starts_with("# Notes\n\n- Note 0\n- Note 1\n"));
+        assert!(markdown.ends_with("- Note 149\n- Checklist\n  - [x] Isolate the host\n  - [ ] Reset the credentials"));
+    }
+
+    #[test]
+    fn should_keep_blocks_not_modeled() {
+        let server = MockNotionServer::start(API_KEY);
+        let client = mock_client(&server, API_KEY, DATABASE_ID);
+        let block = |id : &str, block_type : &str, content : usiem::serde_json::Value| json!({"object": "block", "id": id, "has_children": false, "type": block_type, block_type: content});
+        server.push_response(MockResponse::new(200, json!({
+            "object": "list",
+            "results": [
+                block("b1", "paragraph", json!({"rich_text": [
+                    {"type": "text", "text": {"content": "See ", "link": null}, "plain_text": "See ", "href": null},
+                    {"type": "mention", "mention": {"type": "link_mention", "link_mention": {"href": "https://attack.mitre.org/techniques/T1003/"}}, "plain_text": "OS Credential Dumping", "href": "https://attack.mitre.org/techniques/T1003/"}
+                ], "color": "default"})),
+                block("b2", "callout", json!({"rich_text": [], "icon": {"type": "file", "file": {"url": "https://s3.example.com/icon.png", "expiry_time": "2023-01-01T01:00:00.000Z"}}, "color": "default"})),
+                block("b3", "callout", json!({"rich_text": [], "icon": {"type": "custom_emoji", "custom_emoji": {"id": "45ce454c-d427-4f53-9489-e5d0f3d1db6b", "name": "siem"}}, "color": "default"})),
+                block("b4", "heading_1", json!({"rich_text": "not an array"}))
+            ],
+            "next_cursor": null,
+            "has_more": false
+        })));
+        let blocks = client.retrieve_block_children("59833787-2cf9-4fdf-8782-e53db20768a5").unwrap();
+        assert_eq!(4, blocks.len());
+        assert_eq!("See [OS Credential Dumping](https://attack.mitre.org/techniques/T1003/)", crate::api::block::markdown::blocks_to_markdown(&blocks[0..1]));
+        match &blocks[1].block {
+            BlockElement::Callout(callout) => assert!(matches!(&callout.icon, Some(Icon::File { file }) if file.url == "https://s3.example.com/icon.png")),
+            v => panic!("Unexpected block {:?}", v)
+        }
+        match &blocks[2].block {
+            BlockElement::Callout(callout) => assert!(matches!(&callout.icon, Some(Icon::Unsupported(_)))),
+            v => panic!("Unexpected block {:?}", v)
+        }
+        assert!(matches!(&blocks[3].block, BlockElement::Unsupported(_)));
+        assert_eq!("heading_1", blocks[3].block.type_name());
+    }
+
+    #[test]
+    fn should_fail_appending_to_unknown_block() {
+        let server = MockNotionServer::start(API_KEY);
+        let client = mock_client(&server, API_KEY, DATABASE_ID);
+        let error = client.append_block_children("unknown", vec![BlockElement::heading_one("Test")]).unwrap_err();
+        assert_eq!(Some(&NotionErrorCode::ObjectNotFound), error.code());
+    }
+
+    #[test]
+    fn should_query_database() {
+        let server = MockNotionServer::start(API_KEY);
+        server.add_siem_database(DATABASE_ID);
+        let client = mock_client(&server, API_KEY, DATABASE_ID);
+        let severities = [AlertSeverity::LOW, AlertSeverity::HIGH, AlertSeverity::CRITICAL, AlertSeverity::HIGH];
+        for (i, severity) in severities.into_iter().enumerate() {
+            let mut alert = test_alert();
+            alert.title = format!("Alert {}", i);
+            alert.severity = severity;
+            alert.tags = vec![format!("tag{}", i % 2)];
+            alert.date = 1_665_000_000_000 + (i as i64) * 60_000;
+            client.send_alert(&alert).unwrap();
+        }
+        let high : Vec<String> = client.query_database(DatabaseQuery::new().filter(Filter::select_equals("Priority", "High"))).map(|p| title(&p.unwrap())).collect();
+        assert_eq!(vec!["Alert 1", "Alert 3"], high);
+        let query = DatabaseQuery::new().filter(Filter::and(vec![
+            Filter::multi_select_contains("Tags", "tag0"),
+            Filter::status_equals("Status", "Not started"),
+            Filter::date_after("Fired", "2022-10-05T20:00:30.000Z"),
+        ])).sort(Sort::descending("Fired"));
+        let found : Vec<String> = client.query_database(query).map(|p| title(&p.unwrap())).collect();
+        assert_eq!(vec!["Alert 2"], found);
+        let query = DatabaseQuery::new().filter(Filter::or(vec![
+            Filter::select_equals("Priority", "Low"),
+            Filter::select_equals("Priority", "Critical"),
+        ])).sort(Sort::descending("Fired"));
+        let found : Vec<String> = client.query_database(query).map(|p| title(&p.unwrap())).collect();
+        assert_eq!(vec!["Alert 2", "Alert 0"], found);
+    }
+
+    #[test]
+    fn should_paginate_query_results() {
+        let server = MockNotionServer::start(API_KEY);
+        server.add_siem_database(DATABASE_ID);
+        let client = mock_client(&server, API_KEY, DATABASE_ID);
+        for i in 0..7 {
+            let mut alert = test_alert();
+            alert.title = format!("Alert {}", i);
+            client.send_alert(&alert).unwrap();
+        }
+        let first = client.query_database_page(&DatabaseQuery::new().page_size(5)).unwrap();
+        assert_eq!(5, first.results.len());
+        assert!(first.has_more);
+        let pages : Vec<String> = client.query_database(DatabaseQuery::new().page_size(3).sort(Sort::created_time(SortDirection::Ascending))).map(|p| title(&p.unwrap())).collect();
+        assert_eq!((0..7).map(|i| format!("Alert {}", i)).collect::<Vec<String>>(), pages);
+        let queries = server.requests().iter().filter(|r| r.path.ends_with("/query")).count();
+        assert_eq!(4, queries);
+    }
+
+    #[test]
+    fn should_stop_iterating_on_error() {
+        let server = MockNotionServer::start(API_KEY);
+        let client = mock_client(&server, API_KEY, DATABASE_ID);
+        let mut pages = client.query_database(DatabaseQuery::new());
+        let error = pages.next().unwrap().unwrap_err();
+        assert_eq!(Some(&NotionErrorCode::ObjectNotFound), error.code());
+        assert!(pages.next().is_none());
+    }
+
+    #[test]
+    fn should_update_alert_pages() {
+        let server = MockNotionServer::start(API_KEY);
+        server.add_siem_database(DATABASE_ID);
+        let client = mock_client(&server, API_KEY, DATABASE_ID);
+        let mut alert = test_alert();
+        alert.severity = AlertSeverity::LOW;
         client.send_alert(&alert).unwrap();
+        let page_id = server.pages()[0]["id"].as_str().unwrap().to_owned();
+        let page = client.set_alert_priority(&page_id, &AlertSeverity::HIGH).unwrap();
+        assert_eq!("High", page.properties["Priority"]["select"]["name"]);
+        let page = client.add_alert_tags(&page_id, &["bruteforce".to_owned(), "Critical".to_owned()]).unwrap();
+        let tags : Vec<&str> = page.properties["Tags"]["multi_select"].as_array().unwrap().iter().map(|v| v["name"].as_str().unwrap()).collect();
+        assert_eq!(vec!["Critical", "bruteforce"], tags);
+        let page = client.set_alert_status(&page_id, "Done").unwrap();
+        assert_eq!("Done", page.properties["Status"]["status"]["name"]);
+        let error = client.set_alert_status(&page_id, "Closed").unwrap_err();
+        assert_eq!(Some(&NotionErrorCode::ValidationError), error.code());
+        let page = client.update_page(&page_id, &PageUpdate::new().property("Name", PropertyValue::Title(TitleValue::new("Renamed")))).unwrap();
+        assert_eq!("Renamed", title(&page));
+        assert_eq!("High", page.properties["Priority"]["select"]["name"]);
+    }
+
+    #[test]
+    fn should_archive_alert_pages() {
+        let server = MockNotionServer::start(API_KEY);
+        server.add_siem_database(DATABASE_ID);
+        let client = mock_client(&server, API_KEY, DATABASE_ID);
+        client.send_alert(&test_alert()).unwrap();
+        let page_id = server.pages()[0]["id"].as_str().unwrap().to_owned();
+        assert!(client.archive_page(&page_id).unwrap().archived);
+        assert_eq!(0, client.query_database(DatabaseQuery::new()).count());
+        let error = client.set_alert_status(&page_id, "Done").unwrap_err();
+        assert_eq!(Some(&NotionErrorCode::ValidationError), error.code());
+        assert!(!client.unarchive_page(&page_id).unwrap().archived);
+        assert_eq!(1, client.query_database(DatabaseQuery::new()).count());
+        assert!(!client.retrieve_page(&page_id).unwrap().archived);
+        let error = client.archive_page("unknown").unwrap_err();
+        assert_eq!(Some(&NotionErrorCode::ObjectNotFound), error.code());
+    }
+
+    #[test]
+    fn should_aggregate_alerts() {
+        let server = MockNotionServer::start(API_KEY);
+        let mut database = crate::mock::siem_database(DATABASE_ID);
+        database["properties"]["Occurrences"] = json!({"id": "oCc", "name": "Occurrences", "type": "number", "number": {"format": "number"}});
+        database["properties"]["Last seen"] = json!({"id": "lSt", "name": "Last seen", "type": "date", "date": {}});
+        server.add_database(database);
+        let mut client = mock_client(&server, API_KEY, DATABASE_ID);
+        assert!(client.check_valid_siem_database().unwrap().is_valid());
+        let mut alert = test_alert();
+        alert.aggr_limit = alert.date + 60_000;
+        let page = client.send_alert(&alert).unwrap();
+        assert_eq!(Some(1.0), page.properties["Occurrences"]["number"].as_f64());
+        let before = server.children(&page.id).len();
+        client.aggregate_alert(&page.id, &alert, 2).unwrap();
+        let children = server.children(&page.id);
+        assert!(children.len() > before);
+        assert!(children[before]["heading_3"]["rich_text"][0]["plain_text"].as_str().unwrap().starts_with("Occurrence 2"));
+        let page = client.retrieve_page(&page.id).unwrap();
+        assert_eq!(Some(2.0), page.properties["Occurrences"]["number"].as_f64());
+        assert_eq!(1, page_requests(&server));
+    }
+
+    #[test]
+    fn should_use_mapped_properties() {
+        let server = MockNotionServer::start(API_KEY);
+        let mut database = crate::mock::siem_database(DATABASE_ID);
+        let properties = database["properties"].as_object_mut().unwrap();
+        for (name, renamed) in [("Name", "Nombre"), ("Priority", "Prioridad"), ("Tags", "Etiquetas"), ("Status", "Estado"), ("Fired", "Disparada")] {
+            let mut property = properties.remove(name).unwrap();
+            property["name"] = renamed.into();
+            properties.insert(renamed.to_owned(), property);
+        }
+        server.add_database(database);
+        let mut client = mock_client(&server, API_KEY, DATABASE_ID);
+        assert!(!client.check_valid_siem_database().unwrap().is_valid());
+
+        let mapping = PropertyMapping::new()
+            .map(AlertField::Name, "Nombre")
+            .map(AlertField::Priority, "Prioridad")
+            .map(AlertField::Tags, "%5DhVm")
+            .map(AlertField::Status, "sTat")
+            .map(AlertField::Fired, "fIrd");
+        let mut client = NotionClient::builder(API_KEY, DATABASE_ID).base_url(&server.base_url()).property_mapping(mapping).build().unwrap();
+        assert!(client.check_valid_siem_database().unwrap().is_valid());
+        assert_eq!("Etiquetas", client.property(AlertField::Tags));
+        let page = client.send_alert(&test_alert()).unwrap();
+        assert_eq!("(TEST) test_client_alert", page.properties["Nombre"]["title"][0]["plain_text"]);
+        assert_eq!("Critical", page.properties["Prioridad"]["select"]["name"]);
+        assert_eq!("Critical", page.properties["Etiquetas"]["multi_select"][0]["name"]);
+        assert!(page.properties["Disparada"]["date"]["start"].is_string());
+        let page = client.set_alert_status(&page.id, "Done").unwrap();
+        assert_eq!("Done", page.properties["Estado"]["status"]["name"]);
+    }
+
+    #[test]
+    fn should_create_siem_database() {
+        let server = MockNotionServer::start(API_KEY);
+        let mut client = mock_client(&server, API_KEY, "");
+        let database = client.create_siem_database("b55c9c91-384d-452b-81db-d1ef79372b75", "SIEM Alerts").unwrap();
+        assert_eq!(database.id, client.database_id());
+        let report = client.check_valid_siem_database().unwrap();
+        assert!(report.is_empty(), "{}", report);
+        assert!(report.status_select);
+        assert!(report.aggregation_properties);
+        let mut alert = test_alert();
+        alert.aggr_limit = alert.date + 60_000;
+        let page = client.send_alert(&alert).unwrap();
+        assert_eq!("Not started", page.properties["Status"]["select"]["name"]);
+        assert_eq!(Some(1.0), page.properties["Occurrences"]["number"].as_f64());
+        let page = client.set_alert_status(&page.id, "Done").unwrap();
+        assert_eq!("Done", page.properties["Status"]["select"]["name"]);
+        let request = server.requests().into_iter().find(|r| r.path == "/v1/databases").unwrap();
+        assert_eq!(json!({"options": [
+            {"name": "Informational", "color": "gray"},
+            {"name": "Low", "color": "blue"},
+            {"name": "Medium", "color": "yellow"},
+            {"name": "High", "color": "orange"},
+            {"name": "Critical", "color": "red"}
+        ]}), request.body["properties"]["Priority"]["select"]);
+    }
+
+    #[test]
+    fn should_fail_creating_database_without_parent_access() {
+        let server = MockNotionServer::start(API_KEY);
+        server.push_response(MockResponse::error(404, "object_not_found", "Could not find page with ID: b55c9c91."));
+        let mut client = mock_client(&server, API_KEY, "");
+        let error = client.create_siem_database("b55c9c91", "SIEM Alerts").unwrap_err();
+        assert_eq!(Some(&NotionErrorCode::ObjectNotFound), error.code());
+        assert_eq!("", client.database_id());
+    }
+
+    #[test]
+    fn should_migrate_database() {
+        let server = MockNotionServer::start(API_KEY);
+        let mut database = crate::mock::siem_database(DATABASE_ID);
+        database["properties"].as_object_mut().unwrap().remove("MITRE");
+        database["properties"]["Priority"]["select"]["options"] = json!([{"id": "1", "name": "Low", "color": "blue"}, {"id": "9", "name": "Urgent", "color": "pink"}]);
+        server.add_database(database);
+        let mut client = mock_client(&server, API_KEY, DATABASE_ID);
+        assert!(!client.check_valid_siem_database().unwrap().is_valid());
+
+        let planned = client.migrate_siem_database(true).unwrap();
+        // The optional Occurrences and Last seen properties are also added
+        assert_eq!(7, planned.changes.len());
+        assert!(!client.check_valid_siem_database().unwrap().is_valid());
+        assert!(!server.requests().iter().any(|r| r.method == "PATCH"));
+
+        let applied = client.migrate_siem_database(false).unwrap();
+        assert_eq!(planned, applied);
+        let report = client.check_valid_siem_database().unwrap();
+        assert!(report.is_empty(), "{}", report);
+        assert!(report.aggregation_properties);
+        let database = client.retrieve_database().unwrap();
+        let mut options = database.properties["Priority"].option_names();
+        options.sort_unstable();
+        assert_eq!(vec!["Critical", "High", "Informational", "Low", "Medium", "Urgent"], options);
+        assert!(client.migrate_siem_database(false).unwrap().is_empty());
+        assert_eq!(1, server.requests().iter().filter(|r| r.method == "PATCH").count());
+    }
+
+    fn title(page : &Page) -> String {
+        page.properties["Name"]["title"][0]["plain_text"].as_str().unwrap_or_default().to_owned()
     }
 }
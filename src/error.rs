@@ -0,0 +1,280 @@
+use std::fmt;
+use std::time::Duration;
+
+use reqwest::blocking::Response;
+use reqwest::header::RETRY_AFTER;
+use serde::{Deserialize, Serialize};
+
+use crate::api::block::BlockElement;
+use crate::schema::SchemaReport;
+
+pub type NotionResult<T> = Result<T, NotionError>;
+
+#[derive(Debug)]
+pub enum NotionError {
+    /// The request could not be sent or the response could not be read
+    Connection(reqwest::Error),
+    Serialization(usiem::serde_json::Error),
+    /// Error object returned by the Notion API
+    Api(NotionApiError),
+    /// Unsuccessful response without a Notion error object (ex: from a proxy)
+    Http { status : u16, body : String },
+    /// The database does not have the properties of the alerts
+    InvalidSchema(SchemaReport),
+    /// Some blocks could not be appended to the page, ex: after the page was created
+    Incomplete(Box<IncompletePage>),
+    /// The API key has characters that cannot be sent in the Authorization header
+    InvalidApiKey,
+}
+
+/// Page without all its blocks
+#[derive(Debug)]
+pub struct IncompletePage {
+    pub page_id : String,
+    pub url : String,
+    /// Blocks not appended to the page, in order
+    pub remaining : Vec<BlockElement>,
+    /// Error of the request that failed
+    pub error : NotionError,
+}
+
+/// Error codes documented in https://developers.notion.com/reference/errors
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NotionErrorCode {
+    InvalidJson,
+    InvalidRequestUrl,
+    InvalidRequest,
+    InvalidGrant,
+    ValidationError,
+    MissingVersion,
+    Unauthorized,
+    RestrictedResource,
+    ObjectNotFound,
+    ConflictError,
+    RateLimited,
+    InternalServerError,
+    BadGateway,
+    ServiceUnavailable,
+    DatabaseConnectionUnavailable,
+    GatewayTimeout,
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct NotionApiError {
+    pub status : u16,
+    pub code : NotionErrorCode,
+    pub message : String,
+    #[serde(default)]
+    pub request_id : Option<String>,
+    /// Value of the Retry-After header of rate limited responses
+    #[serde(skip)]
+    pub retry_after : Option<Duration>,
+}
+
+impl NotionErrorCode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NotionErrorCode::InvalidJson => "invalid_json",
+            NotionErrorCode::InvalidRequestUrl => "invalid_request_url",
+            NotionErrorCode::InvalidRequest => "invalid_request",
+            NotionErrorCode::InvalidGrant => "invalid_grant",
+            NotionErrorCode::ValidationError => "validation_error",
+            NotionErrorCode::MissingVersion => "missing_version",
+            NotionErrorCode::Unauthorized => "unauthorized",
+            NotionErrorCode::RestrictedResource => "restricted_resource",
+            NotionErrorCode::ObjectNotFound => "object_not_found",
+            NotionErrorCode::ConflictError => "conflict_error",
+            NotionErrorCode::RateLimited => "rate_limited",
+            NotionErrorCode::InternalServerError => "internal_server_error",
+            NotionErrorCode::BadGateway => "bad_gateway",
+            NotionErrorCode::ServiceUnavailable => "service_unavailable",
+            NotionErrorCode::DatabaseConnectionUnavailable => "database_connection_unavailable",
+            NotionErrorCode::GatewayTimeout => "gateway_timeout",
+            NotionErrorCode::Unknown => "unknown",
+        }
+    }
+}
+
+impl fmt::Display for NotionErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl NotionError {
+    /// Builds the error from an unsuccessful response
+    pub fn from_response(response : Response) -> Self {
+        let status = response.status().as_u16();
+        let retry_after = retry_after(&response);
+        let body = match response.text() {
+            Ok(v) => v,
+            Err(e) => return NotionError::Connection(e)
+        };
+        match usiem::serde_json::from_str::<NotionApiError>(&body) {
+            Ok(mut error) => {
+                error.retry_after = retry_after;
+                NotionError::Api(error)
+            },
+            Err(_) => NotionError::Http { status, body }
+        }
+    }
+
+    pub fn code(&self) -> Option<&NotionErrorCode> {
+        match self {
+            NotionError::Api(e) => Some(&e.code),
+            NotionError::Incomplete(e) => e.error.code(),
+            _ => None
+        }
+    }
+
+    pub fn status(&self) -> Option<u16> {
+        match self {
+            NotionError::Api(e) => Some(e.status),
+            NotionError::Http { status, .. } => Some(*status),
+            NotionError::Connection(e) => e.status().map(|v| v.as_u16()),
+            NotionError::Serialization(_) | NotionError::InvalidSchema(_) | NotionError::InvalidApiKey => None,
+            NotionError::Incomplete(e) => e.error.status()
+        }
+    }
+
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            NotionError::Api(e) => e.retry_after,
+            NotionError::Incomplete(e) => e.error.retry_after(),
+            _ => None
+        }
+    }
+
+    /// The same request may succeed later: rate limits, conflicts, server or connection errors
+    pub fn is_retriable(&self) -> bool {
+        match self {
+            NotionError::Connection(e) => e.is_connect() || e.is_timeout() || e.is_request(),
+            NotionError::Serialization(_) | NotionError::InvalidSchema(_) | NotionError::InvalidApiKey => false,
+            NotionError::Api(e) => match e.code {
+                NotionErrorCode::RateLimited
+                | NotionErrorCode::ConflictError
+                | NotionErrorCode::InternalServerError
+                | NotionErrorCode::BadGateway
+                | NotionErrorCode::ServiceUnavailable
+                | NotionErrorCode::DatabaseConnectionUnavailable
+                | NotionErrorCode::GatewayTimeout => true,
+                NotionErrorCode::Unknown => is_retriable_status(e.status),
+                _ => false
+            },
+            NotionError::Http { status, .. } => is_retriable_status(*status),
+            NotionError::Incomplete(e) => e.error.is_retriable(),
+        }
+    }
+
+    /// Retrying will never succeed without changing the request or the configuration
+    pub fn is_permanent(&self) -> bool {
+        !self.is_retriable()
+    }
+}
+
+fn is_retriable_status(status : u16) -> bool {
+    status == 409 || status == 429 || status >= 500
+}
+
+/// Notion sends the number of seconds to wait in the `Retry-After` header
+fn retry_after(response : &Response) -> Option<Duration> {
+    let value = response.headers().get(RETRY_AFTER)?.to_str().ok()?;
+    let seconds : f64 = value.trim().parse().ok()?;
+    if seconds.is_finite() && seconds >= 0.0 {
+        Some(Duration::from_secs_f64(seconds))
+    } else {
+        None
+    }
+}
+
+impl fmt::Display for NotionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NotionError::Connection(e) => write!(f, "Connection error: {}", e),
+            NotionError::Serialization(e) => write!(f, "Serialization error: {}", e),
+            NotionError::Api(e) => {
+                write!(f, "Notion API error {} ({}): {}", e.code, e.status, e.message)?;
+                if let Some(request_id) = &e.request_id {
+                    write!(f, " [request_id: {}]", request_id)?;
+                }
+                Ok(())
+            },
+            NotionError::Http { status, body } => write!(f, "HTTP error {}: {}", status, body),
+            NotionError::InvalidSchema(report) => write!(f, "{}", report),
+            NotionError::Incomplete(e) => write!(f, "Some blocks were not appended to the page {}: {}", e.url, e.error),
+            NotionError::InvalidApiKey => write!(f, "The API key has characters not allowed in an HTTP header"),
+        }
+    }
+}
+
+impl std::error::Error for NotionError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            NotionError::Connection(e) => Some(e),
+            NotionError::Serialization(e) => Some(e),
+            _ => None
+        }
+    }
+}
+
+impl From<reqwest::Error> for NotionError {
+    fn from(e: reqwest::Error) -> Self {
+        NotionError::Connection(e)
+    }
+}
+
+impl From<usiem::serde_json::Error> for NotionError {
+    fn from(e: usiem::serde_json::Error) -> Self {
+        NotionError::Serialization(e)
+    }
+}
+
+#[cfg(test)]
+mod classification {
+    use super::*;
+    use usiem::serde_json::{self, json};
+
+    fn api_error(value : usiem::serde_json::Value) -> NotionError {
+        NotionError::Api(serde_json::from_value(value).unwrap())
+    }
+
+    #[test]
+    fn should_parse_notion_error_object() {
+        let error = api_error(json!({
+            "object": "error",
+            "status": 404,
+            "code": "object_not_found",
+            "message": "Could not find database with ID: 1234.",
+            "request_id": "6a1d7ff0-6e4b-4f54-94d6-d4a5fc1d8c0a"
+        }));
+        assert_eq!(Some(&NotionErrorCode::ObjectNotFound), error.code());
+        assert_eq!(Some(404), error.status());
+        assert!(error.is_permanent());
+        assert_eq!("Notion API error object_not_found (404): Could not find database with ID: 1234. [request_id: 6a1d7ff0-6e4b-4f54-94d6-d4a5fc1d8c0a]", error.to_string());
+    }
+
+    #[test]
+    fn should_classify_errors() {
+        let retriable = ["rate_limited", "conflict_error", "internal_server_error", "service_unavailable", "database_connection_unavailable", "gateway_timeout"];
+        for code in retriable {
+            assert!(api_error(json!({"status": 500, "code": code, "message": ""})).is_retriable(), "{}", code);
+        }
+        let permanent = ["validation_error", "unauthorized", "restricted_resource", "object_not_found", "invalid_json", "missing_version"];
+        for code in permanent {
+            assert!(api_error(json!({"status": 400, "code": code, "message": ""})).is_permanent(), "{}", code);
+        }
+    }
+
+    #[test]
+    fn should_accept_unknown_codes() {
+        let error = api_error(json!({"status": 503, "code": "new_error_code", "message": ""}));
+        assert_eq!(Some(&NotionErrorCode::Unknown), error.code());
+        assert!(error.is_retriable());
+        let error = api_error(json!({"status": 400, "code": "new_error_code", "message": ""}));
+        assert!(error.is_permanent());
+        assert!(NotionError::Http { status : 502, body : String::new() }.is_retriable());
+    }
+}
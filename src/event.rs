@@ -0,0 +1,293 @@
+//! Tables with the parsed event and the fields of the log of an alert.
+
+use usiem::prelude::auth::AuthLoginType;
+use usiem::prelude::{SiemEvent, SiemLog};
+use usiem::serde_json::Value;
+
+use crate::api::block::BlockElement;
+use crate::api::limits::MAX_CHILDREN;
+
+const MESSAGE_FIELD : &str = "message";
+
+/// Name of the event type and its relevant fields. Fields without value are not included. Events
+/// without a known structure return None.
+pub fn event_rows(event : &SiemEvent) -> Option<(&'static str, Vec<(String, String)>)> {
+    let (name, rows) = match event {
+        SiemEvent::Json(value) => return json_rows(value).map(|rows| ("JSON", rows)),
+        SiemEvent::Auth(auth) => {
+            let mut rows = vec![
+                ("Hostname", auth.hostname.to_string()),
+                ("Outcome", format!("{:?}", auth.outcome)),
+            ];
+            match &auth.login_type {
+                AuthLoginType::Local(login) => rows.extend([
+                    ("Login type", "Local".to_owned()),
+                    ("User", login.user_name.to_string()),
+                    ("Domain", login.domain.to_string()),
+                ]),
+                AuthLoginType::Remote(login) => rows.extend([
+                    ("Login type", "Remote".to_owned()),
+                    ("User", login.user_name.to_string()),
+                    ("Domain", login.domain.to_string()),
+                    ("Source address", login.source_address.to_string()),
+                ]),
+                AuthLoginType::Upgrade(login) => rows.extend([
+                    ("Login type", "Upgrade".to_owned()),
+                    ("Source user", login.source_user.to_string()),
+                    ("Destination user", login.destination_user.to_string()),
+                    ("Destination domain", login.destination_domain.to_string()),
+                ]),
+                AuthLoginType::Validation(login) => rows.extend([
+                    ("Login type", "Validation".to_owned()),
+                    ("User", login.user_name.to_string()),
+                    ("Source address", login.source_address.to_string()),
+                ]),
+                AuthLoginType::Delegation(login) => rows.extend([
+                    ("Login type", "Delegation".to_owned()),
+                    ("Source user", login.source_user.to_string()),
+                    ("Source domain", login.source_domain.to_string()),
+                    ("Destination user", login.destination_user.to_string()),
+                    ("Destination domain", login.destination_domain.to_string()),
+                ]),
+            }
+            ("Authentication", rows)
+        },
+        SiemEvent::Firewall(fw) => ("Firewall", vec![
+            ("Source", format!("{}:{}", fw.source_ip, fw.source_port)),
+            ("Destination", format!("{}:{}", fw.destination_ip, fw.destination_port)),
+            ("Protocol", format!("{:?}", fw.network_protocol)),
+            ("Outcome", format!("{:?}", fw.outcome)),
+            ("In interface", fw.in_interface.to_string()),
+            ("Out interface", fw.out_interface.to_string()),
+            ("In bytes", fw.in_bytes.to_string()),
+            ("Out bytes", fw.out_bytes.to_string()),
+        ]),
+        SiemEvent::Intrusion(ids) => ("Intrusion", vec![
+            ("Source", format!("{}:{}", ids.source_ip, ids.source_port)),
+            ("Destination", format!("{}:{}", ids.destination_ip, ids.destination_port)),
+            ("Protocol", format!("{:?}", ids.network_protocol)),
+            ("Outcome", format!("{:?}", ids.outcome)),
+            ("Rule", ids.rule_name.to_string()),
+            ("Rule id", ids.rule_id.to_string()),
+            ("Category", format!("{:?}", ids.rule_category)),
+        ]),
+        SiemEvent::WebProxy(proxy) => ("Web proxy", vec![
+            ("Source", proxy.source_ip.to_string()),
+            ("Destination", format!("{}:{}", proxy.destination_ip, proxy.destination_port)),
+            ("User", proxy.user_name.to_string()),
+            ("Method", format!("{:?}", proxy.http_method)),
+            ("URL", proxy.url.to_string()),
+            ("Domain", proxy.domain.to_string()),
+            ("Status code", proxy.http_code.to_string()),
+            ("Outcome", format!("{:?}", proxy.outcome)),
+            ("Rule", proxy.rule_name.as_ref().map(|v| v.to_string()).unwrap_or_default()),
+            ("Category", proxy.rule_category.as_ref().map(|v| format!("{:?}", v)).unwrap_or_default()),
+            ("MIME type", proxy.mime_type.to_string()),
+            ("In bytes", proxy.in_bytes.to_string()),
+            ("Out bytes", proxy.out_bytes.to_string()),
+        ]),
+        SiemEvent::WebServer(web) => ("Web server", vec![
+            ("Source", web.source_ip.to_string()),
+            ("Destination", match &web.destination_ip {
+                Some(ip) => format!("{}:{}", ip, web.destination_port),
+                None => String::new()
+            }),
+            ("User", web.user_name.to_string()),
+            ("Method", format!("{:?}", web.http_method)),
+            ("URL", web.url_full.to_string()),
+            ("Status code", web.http_code.to_string()),
+            ("Outcome", format!("{:?}", web.outcome)),
+            ("User agent", web.user_agent.to_string()),
+            ("Duration", web.duration.to_string()),
+            ("In bytes", web.in_bytes.to_string()),
+            ("Out bytes", web.out_bytes.to_string()),
+        ]),
+        SiemEvent::DNS(dns) => ("DNS", vec![
+            ("Source", dns.source_ip.to_string()),
+            ("Server", dns.destination_ip.to_string()),
+            ("Operation", format!("{:?}", dns.op_code)),
+            ("Record type", format!("{:?}", dns.record_type)),
+            ("Name", dns.record_name.to_string()),
+            ("Data", dns.data.as_ref().map(|v| v.to_string()).unwrap_or_default()),
+        ]),
+        SiemEvent::DHCP(dhcp) => ("DHCP", vec![
+            ("Source", dhcp.source_ip.to_string()),
+            ("MAC", format!("{:012x}", dhcp.source_mac)),
+            ("Record type", format!("{:?}", dhcp.record_type)),
+            ("Source hostname", dhcp.source_hostname.to_string()),
+            ("Hostname", dhcp.hostname.to_string()),
+        ]),
+        _ => return None
+    };
+    Some((name, rows.into_iter().filter(|(_, value)| !value.is_empty()).map(|(field, value)| (field.to_owned(), value)).collect()))
+}
+
+/// Top level members of a JSON object with a text, number or boolean value. Nested objects and arrays
+/// are left out.
+fn json_rows(value : &Value) -> Option<Vec<(String, String)>> {
+    let rows = value.as_object()?.iter().filter_map(|(field, value)| match value {
+        Value::String(v) if !v.is_empty() => Some((field.clone(), v.clone())),
+        Value::Number(v) => Some((field.clone(), v.to_string())),
+        Value::Bool(v) => Some((field.clone(), v.to_string())),
+        _ => None
+    }).collect();
+    Some(rows)
+}
+
+/// Heading and table with the fields of the parsed event, or nothing if the event has no known structure
+pub fn event_table(log : &SiemLog) -> Vec<BlockElement> {
+    match event_rows(log.event()) {
+        Some((name, rows)) if !rows.is_empty() => table(&format!("{} event", name), rows.into_iter().map(|(field, value)| vec![field, value])),
+        _ => Vec::new()
+    }
+}
+
+/// Heading and key/value table with the fields of the log, or nothing if it has no fields. The message
+/// is left out, it is shown in its own code blocks.
+pub fn fields_table(log : &SiemLog) -> Vec<BlockElement> {
+    let rows : Vec<Vec<String>> = log.fields().iter()
+        .filter(|(field, _)| field.as_ref() != MESSAGE_FIELD)
+        .map(|(field, value)| vec![field.to_string(), value.to_string()])
+        .collect();
+    if rows.is_empty() {
+        return Vec::new();
+    }
+    table("Log fields", rows.into_iter())
+}
+
+/// Tables can have up to MAX_CHILDREN rows in the request. The last row tells how many rows were left out.
+fn table<I>(title : &str, rows : I) -> Vec<BlockElement> where I : ExactSizeIterator<Item = Vec<String>> {
+    let total = rows.len();
+    let mut table = vec![vec!["Field".to_owned(), "Value".to_owned()]];
+    if total > MAX_CHILDREN - 1 {
+        table.extend(rows.take(MAX_CHILDREN - 2));
+        table.push(vec![String::from("..."), format!("{} more fields", total - (MAX_CHILDREN - 2))]);
+    } else {
+        table.extend(rows);
+    }
+    vec![BlockElement::heading_three(title), BlockElement::table(&table, true)]
+}
+
+#[cfg(test)]
+mod tables {
+    use std::borrow::Cow;
+    use usiem::prelude::{SiemEvent, SiemLog, field::{SiemField, SiemIp}};
+    use usiem::prelude::auth::{AuthEvent, AuthLoginType, LoginOutcome, RemoteLogin};
+    use usiem::prelude::firewall::{FirewallEvent, FirewallOutcome};
+    use usiem::prelude::protocol::NetworkProtocol;
+    use usiem::serde_json;
+    use super::*;
+
+    fn cells(blocks : &[BlockElement]) -> Vec<Vec<String>> {
+        let table = serde_json::to_value(&blocks[1]).unwrap();
+        table["table"]["children"].as_array().unwrap().iter().map(|row| {
+            row["table_row"]["cells"].as_array().unwrap().iter().map(|cell| cell.as_array().unwrap().iter().map(|v| v["text"]["content"].as_str().unwrap()).collect()).collect()
+        }).collect()
+    }
+
+    #[test]
+    fn should_render_auth_event() {
+        let mut log = SiemLog::new("login", 0, "localhost");
+        log.set_event(SiemEvent::Auth(AuthEvent {
+            hostname: Cow::Borrowed("hostname1"),
+            outcome: LoginOutcome::FAIL,
+            login_type: AuthLoginType::Remote(RemoteLogin {
+                domain: Cow::Borrowed(""),
+                source_address: Cow::Borrowed("10.10.10.10"),
+                user_name: Cow::Borrowed("cancamusa"),
+            }),
+        }));
+        let blocks = event_table(&log);
+        assert_eq!("Authentication event", serde_json::to_value(&blocks[0]).unwrap()["heading_3"]["rich_text"][0]["text"]["content"]);
+        assert_eq!(vec![
+            vec!["Field", "Value"],
+            vec!["Hostname", "hostname1"],
+            vec!["Outcome", "FAIL"],
+            vec!["Login type", "Remote"],
+            vec!["User", "cancamusa"],
+            vec!["Source address", "10.10.10.10"],
+        ], cells(&blocks));
+    }
+
+    #[test]
+    fn should_render_firewall_event() {
+        let mut log = SiemLog::new("connection", 0, "localhost");
+        log.set_event(SiemEvent::Firewall(FirewallEvent {
+            source_ip: SiemIp::from_ip_str("192.168.2.1").unwrap(),
+            destination_ip: SiemIp::from_ip_str("192.168.3.2").unwrap(),
+            source_port: 35861,
+            destination_port: 514,
+            network_protocol: NetworkProtocol::UDP,
+            outcome: FirewallOutcome::BLOCK,
+            in_bytes: 102,
+            out_bytes: 0,
+            in_interface: Cow::Borrowed("ethernet1"),
+            out_interface: Cow::Borrowed(""),
+        }));
+        let rows = cells(&event_table(&log));
+        assert_eq!(vec!["Source", "192.168.2.1:35861"], rows[1]);
+        assert_eq!(vec!["Destination", "192.168.3.2:514"], rows[2]);
+        assert_eq!(vec!["Protocol", "UDP"], rows[3]);
+        assert_eq!(vec!["Outcome", "BLOCK"], rows[4]);
+        assert_eq!(8, rows.len());
+    }
+
+    #[test]
+    fn should_render_json_event() {
+        let mut log = SiemLog::new("json", 0, "localhost");
+        log.set_event(SiemEvent::Json(serde_json::json!({
+            "action": "delete",
+            "count": 3,
+            "empty": "",
+            "success": false,
+            "user": {"name": "root"},
+            "files": ["a", "b"],
+            "reason": null
+        })));
+        let blocks = event_table(&log);
+        assert_eq!("JSON event", serde_json::to_value(&blocks[0]).unwrap()["heading_3"]["rich_text"][0]["text"]["content"]);
+        assert_eq!(vec![
+            vec!["Field", "Value"],
+            vec!["action", "delete"],
+            vec!["count", "3"],
+            vec!["success", "false"],
+        ], cells(&blocks));
+
+        let members : serde_json::Map<String, serde_json::Value> = (0..150).map(|i| (format!("field{:03}", i), serde_json::Value::from(i))).collect();
+        log.set_event(SiemEvent::Json(serde_json::Value::Object(members)));
+        let rows = cells(&event_table(&log));
+        assert_eq!(MAX_CHILDREN, rows.len());
+        assert_eq!(vec!["...", "52 more fields"], rows[MAX_CHILDREN - 1]);
+
+        log.set_event(SiemEvent::Json(serde_json::json!(["a", "b"])));
+        assert!(event_table(&log).is_empty());
+    }
+
+    #[test]
+    fn should_skip_unknown_events() {
+        let log = SiemLog::new("log", 0, "localhost");
+        assert!(event_table(&log).is_empty());
+    }
+
+    #[test]
+    fn should_render_log_fields() {
+        let mut log = SiemLog::new("log", 0, "localhost");
+        log.add_field("user.name", SiemField::User(String::from("root")));
+        log.add_field("source.ip", SiemField::IP(SiemIp::from_ip_str("10.10.10.10").unwrap()));
+        assert_eq!(vec![
+            vec!["Field", "Value"],
+            vec!["event_created", "0"],
+            vec!["event_received", "0"],
+            vec!["origin", "localhost"],
+            vec!["source.ip", "10.10.10.10"],
+            vec!["user.name", "root"],
+        ], cells(&fields_table(&log)));
+
+        for i in 0..150 {
+            log.add_field(&format!("field{:03}", i), SiemField::from_str(format!("value{}", i)));
+        }
+        let rows = cells(&fields_table(&log));
+        assert_eq!(MAX_CHILDREN, rows.len());
+        assert_eq!(vec!["...", "57 more fields"], rows[MAX_CHILDREN - 1]);
+    }
+}
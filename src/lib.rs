@@ -1,6 +1,17 @@
 pub mod api;
 pub mod client;
+pub mod error;
+pub mod event;
+pub mod mapping;
+pub mod retry;
+pub mod routing;
+pub mod schema;
+pub mod template;
 mod alerter;
+mod aggregation;
+mod queue;
+#[cfg(any(test, feature = "mock"))]
+pub mod mock;
 
 pub use alerter::NotionAlert;
 
@@ -11,4 +22,4 @@ mod tests {
     fn test_basic() {
         assert_eq!(3,1+2);
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,167 @@
+//! Database properties where each field of the alert is stored.
+//!
+//! By default the alerter uses the properties "Name", "Priority", "MITRE", "Tags", "Status" and "Fired".
+//! Each field can be mapped to another property name or, better, to the property id, which does not change
+//! when the property is renamed in Notion.
+
+use std::collections::BTreeMap;
+
+use usiem::prelude::dataset::text_map::TextMapSynDataset;
+
+use crate::api::database::DatabasePropertyIds;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum AlertField {
+    /// Title of the alert
+    Name,
+    Priority,
+    Mitre,
+    Tags,
+    Status,
+    /// Date of the alert
+    Fired,
+    /// Optional: number of alerts aggregated in the page
+    Occurrences,
+    /// Optional: date of the last alert aggregated in the page
+    LastSeen,
+}
+
+impl AlertField {
+    /// Fields that the database must have
+    pub const REQUIRED : [AlertField; 6] = [AlertField::Name, AlertField::Priority, AlertField::Mitre, AlertField::Tags, AlertField::Status, AlertField::Fired];
+    pub const ALL : [AlertField; 8] = [AlertField::Name, AlertField::Priority, AlertField::Mitre, AlertField::Tags, AlertField::Status, AlertField::Fired, AlertField::Occurrences, AlertField::LastSeen];
+
+    /// Key of the field in the mapping dataset
+    pub fn key(&self) -> &'static str {
+        match self {
+            AlertField::Name => "name",
+            AlertField::Priority => "priority",
+            AlertField::Mitre => "mitre",
+            AlertField::Tags => "tags",
+            AlertField::Status => "status",
+            AlertField::Fired => "fired",
+            AlertField::Occurrences => "occurrences",
+            AlertField::LastSeen => "last_seen",
+        }
+    }
+
+    /// Property used when the field is not mapped
+    pub fn default_property(&self) -> &'static str {
+        match self {
+            AlertField::Name => "Name",
+            AlertField::Priority => "Priority",
+            AlertField::Mitre => "MITRE",
+            AlertField::Tags => "Tags",
+            AlertField::Status => "Status",
+            AlertField::Fired => "Fired",
+            AlertField::Occurrences => "Occurrences",
+            AlertField::LastSeen => "Last seen",
+        }
+    }
+
+    /// Type of the property where the field is stored
+    pub fn property_type(&self) -> &'static str {
+        match self {
+            AlertField::Name => "title",
+            AlertField::Priority => "select",
+            AlertField::Mitre | AlertField::Tags => "multi_select",
+            AlertField::Status => "status",
+            AlertField::Fired | AlertField::LastSeen => "date",
+            AlertField::Occurrences => "number",
+        }
+    }
+
+    pub fn from_key(key : &str) -> Option<Self> {
+        Self::ALL.iter().find(|v| v.key() == key).copied()
+    }
+}
+
+/// Name or id of the property of each alert field
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PropertyMapping {
+    properties : BTreeMap<AlertField, String>,
+}
+
+impl PropertyMapping {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stores the field in the property with this name or id
+    pub fn map(mut self, field : AlertField, property : &str) -> Self {
+        self.properties.insert(field, property.to_owned());
+        self
+    }
+
+    /// Reads the mapping from a dataset with the field keys ("name", "priority", "mitre", "tags", "status",
+    /// "fired", "occurrences" and "last_seen") and the property names or ids as values
+    pub fn from_dataset(dataset : &TextMapSynDataset) -> Self {
+        let mut mapping = Self::new();
+        for field in AlertField::ALL {
+            if let Some(property) = dataset.get(field.key()) {
+                mapping = mapping.map(field, property);
+            }
+        }
+        mapping
+    }
+
+    /// Configured name or id of the property
+    pub fn property(&self, field : AlertField) -> &str {
+        self.properties.get(&field).map(|v| &v[..]).unwrap_or_else(|| field.default_property())
+    }
+
+    /// Replaces the property ids with the names they have in the database. Properties that are
+    /// not in the database are kept as they are.
+    pub fn resolve(&self, database : &DatabasePropertyIds) -> Self {
+        let mut resolved = Self::new();
+        for field in AlertField::ALL {
+            let property = self.property(field);
+            let name = if database.properties.contains_key(property) {
+                property
+            } else {
+                database.properties.iter().find(|(_, v)| v.id == property).map(|(name, _)| &name[..]).unwrap_or(property)
+            };
+            resolved = resolved.map(field, name);
+        }
+        resolved
+    }
+}
+
+#[cfg(test)]
+mod resolution {
+    use super::*;
+    use usiem::serde_json::{self, json};
+
+    #[test]
+    fn should_resolve_names_and_ids() {
+        let database : DatabasePropertyIds = serde_json::from_value(json!({
+            "properties": {
+                "Nombre": {"id": "title", "type": "title", "title": {}},
+                "Prioridad": {"id": "%3DbQp", "type": "select", "select": {}},
+                "Etiquetas": {"id": "%5DhVm", "type": "multi_select", "multi_select": {}},
+                "Status": {"id": "sTat", "type": "status", "status": {}},
+            }
+        })).unwrap();
+        let mapping = PropertyMapping::new()
+            .map(AlertField::Name, "Nombre")
+            .map(AlertField::Priority, "%3DbQp")
+            .map(AlertField::Tags, "%5DhVm")
+            .map(AlertField::Fired, "Disparada");
+        let resolved = mapping.resolve(&database);
+        assert_eq!("Nombre", resolved.property(AlertField::Name));
+        assert_eq!("Prioridad", resolved.property(AlertField::Priority));
+        assert_eq!("Etiquetas", resolved.property(AlertField::Tags));
+        assert_eq!("Status", resolved.property(AlertField::Status));
+        assert_eq!("Disparada", resolved.property(AlertField::Fired));
+        assert_eq!("MITRE", resolved.property(AlertField::Mitre));
+        assert_eq!("Last seen", resolved.property(AlertField::LastSeen));
+    }
+
+    #[test]
+    fn should_parse_field_keys() {
+        for field in AlertField::ALL {
+            assert_eq!(Some(field), AlertField::from_key(field.key()));
+        }
+        assert_eq!(None, AlertField::from_key("unknown"));
+    }
+}
@@ -0,0 +1,1102 @@
+//! Minimal in-process Notion API server used to exercise the client and the alerter offline.
+//!
+//! It understands the subset of the Notion REST API used by this crate and answers with
+//! the same JSON shapes (and error objects) as the real service.
+use std::collections::{BTreeMap, VecDeque};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use std::borrow::Cow;
+
+use usiem::chrono::{SecondsFormat, Utc};
+use usiem::components::common::{SiemComponentStateStorage, StorageError};
+use usiem::serde_json::{self, json, Map, Value};
+
+/// Maximum length of a single rich text content accepted by Notion
+const MAX_TEXT_LENGTH : usize = 2000;
+/// Maximum number of blocks in a single request
+const MAX_CHILDREN : usize = 100;
+const DEFAULT_PAGE_SIZE : usize = 100;
+
+/// A request received by the mock server
+#[derive(Debug, Clone)]
+pub struct MockRequest {
+    pub method : String,
+    pub path : String,
+    pub body : Value,
+    /// Bearer token of the Authorization header
+    pub api_key : Option<String>,
+}
+
+/// A canned response to be returned instead of processing the next request
+#[derive(Debug, Clone)]
+pub struct MockResponse {
+    pub status : u16,
+    pub headers : Vec<(String, String)>,
+    pub body : Value,
+}
+
+impl MockResponse {
+    pub fn new(status : u16, body : Value) -> Self {
+        Self {
+            status,
+            headers : Vec::new(),
+            body,
+        }
+    }
+    /// Notion error object
+    pub fn error(status : u16, code : &str, message : &str) -> Self {
+        Self::new(
+            status,
+            json!({
+                "object": "error",
+                "status": status,
+                "code": code,
+                "message": message,
+                "request_id": "00000000-0000-4000-8000-000000000000"
+            }),
+        )
+    }
+    pub fn with_header(mut self, name : &str, value : &str) -> Self {
+        self.headers.push((name.to_owned(), value.to_owned()));
+        self
+    }
+}
+
+#[derive(Default)]
+struct MockState {
+    api_keys : Vec<String>,
+    databases : BTreeMap<String, Value>,
+    pages : BTreeMap<String, Value>,
+    page_order : Vec<String>,
+    blocks : BTreeMap<String, Value>,
+    children : BTreeMap<String, Vec<String>>,
+    failures : VecDeque<MockResponse>,
+    /// Responses for the next request with a method and path suffix
+    path_failures : Vec<(String, String, MockResponse)>,
+    requests : Vec<MockRequest>,
+    counter : u64,
+}
+
+pub struct MockNotionServer {
+    addr : SocketAddr,
+    state : Arc<Mutex<MockState>>,
+    stop : Arc<AtomicBool>,
+    handle : Option<JoinHandle<()>>,
+}
+
+impl MockNotionServer {
+    /// Starts a server listening on a random local port. Requests must use `api_key` as bearer token.
+    pub fn start(api_key : &str) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("Cannot bind mock Notion server");
+        let addr = listener.local_addr().expect("Cannot get mock server address");
+        let state = Arc::new(Mutex::new(MockState {
+            api_keys : vec![api_key.to_owned()],
+            ..Default::default()
+        }));
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_state = state.clone();
+        let thread_stop = stop.clone();
+        let handle = std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                if thread_stop.load(Ordering::Relaxed) {
+                    return;
+                }
+                if let Ok(stream) = stream {
+                    handle_connection(stream, &thread_state);
+                }
+            }
+        });
+        Self {
+            addr,
+            state,
+            stop,
+            handle : Some(handle),
+        }
+    }
+
+    /// Base URL to be used with `NotionClientBuilder::base_url`
+    pub fn base_url(&self) -> String {
+        format!("http://{}/v1", self.addr)
+    }
+
+    /// Accepts another API key, like a second integration with access to the same workspace
+    pub fn add_api_key(&self, api_key : &str) {
+        self.state.lock().unwrap().api_keys.push(api_key.to_owned());
+    }
+
+    /// Registers a database. `definition` is a Notion database object.
+    pub fn add_database(&self, definition : Value) {
+        let id = definition.get("id").and_then(|v| v.as_str()).unwrap_or_default().to_owned();
+        self.state.lock().unwrap().databases.insert(id, definition);
+    }
+
+    /// Registers a database with the schema expected by the alerter
+    pub fn add_siem_database(&self, id : &str) {
+        self.add_database(siem_database(id));
+    }
+
+    /// The next request will be answered with this response
+    pub fn push_response(&self, response : MockResponse) {
+        self.state.lock().unwrap().failures.push_back(response);
+    }
+
+    /// The next request with the method and a path ending with `path` will be answered with this response
+    pub fn push_response_for(&self, method : &str, path : &str, response : MockResponse) {
+        self.state.lock().unwrap().path_failures.push((method.to_owned(), path.to_owned(), response));
+    }
+
+    /// Pages created, in creation order
+    pub fn pages(&self) -> Vec<Value> {
+        let state = self.state.lock().unwrap();
+        state.page_order.iter().filter_map(|id| state.pages.get(id).cloned()).collect()
+    }
+
+    /// Direct children of a page or block
+    pub fn children(&self, block_id : &str) -> Vec<Value> {
+        let state = self.state.lock().unwrap();
+        state.children.get(block_id).map(|ids| {
+                ids.iter().filter_map(|id| state.blocks.get(id).cloned()).collect()
+            }).unwrap_or_default()
+    }
+
+    /// Requests received, including the rejected ones
+    pub fn requests(&self) -> Vec<MockRequest> {
+        self.state.lock().unwrap().requests.clone()
+    }
+}
+
+impl Drop for MockNotionServer {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        // Unblock the accept loop
+        let _ = TcpStream::connect(self.addr);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+/// Component state storage kept in memory. Clones share the same data, like a real database connection.
+#[derive(Clone, Default)]
+pub struct MemoryStateStorage {
+    values : Arc<Mutex<BTreeMap<String, String>>>,
+    files : Arc<Mutex<BTreeMap<String, Vec<u8>>>>,
+}
+
+impl MemoryStateStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Stored keys, in order
+    pub fn keys(&self) -> Vec<String> {
+        self.values.lock().unwrap().keys().cloned().collect()
+    }
+}
+
+impl SiemComponentStateStorage for MemoryStateStorage {
+    fn get_value(&self, key : Cow<'static, str>) -> Result<String, StorageError> {
+        self.values.lock().unwrap().get(key.as_ref()).cloned().ok_or(StorageError::NotExists)
+    }
+
+    fn set_value(
+        &mut self,
+        key : Cow<'static, str>,
+        value : String,
+        replace : bool,
+    ) -> Result<(), StorageError> {
+        let mut values = self.values.lock().unwrap();
+        if !replace && values.contains_key(key.as_ref()) {
+            return Err(StorageError::AlredyExists);
+        }
+        values.insert(key.into_owned(), value);
+        Ok(())
+    }
+
+    fn get_file(&self, filepath : String) -> Result<Vec<u8>, StorageError> {
+        self.files.lock().unwrap().get(&filepath).cloned().ok_or(StorageError::NotExists)
+    }
+
+    fn get_file_size(&self, filepath : String) -> Result<u64, StorageError> {
+        self.get_file(filepath).map(|v| v.len() as u64)
+    }
+
+    fn get_file_range(
+        &self,
+        filepath : String,
+        start : u64,
+        end : u64,
+    ) -> Result<Vec<u8>, StorageError> {
+        let content = self.get_file(filepath)?;
+        let end = (end as usize).min(content.len());
+        let start = (start as usize).min(end);
+        Ok(content[start..end].to_vec())
+    }
+
+    fn set_file(&mut self, filepath : String, content : Vec<u8>) -> Result<(), StorageError> {
+        self.files.lock().unwrap().insert(filepath, content);
+        Ok(())
+    }
+
+    fn set_file_range(
+        &mut self,
+        filepath : String,
+        content : Vec<u8>,
+        start : u64,
+        _end : u64,
+    ) -> Result<(), StorageError> {
+        let mut files = self.files.lock().unwrap();
+        let file = files.entry(filepath).or_default();
+        let start = start as usize;
+        if file.len() < start + content.len() {
+            file.resize(start + content.len(), 0);
+        }
+        file[start..start + content.len()].copy_from_slice(&content);
+        Ok(())
+    }
+
+    fn duplicate(&self) -> Box<dyn SiemComponentStateStorage> {
+        Box::new(self.clone())
+    }
+}
+
+/// Database object with the properties needed by the alerter
+pub fn siem_database(id : &str) -> Value {
+    json!({
+        "object": "database",
+        "id": id,
+        "created_time": "2022-10-01T10:00:00.000Z",
+        "last_edited_time": "2022-10-01T10:00:00.000Z",
+        "title": [plain_text("SIEM Alerts")],
+        "properties": {
+            "Name": {"id": "title", "name": "Name", "type": "title", "title": {}},
+            "Priority": {"id": "%3DbQp", "name": "Priority", "type": "select", "select": {
+                "options": [
+                    {"id": "1", "name": "Informational", "color": "gray"},
+                    {"id": "2", "name": "Low", "color": "blue"},
+                    {"id": "3", "name": "Medium", "color": "yellow"},
+                    {"id": "4", "name": "High", "color": "orange"},
+                    {"id": "5", "name": "Critical", "color": "red"}
+                ]
+            }},
+            "MITRE": {"id": "Ss%3Aw", "name": "MITRE", "type": "multi_select", "multi_select": {"options": []}},
+            "Tags": {"id": "%5DhVm", "name": "Tags", "type": "multi_select", "multi_select": {"options": []}},
+            "Status": {"id": "sTat", "name": "Status", "type": "status", "status": {
+                "options": [
+                    {"id": "s1", "name": "Not started", "color": "default"},
+                    {"id": "s2", "name": "In progress", "color": "blue"},
+                    {"id": "s3", "name": "Done", "color": "green"}
+                ],
+                "groups": [
+                    {"id": "g1", "name": "To-do", "color": "gray", "option_ids": ["s1"]},
+                    {"id": "g2", "name": "In progress", "color": "blue", "option_ids": ["s2"]},
+                    {"id": "g3", "name": "Complete", "color": "green", "option_ids": ["s3"]}
+                ]
+            }},
+            "Fired": {"id": "fIrd", "name": "Fired", "type": "date", "date": {}}
+        }
+    })
+}
+
+fn plain_text(content : &str) -> Value {
+    json!({
+        "type": "text",
+        "text": {"content": content, "link": null},
+        "annotations": default_annotations(),
+        "plain_text": content,
+        "href": null
+    })
+}
+
+fn default_annotations() -> Value {
+    json!({
+        "bold": false,
+        "italic": false,
+        "strikethrough": false,
+        "underline": false,
+        "code": false,
+        "color": "default"
+    })
+}
+
+fn now() -> String {
+    Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+fn handle_connection(mut stream : TcpStream, state : &Arc<Mutex<MockState>>) {
+    let _ = stream.set_read_timeout(Some(Duration::from_secs(5)));
+    let request = match read_request(&stream) {
+        Some(v) => v,
+        None => return,
+    };
+    let response = {
+        let mut state = state.lock().unwrap();
+        state.requests.push(MockRequest {
+            method : request.method.clone(),
+            path : request.path.clone(),
+            body : request.body.clone(),
+            api_key : request.headers.get("authorization").and_then(|v| v.strip_prefix("Bearer ")).map(|v| v.to_owned()),
+        });
+        let path_failure = state.path_failures.iter().position(|(method, path, _)| *method == request.method && request.path.ends_with(path.as_str()));
+        match (state.failures.pop_front(), path_failure) {
+            (Some(response), _) => response,
+            (None, Some(position)) => state.path_failures.remove(position).2,
+            (None, None) => state.process(&request),
+        }
+    };
+    write_response(&mut stream, response);
+}
+
+struct RawRequest {
+    method : String,
+    path : String,
+    query : BTreeMap<String, String>,
+    headers : BTreeMap<String, String>,
+    body : Value,
+}
+
+fn read_request(stream : &TcpStream) -> Option<RawRequest> {
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    reader.read_line(&mut line).ok()?;
+    let mut parts = line.split_whitespace();
+    let method = parts.next()?.to_owned();
+    let target = parts.next()?.to_owned();
+    let mut headers = BTreeMap::new();
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).ok()? == 0 {
+            break;
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            headers.insert(name.trim().to_lowercase(), value.trim().to_owned());
+        }
+    }
+    let length : usize = headers.get("content-length").and_then(|v| v.parse().ok()).unwrap_or(0);
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).ok()?;
+    let body = if body.is_empty() {
+        Value::Null
+    } else {
+        serde_json::from_slice(&body).unwrap_or(Value::Null)
+    };
+    let (path, query) = match target.split_once('?') {
+        Some((path, query)) => (path.to_owned(), query.to_owned()),
+        None => (target, String::new()),
+    };
+    let query = query.split('&').filter_map(|v| v.split_once('=')).map(|(k, v)| (k.to_owned(), v.to_owned())).collect();
+    Some(RawRequest {
+        method,
+        path,
+        query,
+        headers,
+        body,
+    })
+}
+
+fn write_response(stream : &mut TcpStream, response : MockResponse) {
+    let body = response.body.to_string();
+    let mut raw = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n",
+        response.status,
+        reason(response.status),
+        body.len()
+    );
+    for (name, value) in &response.headers {
+        raw.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    raw.push_str("\r\n");
+    raw.push_str(&body);
+    let _ = stream.write_all(raw.as_bytes());
+    let _ = stream.flush();
+}
+
+fn reason(status : u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        409 => "Conflict",
+        429 => "Too Many Requests",
+        500 => "Internal Server Error",
+        502 => "Bad Gateway",
+        503 => "Service Unavailable",
+        _ => "Unknown",
+    }
+}
+
+impl MockState {
+    fn process(&mut self, request : &RawRequest) -> MockResponse {
+        let authorized = self.api_keys.iter().any(|key| request.headers.get("authorization") == Some(&format!("Bearer {}", key)));
+        if !authorized {
+            return MockResponse::error(401, "unauthorized", "API token is invalid.");
+        }
+        if !request.headers.contains_key("notion-version") {
+            return MockResponse::error(
+                400,
+                "missing_version",
+                "Notion-Version header failed validation: Notion-Version header should be defined, instead was `undefined`.",
+            );
+        }
+        let segments : Vec<&str> = request.path.trim_matches('/').split('/').collect();
+        match (request.method.as_str(), &segments[..]) {
+            ("POST", ["v1", "databases"]) => self.create_database(&request.body),
+            ("GET", ["v1", "databases", id]) => self.get_database(id),
+            ("PATCH", ["v1", "databases", id]) => self.update_database(id, &request.body),
+            ("POST", ["v1", "databases", id, "query"]) => self.query_database(id, &request.body),
+            ("POST", ["v1", "pages"]) => self.create_page(&request.body),
+            ("GET", ["v1", "pages", id]) => self.get_page(id),
+            ("PATCH", ["v1", "pages", id]) => self.update_page(id, &request.body),
+            ("GET", ["v1", "blocks", id, "children"]) => self.list_children(id, &request.query),
+            ("PATCH", ["v1", "blocks", id, "children"]) => self.append_children(id, &request.body),
+            _ => MockResponse::error(
+                400,
+                "invalid_request_url",
+                "Invalid request URL.",
+            ),
+        }
+    }
+
+    fn next_id(&mut self) -> String {
+        self.counter += 1;
+        format!("{:08x}-0000-4000-8000-{:012x}", 0x5153_u32, self.counter)
+    }
+
+    fn create_database(&mut self, body : &Value) -> MockResponse {
+        let parent = match body.pointer("/parent/page_id").and_then(|v| v.as_str()) {
+            Some(v) => v.to_owned(),
+            None => return validation_error("body.parent.page_id should be defined, instead was `undefined`."),
+        };
+        let requested = match body.get("properties").and_then(|v| v.as_object()) {
+            Some(v) => v.clone(),
+            None => return validation_error("body.properties should be defined, instead was `undefined`."),
+        };
+        let mut properties = Map::new();
+        for (name, schema) in requested {
+            let id = format!("p{}", properties.len());
+            match property_definition(&name, &id, &schema) {
+                Ok(definition) => properties.insert(name, definition),
+                Err(e) => return e,
+            };
+        }
+        if properties.values().filter(|v| v["type"] == "title").count() != 1 {
+            return validation_error("Title is not provided");
+        }
+        let id = self.next_id();
+        let now = now();
+        let title : Vec<Value> = body.get("title").and_then(|v| v.as_array()).map(|v| v.iter().map(|t| plain_text(t.pointer("/text/content").and_then(|v| v.as_str()).unwrap_or_default())).collect()).unwrap_or_default();
+        let database = json!({
+            "object": "database",
+            "id": id,
+            "created_time": now,
+            "last_edited_time": now,
+            "title": title,
+            "parent": {"type": "page_id", "page_id": parent},
+            "url": format!("https://www.notion.so/{}", id.replace('-', "")),
+            "archived": false,
+            "properties": properties,
+        });
+        self.databases.insert(id, database.clone());
+        MockResponse::new(200, database)
+    }
+
+    /// Adds or changes properties. Select options that are not in the request are removed, like Notion does.
+    fn update_database(&mut self, id : &str, body : &Value) -> MockResponse {
+        let mut database = match self.databases.get(id) {
+            Some(v) => v.clone(),
+            None => return not_found("database", id),
+        };
+        let requested = body.get("properties").and_then(|v| v.as_object()).cloned().unwrap_or_default();
+        for (name, schema) in requested {
+            let properties = database["properties"].as_object_mut().unwrap();
+            if schema.is_null() {
+                properties.remove(&name);
+                continue;
+            }
+            let existing = find_property_entry(&Value::Object(properties.clone()), &name).map(|(k, v)| (k.clone(), v.clone()));
+            let (key, id) = match existing {
+                Some((key, definition)) => {
+                    let typ = definition["type"].as_str().unwrap_or_default().to_owned();
+                    if typ == "status" && schema.get("status").is_some() {
+                        return validation_error(&format!("Cannot update status property {} via the API.", name));
+                    }
+                    (key, definition["id"].as_str().unwrap_or_default().to_owned())
+                }
+                None => (name.clone(), self.next_id()),
+            };
+            let definition = match property_definition(&key, &id, &schema) {
+                Ok(v) => v,
+                Err(e) => return e,
+            };
+            properties.insert(key, definition);
+        }
+        database["last_edited_time"] = json!(now());
+        self.databases.insert(id.to_owned(), database.clone());
+        MockResponse::new(200, database)
+    }
+
+    fn get_database(&self, id : &str) -> MockResponse {
+        match self.databases.get(id) {
+            Some(db) => MockResponse::new(200, db.clone()),
+            None => not_found("database", id),
+        }
+    }
+
+    fn query_database(&self, id : &str, body : &Value) -> MockResponse {
+        if !self.databases.contains_key(id) {
+            return not_found("database", id);
+        }
+        let filter = body.get("filter");
+        let mut pages : Vec<&Value> = self.page_order.iter().filter_map(|v| self.pages.get(v)).filter(|page| page.pointer("/parent/database_id").and_then(|v| v.as_str()) == Some(id)).filter(|page| page.get("archived") != Some(&Value::Bool(true))).filter(|page| filter.map(|f| matches_filter(f, page)).unwrap_or(true)).collect();
+        if let Some(sorts) = body.get("sorts").and_then(|v| v.as_array()) {
+            pages.sort_by(|a, b| {
+                for sort in sorts {
+                    let ordering = sort_key(sort, a).cmp(&sort_key(sort, b));
+                    let ordering = if sort.get("direction").and_then(|v| v.as_str()) == Some("descending") {
+                        ordering.reverse()
+                    } else {
+                        ordering
+                    };
+                    if ordering != std::cmp::Ordering::Equal {
+                        return ordering;
+                    }
+                }
+                std::cmp::Ordering::Equal
+            });
+        }
+        let page_size = body.get("page_size").and_then(|v| v.as_u64()).map(|v| v as usize).unwrap_or(DEFAULT_PAGE_SIZE).min(DEFAULT_PAGE_SIZE);
+        let start = match body.get("start_cursor").and_then(|v| v.as_str()) {
+            Some(cursor) => pages.iter().position(|v| v.get("id").and_then(|v| v.as_str()) == Some(cursor)).unwrap_or(pages.len()),
+            None => 0,
+        };
+        let end = (start + page_size).min(pages.len());
+        let next_cursor = pages.get(end).and_then(|v| v.get("id")).and_then(|v| v.as_str()).map(|v| v.to_owned());
+        let results : Vec<Value> = pages[start..end].iter().map(|v| (*v).clone()).collect();
+        let mut list = list(results, next_cursor);
+        list["type"] = json!("page_or_database");
+        list.as_object_mut().unwrap().remove("block");
+        list["page_or_database"] = json!({});
+        MockResponse::new(200, list)
+    }
+
+    fn get_page(&self, id : &str) -> MockResponse {
+        match self.pages.get(id) {
+            Some(page) => MockResponse::new(200, page.clone()),
+            None => not_found("page", id),
+        }
+    }
+
+    fn update_page(&mut self, id : &str, body : &Value) -> MockResponse {
+        let page = match self.pages.get(id) {
+            Some(v) => v.clone(),
+            None => return not_found("page", id),
+        };
+        let archived = page.get("archived") == Some(&Value::Bool(true));
+        let unarchive = body.get("archived") == Some(&Value::Bool(false));
+        if archived && !unarchive {
+            return validation_error("Can't edit block that is archived. You must unarchive the block before editing.");
+        }
+        let database_id = page.pointer("/parent/database_id").and_then(|v| v.as_str()).unwrap_or_default();
+        let schema = self.databases.get(database_id).and_then(|db| db.get("properties").cloned()).unwrap_or_default();
+        let mut page = page;
+        if let Some(properties) = page.get_mut("properties").and_then(|v| v.as_object_mut()) {
+            if let Err(e) = apply_properties(&schema, properties, body.get("properties")) {
+                return e;
+            }
+        }
+        if let Some(archived) = body.get("archived").and_then(|v| v.as_bool()) {
+            page["archived"] = Value::Bool(archived);
+        }
+        page["last_edited_time"] = json!(now());
+        self.pages.insert(id.to_owned(), page.clone());
+        MockResponse::new(200, page)
+    }
+
+    fn create_page(&mut self, body : &Value) -> MockResponse {
+        let database_id = match body.pointer("/parent/database_id").and_then(|v| v.as_str()) {
+            Some(v) => v.to_owned(),
+            None => {
+                return validation_error("body.parent.database_id should be defined, instead was `undefined`.")
+            }
+        };
+        let schema = match self.databases.get(&database_id) {
+            Some(db) => db.get("properties").cloned().unwrap_or_default(),
+            None => return not_found("database", &database_id),
+        };
+        let properties = match self.build_properties(&schema, body.get("properties")) {
+            Ok(v) => v,
+            Err(e) => return e,
+        };
+        let children = body.get("children").and_then(|v| v.as_array()).cloned().unwrap_or_default();
+        if let Err(e) = validate_children(&children, "body.children") {
+            return e;
+        }
+        let id = self.next_id();
+        let now = now();
+        let page = json!({
+            "object": "page",
+            "id": id,
+            "created_time": now,
+            "last_edited_time": now,
+            "created_by": {"object": "user", "id": "mock-user"},
+            "last_edited_by": {"object": "user", "id": "mock-user"},
+            "cover": null,
+            "icon": null,
+            "parent": {"type": "database_id", "database_id": database_id},
+            "archived": false,
+            "properties": properties,
+            "url": format!("https://www.notion.so/{}", id.replace('-', "")),
+        });
+        self.pages.insert(id.clone(), page.clone());
+        self.page_order.push(id.clone());
+        self.children.insert(id.clone(), Vec::new());
+        for child in children {
+            self.store_block(&id, child);
+        }
+        MockResponse::new(200, page)
+    }
+
+    fn build_properties(&self, schema : &Value, values : Option<&Value>) -> Result<Value, MockResponse> {
+        let mut properties = Map::new();
+        // Properties without value are returned empty, status gets its default option
+        if let Some(schema) = schema.as_object() {
+            for (name, definition) in schema {
+                let typ = definition.get("type").and_then(|v| v.as_str()).unwrap_or_default();
+                properties.insert(
+                    name.clone(),
+                    json!({"id": definition.get("id").cloned().unwrap_or_default(), "type": typ, typ : empty_value(typ, definition)}),
+                );
+            }
+        }
+        apply_properties(schema, &mut properties, values)?;
+        Ok(Value::Object(properties))
+    }
+
+    fn store_block(&mut self, parent : &str, block : Value) -> Value {
+        let typ = block_type(&block);
+        let mut content = block.get(&typ).cloned().unwrap_or_else(|| json!({}));
+        let nested = match content.as_object_mut() {
+            Some(obj) => {
+                if let Some(rich_text) = obj.get("rich_text") {
+                    let rich_text = normalize_rich_text(rich_text);
+                    obj.insert("rich_text".to_owned(), rich_text);
+                }
+                obj.remove("children").and_then(|v| v.as_array().cloned()).unwrap_or_default()
+            }
+            None => Vec::new(),
+        };
+        let id = self.next_id();
+        let now = now();
+        let stored = json!({
+            "object": "block",
+            "id": id,
+            "parent": {"type": "block_id", "block_id": parent},
+            "created_time": now,
+            "last_edited_time": now,
+            "has_children": !nested.is_empty(),
+            "archived": false,
+            "type": typ,
+            typ.as_str(): content,
+        });
+        self.blocks.insert(id.clone(), stored.clone());
+        self.children.entry(parent.to_owned()).or_default().push(id.clone());
+        for child in nested {
+            self.store_block(&id, child);
+        }
+        stored
+    }
+
+    fn list_children(&self, id : &str, query : &BTreeMap<String, String>) -> MockResponse {
+        let ids = match self.children.get(id) {
+            Some(v) => v,
+            None => return not_found("block", id),
+        };
+        let page_size = query.get("page_size").and_then(|v| v.parse().ok()).unwrap_or(DEFAULT_PAGE_SIZE).min(DEFAULT_PAGE_SIZE);
+        let start = match query.get("start_cursor") {
+            Some(cursor) => ids.iter().position(|v| v == cursor).unwrap_or(ids.len()),
+            None => 0,
+        };
+        let end = (start + page_size).min(ids.len());
+        let results : Vec<Value> = ids[start..end].iter().filter_map(|v| self.blocks.get(v).cloned()).collect();
+        MockResponse::new(200, list(results, ids.get(end).cloned()))
+    }
+
+    fn append_children(&mut self, id : &str, body : &Value) -> MockResponse {
+        if !self.children.contains_key(id) && !self.blocks.contains_key(id) {
+            return not_found("block", id);
+        }
+        let children = match body.get("children").and_then(|v| v.as_array()) {
+            Some(v) => v.clone(),
+            None => return validation_error("body.children should be defined, instead was `undefined`."),
+        };
+        if let Err(e) = validate_children(&children, "body.children") {
+            return e;
+        }
+        if self.pages.get(id).and_then(|v| v.get("archived")) == Some(&Value::Bool(true)) {
+            return validation_error("Can't edit block that is archived. You must unarchive the block before editing.");
+        }
+        if let Some(block) = self.blocks.get_mut(id) {
+            block["has_children"] = Value::Bool(true);
+        }
+        let results : Vec<Value> = children.into_iter().map(|child| self.store_block(id, child)).collect();
+        MockResponse::new(200, list(results, None))
+    }
+}
+
+fn list(results : Vec<Value>, next_cursor : Option<String>) -> Value {
+    json!({
+        "object": "list",
+        "results": results,
+        "has_more": next_cursor.is_some(),
+        "next_cursor": next_cursor,
+        "type": "block",
+        "block": {}
+    })
+}
+
+/// Validates the property values against the database schema and stores them in `properties`
+fn apply_properties(schema : &Value, properties : &mut Map<String, Value>, values : Option<&Value>) -> Result<(), MockResponse> {
+    let values = match values.and_then(|v| v.as_object()) {
+        Some(v) => v,
+        None => return Ok(()),
+    };
+    for (name, value) in values {
+        let (key, definition) = match find_property_entry(schema, name) {
+            Some(v) => v,
+            None => {
+                return Err(validation_error(&format!(
+                    "{} is not a property that exists.",
+                    name
+                )))
+            }
+        };
+        let typ = definition.get("type").and_then(|v| v.as_str()).unwrap_or_default();
+        let content = match value.get(typ) {
+            Some(v) => v.clone(),
+            None => {
+                return Err(validation_error(&format!(
+                    "{} is expected to be {}.",
+                    name, typ
+                )))
+            }
+        };
+        let content = match typ {
+            "title" | "rich_text" => normalize_rich_text(&content),
+            "status" => {
+                let option = content.get("name").and_then(|n| {
+                    definition.pointer("/status/options").and_then(|v| v.as_array()).and_then(|options| options.iter().find(|o| o.get("name") == Some(n)))
+                });
+                match option {
+                    Some(v) => v.clone(),
+                    None => {
+                        return Err(validation_error(&format!(
+                            "Invalid status option. Status option \"{}\" does not exist\".",
+                            content.get("name").and_then(|v| v.as_str()).unwrap_or_default()
+                        )))
+                    }
+                }
+            }
+            _ => content,
+        };
+        properties.insert(
+            key.clone(),
+            json!({"id": definition.get("id").cloned().unwrap_or_default(), "type": typ, typ : content}),
+        );
+    }
+    Ok(())
+}
+
+fn empty_value(typ : &str, definition : &Value) -> Value {
+    match typ {
+        "title" | "rich_text" | "multi_select" | "people" | "relation" | "files" => json!([]),
+        "checkbox" => json!(false),
+        "status" => definition.pointer("/status/options/0").cloned().unwrap_or(Value::Null),
+        _ => Value::Null,
+    }
+}
+
+/// Evaluates a database query filter against a stored page
+fn matches_filter(filter : &Value, page : &Value) -> bool {
+    if let Some(filters) = filter.get("and").and_then(|v| v.as_array()) {
+        return filters.iter().all(|f| matches_filter(f, page));
+    }
+    if let Some(filters) = filter.get("or").and_then(|v| v.as_array()) {
+        return filters.iter().any(|f| matches_filter(f, page));
+    }
+    let obj = match filter.as_object() {
+        Some(v) => v,
+        None => return false,
+    };
+    if let Some(timestamp) = obj.get("timestamp").and_then(|v| v.as_str()) {
+        let value = page.get(timestamp).cloned().unwrap_or(Value::Null);
+        return match obj.get(timestamp) {
+            Some(condition) => matches_condition("date", condition, &json!({"start": value})),
+            None => false,
+        };
+    }
+    let property = obj.get("property").and_then(|v| v.as_str()).unwrap_or_default();
+    let (typ, condition) = match obj.iter().find(|(k, _)| *k != "property") {
+        Some(v) => v,
+        None => return false,
+    };
+    let value = page.get("properties").and_then(|props| find_property(props, property)).and_then(|v| v.get(typ)).cloned().unwrap_or(Value::Null);
+    matches_condition(typ, condition, &value)
+}
+
+fn matches_condition(typ : &str, condition : &Value, value : &Value) -> bool {
+    let (operator, argument) = match condition.as_object().and_then(|v| v.iter().next()) {
+        Some(v) => v,
+        None => return false,
+    };
+    match typ {
+        "title" | "rich_text" => {
+            let text = rich_text_content(value);
+            let argument = argument.as_str().unwrap_or_default();
+            match operator.as_str() {
+                "equals" => text == argument,
+                "does_not_equal" => text != argument,
+                "contains" => text.contains(argument),
+                "does_not_contain" => !text.contains(argument),
+                "starts_with" => text.starts_with(argument),
+                "ends_with" => text.ends_with(argument),
+                "is_empty" => text.is_empty(),
+                "is_not_empty" => !text.is_empty(),
+                _ => false,
+            }
+        }
+        "select" | "status" => {
+            let name = value.get("name").and_then(|v| v.as_str());
+            match operator.as_str() {
+                "equals" => name == argument.as_str(),
+                "does_not_equal" => name != argument.as_str(),
+                "is_empty" => name.is_none(),
+                "is_not_empty" => name.is_some(),
+                _ => false,
+            }
+        }
+        "multi_select" => {
+            let names : Vec<&str> = value.as_array().map(|v| v.iter().filter_map(|o| o.get("name").and_then(|n| n.as_str())).collect()).unwrap_or_default();
+            match operator.as_str() {
+                "contains" => names.iter().any(|n| Some(*n) == argument.as_str()),
+                "does_not_contain" => !names.iter().any(|n| Some(*n) == argument.as_str()),
+                "is_empty" => names.is_empty(),
+                "is_not_empty" => !names.is_empty(),
+                _ => false,
+            }
+        }
+        "date" => {
+            let start = value.get("start").and_then(|v| v.as_str()).map(parse_date);
+            let argument = argument.as_str().map(parse_date);
+            match (operator.as_str(), start, argument) {
+                ("is_empty", start, _) => start.is_none(),
+                ("is_not_empty", start, _) => start.is_some(),
+                ("equals", Some(a), Some(b)) => a == b,
+                ("before", Some(a), Some(b)) => a < b,
+                ("after", Some(a), Some(b)) => a > b,
+                ("on_or_before", Some(a), Some(b)) => a <= b,
+                ("on_or_after", Some(a), Some(b)) => a >= b,
+                _ => false,
+            }
+        }
+        "number" => {
+            let number = value.as_f64();
+            let argument = argument.as_f64();
+            match (operator.as_str(), number, argument) {
+                ("is_empty", number, _) => number.is_none(),
+                ("is_not_empty", number, _) => number.is_some(),
+                ("equals", Some(a), Some(b)) => a == b,
+                ("does_not_equal", Some(a), Some(b)) => a != b,
+                ("greater_than", Some(a), Some(b)) => a > b,
+                ("less_than", Some(a), Some(b)) => a < b,
+                ("greater_than_or_equal_to", Some(a), Some(b)) => a >= b,
+                ("less_than_or_equal_to", Some(a), Some(b)) => a <= b,
+                _ => false,
+            }
+        }
+        "checkbox" => match operator.as_str() {
+            "equals" => value.as_bool().unwrap_or(false) == argument.as_bool().unwrap_or(false),
+            "does_not_equal" => value.as_bool().unwrap_or(false) != argument.as_bool().unwrap_or(false),
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// Dates are compared as milliseconds when they can be parsed
+fn parse_date(date : &str) -> i64 {
+    if let Ok(v) = usiem::chrono::DateTime::parse_from_rfc3339(date) {
+        return v.timestamp_millis();
+    }
+    if let Ok(v) = usiem::chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d") {
+        return v.and_hms_opt(0, 0, 0).map(|v| v.and_utc().timestamp_millis()).unwrap_or(0);
+    }
+    0
+}
+
+fn rich_text_content(value : &Value) -> String {
+    value.as_array().map(|v| {
+            v.iter().filter_map(|t| t.get("plain_text").and_then(|p| p.as_str())).collect()
+        }).unwrap_or_default()
+}
+
+/// Value used to sort pages, dates are sorted by their text
+fn sort_key(sort : &Value, page : &Value) -> String {
+    if let Some(timestamp) = sort.get("timestamp").and_then(|v| v.as_str()) {
+        return page.get(timestamp).and_then(|v| v.as_str()).unwrap_or_default().to_owned();
+    }
+    let property = sort.get("property").and_then(|v| v.as_str()).unwrap_or_default();
+    let value = match page.get("properties").and_then(|props| find_property(props, property)) {
+        Some(v) => v,
+        None => return String::new(),
+    };
+    let typ = value.get("type").and_then(|v| v.as_str()).unwrap_or_default();
+    let content = value.get(typ).cloned().unwrap_or(Value::Null);
+    match typ {
+        "title" | "rich_text" => rich_text_content(&content),
+        "select" | "status" => content.get("name").and_then(|v| v.as_str()).unwrap_or_default().to_owned(),
+        "date" => content.get("start").and_then(|v| v.as_str()).unwrap_or_default().to_owned(),
+        "number" => format!("{:020.6}", content.as_f64().unwrap_or(0.0)),
+        _ => content.to_string(),
+    }
+}
+
+/// Definition of a property from the schema of a create or update request
+fn property_definition(name : &str, id : &str, schema : &Value) -> Result<Value, MockResponse> {
+    let (typ, config) = match schema.as_object().and_then(|v| v.iter().find(|(k, _)| *k != "name" && *k != "type")) {
+        Some((typ, config)) => (typ.clone(), config.clone()),
+        None => return Err(validation_error(&format!("body.properties.{} should be a property schema.", name))),
+    };
+    validate_property_schema(name, &typ)?;
+    let id = if typ == "title" { "title" } else { id };
+    let config = with_option_ids(&typ, config);
+    Ok(json!({"id": id, "name": name, "type": typ, typ.as_str(): config}))
+}
+
+fn validate_property_schema(name : &str, typ : &str) -> Result<(), MockResponse> {
+    match typ {
+        "title" | "rich_text" | "number" | "select" | "multi_select" | "date" | "people" | "files" | "checkbox"
+        | "url" | "email" | "phone_number" | "formula" | "relation" | "rollup" | "created_time" | "created_by"
+        | "last_edited_time" | "last_edited_by" => Ok(()),
+        "status" => Err(validation_error(&format!(
+            "Cannot create new status property {}. Status properties cannot be created via the API.",
+            name
+        ))),
+        _ => Err(validation_error(&format!("body.properties.{} has an invalid type {}.", name, typ))),
+    }
+}
+
+/// Select options get an id, and the "default" color if it was not given
+fn with_option_ids(typ : &str, mut config : Value) -> Value {
+    if typ != "select" && typ != "multi_select" {
+        return config;
+    }
+    if let Some(options) = config.get_mut("options").and_then(|v| v.as_array_mut()) {
+        for (i, option) in options.iter_mut().enumerate() {
+            option["id"] = Value::String(format!("o{}", i));
+            if option.get("color").is_none() {
+                option["color"] = "default".into();
+            }
+        }
+    } else {
+        config["options"] = json!([]);
+    }
+    config
+}
+
+fn not_found(object : &str, id : &str) -> MockResponse {
+    MockResponse::error(
+        404,
+        "object_not_found",
+        &format!(
+            "Could not find {} with ID: {}. Make sure the relevant pages and databases are shared with your integration.",
+            object, id
+        ),
+    )
+}
+
+fn validation_error(message : &str) -> MockResponse {
+    MockResponse::error(400, "validation_error", &format!("body failed validation: {}", message))
+}
+
+fn find_property<'a>(schema : &'a Value, name : &str) -> Option<&'a Value> {
+    find_property_entry(schema, name).map(|(_, v)| v)
+}
+
+/// Finds a property by name or id
+fn find_property_entry<'a>(schema : &'a Value, name : &str) -> Option<(&'a String, &'a Value)> {
+    let schema = schema.as_object()?;
+    match schema.get_key_value(name) {
+        Some(v) => Some(v),
+        None => schema.iter().find(|(_, v)| v.get("id").and_then(|v| v.as_str()) == Some(name)),
+    }
+}
+
+fn block_type(block : &Value) -> String {
+    if let Some(typ) = block.get("type").and_then(|v| v.as_str()) {
+        return typ.to_owned();
+    }
+    block.as_object().and_then(|obj| obj.keys().find(|k| *k != "object").cloned()).unwrap_or_default()
+}
+
+fn normalize_rich_text(rich_text : &Value) -> Value {
+    let items = match rich_text.as_array() {
+        Some(v) => v,
+        None => return rich_text.clone(),
+    };
+    Value::Array(
+        items.iter().map(|item| {
+                let mut item = item.clone();
+                let content = item.pointer("/text/content").or_else(|| item.pointer("/equation/expression")).and_then(|v| v.as_str()).unwrap_or_default().to_owned();
+                if let Some(obj) = item.as_object_mut() {
+                    obj.entry("type").or_insert_with(|| json!("text"));
+                    obj.entry("annotations").or_insert_with(default_annotations);
+                    obj.entry("plain_text").or_insert_with(|| json!(content));
+                    obj.entry("href").or_insert(Value::Null);
+                }
+                item
+            }).collect(),
+    )
+}
+
+fn validate_children(children : &[Value], path : &str) -> Result<(), MockResponse> {
+    if children.len() > MAX_CHILDREN {
+        return Err(validation_error(&format!(
+            "{}.length should be ≤ `{}`, instead was `{}`.",
+            path,
+            MAX_CHILDREN,
+            children.len()
+        )));
+    }
+    for (i, child) in children.iter().enumerate() {
+        let typ = block_type(child);
+        let content = match child.get(&typ) {
+            Some(v) => v,
+            None => continue,
+        };
+        if let Some(rich_text) = content.get("rich_text").and_then(|v| v.as_array()) {
+            for (j, text) in rich_text.iter().enumerate() {
+                let length = text.pointer("/text/content").and_then(|v| v.as_str()).map(|v| v.chars().count()).unwrap_or(0);
+                if length > MAX_TEXT_LENGTH {
+                    return Err(validation_error(&format!(
+                        "{}[{}].{}.rich_text[{}].text.content.length should be ≤ `{}`, instead was `{}`.",
+                        path, i, typ, j, MAX_TEXT_LENGTH, length
+                    )));
+                }
+            }
+        }
+        if let Some(nested) = content.get("children").and_then(|v| v.as_array()) {
+            validate_children(nested, &format!("{}[{}].{}.children", path, i, typ))?;
+        }
+    }
+    Ok(())
+}
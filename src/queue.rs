@@ -0,0 +1,367 @@
+use std::borrow::Cow;
+use std::collections::{BTreeSet, VecDeque};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use usiem::components::common::SiemComponentStateStorage;
+use usiem::prelude::alert::SiemAlert;
+use usiem::serde_json::{self, Value};
+
+use crate::aggregation::{is_aggregable, AggregatedAlert};
+use crate::api::block::BlockElement;
+use crate::api::page::{DatabaseParent, PageElement};
+use crate::client::alert_date;
+use crate::error::IncompletePage;
+use crate::retry::RetryPolicy;
+
+/// Storage key of the ids of the queued alerts. Each alert is stored in `notion_queue:<id>`.
+const STORAGE_KEY : &str = "notion_queue";
+/// The oldest alerts are discarded when the queue is full
+pub const MAX_QUEUED_ALERTS : usize = 1000;
+
+/// Alert that could not be sent to Notion
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct PendingAlert {
+    pub title : String,
+    pub rule : String,
+    /// Tenant of the alert, whose API key is used to send the page
+    #[serde(default)]
+    pub tenant : String,
+    /// Body of the page creation request. SiemAlert cannot be deserialized, so the page is stored instead.
+    pub page : Value,
+    /// Page already created for the alert: only the blocks of `page` are appended to it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub page_id : Option<String>,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub url : String,
+    /// Aggregation key of an aggregable alert, whose page is registered when it is created
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub aggr_key : String,
+    #[serde(default)]
+    pub aggr_limit : i64,
+    /// New occurrence of an alert in its open page `page_id`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub occurrence : Option<PendingOccurrence>,
+    /// Failed delivery attempts
+    pub attempts : u32,
+    /// Time (milliseconds) of the next delivery attempt
+    pub next_attempt : i64,
+    /// Id of the alert in the queue storage, assigned when it is queued
+    #[serde(skip)]
+    pub id : u64,
+}
+
+/// Occurrence of an aggregated alert. Its Occurrences and Last seen properties are updated after its blocks are appended.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct PendingOccurrence {
+    pub number : u64,
+    pub last_seen : String,
+}
+
+impl PendingAlert {
+    /// New page of the alert. `aggr_key` is the key of its page if the alert is aggregable.
+    pub fn new(alert : &SiemAlert, aggr_key : &str, page : &PageElement, now : i64) -> Self {
+        let aggregable = is_aggregable(alert);
+        Self {
+            title : alert.title.clone(),
+            rule : alert.rule.clone(),
+            tenant : alert.log.tenant().to_owned(),
+            page : serde_json::to_value(page).unwrap_or(Value::Null),
+            page_id : None,
+            url : String::new(),
+            aggr_key : if aggregable { aggr_key.to_owned() } else { String::new() },
+            aggr_limit : if aggregable { alert.aggr_limit } else { 0 },
+            occurrence : None,
+            attempts : 0,
+            next_attempt : now,
+            id : 0,
+        }
+    }
+
+    /// Occurrence `entry.occurrences` of the alert in the open page of its `aggr_key`. `blocks` are the blocks
+    /// not appended yet to the page.
+    pub fn occurrence(alert : &SiemAlert, aggr_key : &str, entry : &AggregatedAlert, database_id : &str, blocks : &[BlockElement], now : i64) -> Self {
+        let page = PageElement {
+            parent : DatabaseParent { database_id : database_id.to_owned() },
+            ..Default::default()
+        };
+        let mut pending = Self::new(alert, aggr_key, &page, now);
+        pending.page["children"] = serde_json::to_value(blocks).unwrap_or(Value::Null);
+        pending.page_id = Some(entry.page_id.clone());
+        pending.url = entry.url.clone();
+        pending.occurrence = Some(PendingOccurrence {
+            number : entry.occurrences,
+            last_seen : alert_date(alert),
+        });
+        pending
+    }
+
+    pub fn page(&self) -> Result<PageElement, serde_json::Error> {
+        serde_json::from_value(self.page.clone())
+    }
+
+    /// Database where the page is created
+    pub fn database_id(&self) -> &str {
+        self.page["parent"]["database_id"].as_str().unwrap_or_default()
+    }
+
+    /// The page was created without all its blocks: the next attempts only append the remaining ones
+    pub fn set_incomplete(&mut self, incomplete : &IncompletePage) {
+        self.page_id = Some(incomplete.page_id.clone());
+        self.url = incomplete.url.clone();
+        self.page["children"] = serde_json::to_value(&incomplete.remaining).unwrap_or(Value::Null);
+    }
+
+    /// The blocks were appended to the page `page_id`: the next attempts only update its properties
+    pub fn set_appended(&mut self) {
+        self.page["children"] = Value::Array(Vec::new());
+    }
+}
+
+/// Alerts waiting to be sent to Notion. The queue is saved in the component state storage,
+/// so the alerts are replayed when the component restarts. Each alert is stored in its own key
+/// and only the alerts that changed are written again.
+///
+/// Queued alerts are retried with the backoff of the policy until they are delivered or
+/// `max_retries` is reached.
+#[derive(Debug, Clone)]
+pub struct AlertQueue {
+    pending : VecDeque<PendingAlert>,
+    policy : RetryPolicy,
+    next_id : u64,
+    /// Alerts queued or rescheduled since the last persist
+    changed : BTreeSet<u64>,
+    /// Alerts in the storage
+    stored : BTreeSet<u64>,
+}
+
+impl Default for AlertQueue {
+    fn default() -> Self {
+        Self::new(Self::default_policy())
+    }
+}
+
+impl AlertQueue {
+    pub fn new(policy : RetryPolicy) -> Self {
+        Self {
+            pending : VecDeque::new(),
+            policy,
+            next_id : 1,
+            changed : BTreeSet::new(),
+            stored : BTreeSet::new(),
+        }
+    }
+
+    /// Retries every 10 seconds at first, up to every 10 minutes, without limit
+    pub fn default_policy() -> RetryPolicy {
+        RetryPolicy {
+            max_retries : u32::MAX,
+            base_delay : Duration::from_secs(10),
+            max_delay : Duration::from_secs(600),
+        }
+    }
+
+    /// Restores the alerts saved by a previous run. They are sent as soon as the component starts.
+    pub fn load(&mut self, storage : &dyn SiemComponentStateStorage) {
+        let ids = match storage.get_value(Cow::Borrowed(STORAGE_KEY)).ok().and_then(|v| serde_json::from_str::<Vec<u64>>(&v).ok()) {
+            Some(v) => v,
+            None => return
+        };
+        for id in ids {
+            let stored = storage.get_value(entry_key(id)).ok().and_then(|v| serde_json::from_str::<PendingAlert>(&v).ok());
+            if let Some(mut pending) = stored {
+                pending.id = id;
+                pending.next_attempt = 0;
+                self.next_id = self.next_id.max(id + 1);
+                self.stored.insert(id);
+                self.pending.push_back(pending);
+            }
+        }
+    }
+
+    /// Saves the alerts queued or rescheduled since the last call and removes the ones that left the
+    /// queue. Alerts taken with `take_due` must be restored before.
+    pub fn persist(&mut self, storage : &mut dyn SiemComponentStateStorage) {
+        for pending in &self.pending {
+            if !self.changed.contains(&pending.id) {
+                continue;
+            }
+            if let Ok(value) = serde_json::to_string(pending) {
+                let _ = storage.set_value(entry_key(pending.id), value, true);
+            }
+        }
+        self.changed.clear();
+        let ids : BTreeSet<u64> = self.pending.iter().map(|v| v.id).collect();
+        if ids == self.stored {
+            return;
+        }
+        for removed in self.stored.difference(&ids) {
+            let _ = storage.set_value(entry_key(*removed), String::new(), true);
+        }
+        let order : Vec<u64> = self.pending.iter().map(|v| v.id).collect();
+        if let Ok(value) = serde_json::to_string(&order) {
+            let _ = storage.set_value(Cow::Borrowed(STORAGE_KEY), value, true);
+        }
+        self.stored = ids;
+    }
+
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Queues the alert. Returns the alert discarded to make room for it, if the queue was full.
+    pub fn push(&mut self, mut pending : PendingAlert) -> Option<PendingAlert> {
+        pending.id = self.next_id;
+        self.next_id += 1;
+        self.changed.insert(pending.id);
+        let discarded = if self.pending.len() >= MAX_QUEUED_ALERTS {
+            self.pending.pop_front()
+        } else {
+            None
+        };
+        self.pending.push_back(pending);
+        discarded
+    }
+
+    /// Time of the earliest delivery attempt
+    pub fn next_attempt(&self) -> Option<i64> {
+        self.pending.iter().map(|v| v.next_attempt).min()
+    }
+
+    /// Removes the alerts that must be sent now, in the order they were queued
+    pub fn take_due(&mut self, now : i64) -> Vec<PendingAlert> {
+        let (due, waiting) : (VecDeque<PendingAlert>, VecDeque<PendingAlert>) = std::mem::take(&mut self.pending).into_iter().partition(|v| v.next_attempt <= now);
+        self.pending = waiting;
+        due.into()
+    }
+
+    /// Puts back alerts that were taken but not attempted
+    pub fn restore(&mut self, alerts : Vec<PendingAlert>) {
+        for alert in alerts.into_iter().rev() {
+            self.pending.push_front(alert);
+        }
+    }
+
+    /// Schedules a new attempt after a failed one. Returns false if the alert reached `max_retries`
+    /// and must be discarded.
+    pub fn reschedule(&mut self, pending : &mut PendingAlert, now : i64) -> bool {
+        self.changed.insert(pending.id);
+        pending.next_attempt = now + self.policy.backoff(pending.attempts).as_millis() as i64;
+        pending.attempts = pending.attempts.saturating_add(1);
+        pending.attempts <= self.policy.max_retries
+    }
+}
+
+fn entry_key(id : u64) -> Cow<'static, str> {
+    Cow::Owned(format!("{}:{}", STORAGE_KEY, id))
+}
+
+#[cfg(test)]
+mod pending {
+    use super::*;
+    use crate::mock::MemoryStateStorage;
+    use usiem::serde_json::json;
+
+    fn pending(title : &str, next_attempt : i64) -> PendingAlert {
+        PendingAlert {
+            title : title.to_owned(),
+            rule : "ruleset::example::rule1".to_owned(),
+            tenant : String::new(),
+            page : json!({"parent": {"database_id": "d9824bdc"}, "properties": {}}),
+            page_id : None,
+            url : String::new(),
+            aggr_key : String::new(),
+            aggr_limit : 0,
+            occurrence : None,
+            attempts : 0,
+            next_attempt,
+            id : 0
+        }
+    }
+
+    fn policy() -> RetryPolicy {
+        RetryPolicy {
+            max_retries : 2,
+            base_delay : Duration::from_millis(100),
+            max_delay : Duration::from_millis(100),
+        }
+    }
+
+    #[test]
+    fn should_take_due_alerts_in_order() {
+        let mut queue = AlertQueue::new(policy());
+        queue.push(pending("a", 10));
+        queue.push(pending("b", 30));
+        queue.push(pending("c", 20));
+        assert_eq!(Some(10), queue.next_attempt());
+        let due : Vec<String> = queue.take_due(20).into_iter().map(|v| v.title).collect();
+        assert_eq!(vec!["a", "c"], due);
+        assert_eq!(1, queue.len());
+        let due = queue.take_due(30);
+        queue.restore(due);
+        assert_eq!(Some(30), queue.next_attempt());
+    }
+
+    #[test]
+    fn should_back_off_until_max_retries() {
+        let mut queue = AlertQueue::new(policy());
+        let mut retried = pending("a", 0);
+        assert!(queue.reschedule(&mut retried, 1000));
+        assert_eq!(1, retried.attempts);
+        assert!(retried.next_attempt >= 1050 && retried.next_attempt <= 1100);
+        assert!(queue.reschedule(&mut retried, 1000));
+        assert!(!queue.reschedule(&mut retried, 1000));
+    }
+
+    #[test]
+    fn should_discard_oldest_when_full() {
+        let mut queue = AlertQueue::new(policy());
+        for i in 0..MAX_QUEUED_ALERTS {
+            assert!(queue.push(pending(&i.to_string(), 0)).is_none());
+        }
+        assert_eq!(Some("0".to_owned()), queue.push(pending("new", 0)).map(|v| v.title));
+        assert_eq!(MAX_QUEUED_ALERTS, queue.len());
+    }
+
+    #[test]
+    fn should_persist_queue() {
+        let mut storage = MemoryStateStorage::new();
+        let mut queue = AlertQueue::new(policy());
+        queue.push(pending("a", 10));
+        queue.persist(&mut storage);
+        let mut restarted = AlertQueue::new(policy());
+        restarted.load(&storage);
+        let mut expected = pending("a", 0);
+        expected.id = 1;
+        assert_eq!(vec![expected], restarted.take_due(0));
+    }
+
+    #[test]
+    fn should_persist_only_changed_alerts() {
+        let mut storage = MemoryStateStorage::new();
+        let mut queue = AlertQueue::new(policy());
+        queue.push(pending("a", 10));
+        queue.push(pending("b", 20));
+        queue.persist(&mut storage);
+        storage.set_value(Cow::Borrowed("notion_queue:2"), "untouched".to_owned(), true).unwrap();
+
+        // "a" is delivered and "c" is queued
+        queue.take_due(10);
+        queue.push(pending("c", 30));
+        queue.persist(&mut storage);
+        assert_eq!(Ok("[2,3]".to_owned()), storage.get_value(Cow::Borrowed("notion_queue")).map_err(|_| ()));
+        assert_eq!(Ok(String::new()), storage.get_value(Cow::Borrowed("notion_queue:1")).map_err(|_| ()));
+        assert_eq!(Ok("untouched".to_owned()), storage.get_value(Cow::Borrowed("notion_queue:2")).map_err(|_| ()));
+
+        // "b" is rescheduled
+        let mut due = queue.take_due(20);
+        queue.reschedule(&mut due[0], 1000);
+        queue.restore(due);
+        queue.persist(&mut storage);
+        let mut restarted = AlertQueue::new(policy());
+        restarted.load(&storage);
+        let due = restarted.take_due(0);
+        assert_eq!(vec!["b", "c"], due.iter().map(|v| v.title.as_str()).collect::<Vec<_>>());
+        assert_eq!(1, due[0].attempts);
+    }
+}
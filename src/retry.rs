@@ -0,0 +1,108 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::Duration;
+
+use crate::error::NotionError;
+
+/// How the client retries requests that failed because of rate limits, server errors or connection problems.
+///
+/// Rate limited requests wait for the time given in the `Retry-After` header, up to `max_delay`, the
+/// other retriable errors use an exponential backoff with jitter. Permanent errors are returned without retrying.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Retries after the first attempt
+    pub max_retries : u32,
+    /// Delay before the first retry, doubled on each attempt
+    pub base_delay : Duration,
+    /// Upper bound of the backoff delay and of the `Retry-After` wait
+    pub max_delay : Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries : 5,
+            base_delay : Duration::from_millis(500),
+            max_delay : Duration::from_secs(30),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Never retry
+    pub fn none() -> Self {
+        Self {
+            max_retries : 0,
+            base_delay : Duration::ZERO,
+            max_delay : Duration::ZERO,
+        }
+    }
+
+    /// Exponential backoff with "equal jitter": a random delay between half and the full exponential delay
+    pub fn backoff(&self, attempt : u32) -> Duration {
+        let exponential = self.base_delay.saturating_mul(2u32.saturating_pow(attempt)).min(self.max_delay);
+        let half = exponential / 2;
+        let jitter_range = (exponential - half).as_millis() as u64;
+        if jitter_range == 0 {
+            return exponential;
+        }
+        half + Duration::from_millis(random() % (jitter_range + 1))
+    }
+
+    /// Time to wait before retrying a failed request, `None` if it must not be retried. The wait blocks
+    /// the component, so a `Retry-After` longer than `max_delay` is cut to `max_delay`.
+    pub fn delay_for(&self, error : &NotionError, attempt : u32) -> Option<Duration> {
+        if attempt >= self.max_retries || !error.is_retriable() {
+            return None;
+        }
+        Some(match error.retry_after() {
+            Some(retry_after) => retry_after.min(self.max_delay),
+            None => self.backoff(attempt)
+        })
+    }
+}
+
+fn random() -> u64 {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u128(std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap_or_default().as_nanos());
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod backoff {
+    use std::time::Duration;
+    use usiem::serde_json::{self, json};
+    use crate::error::{NotionApiError, NotionError};
+    use super::RetryPolicy;
+
+    #[test]
+    fn should_grow_exponentially_with_jitter() {
+        let policy = RetryPolicy {
+            max_retries : 10,
+            base_delay : Duration::from_millis(100),
+            max_delay : Duration::from_secs(2),
+        };
+        for attempt in 0..10 {
+            let expected = Duration::from_millis(100 * 2u64.pow(attempt)).min(Duration::from_secs(2));
+            let delay = policy.backoff(attempt);
+            assert!(delay <= expected);
+            assert!(delay >= expected / 2);
+        }
+    }
+
+    #[test]
+    fn should_not_wait_without_delay() {
+        assert_eq!(Duration::ZERO, RetryPolicy::none().backoff(3));
+    }
+
+    #[test]
+    fn should_cap_retry_after() {
+        let policy = RetryPolicy::default();
+        let mut error : NotionApiError = serde_json::from_value(json!({"status": 429, "code": "rate_limited", "message": ""})).unwrap();
+        error.retry_after = Some(Duration::from_secs(2));
+        assert_eq!(Some(Duration::from_secs(2)), policy.delay_for(&NotionError::Api(error.clone()), 0));
+        error.retry_after = Some(Duration::from_secs(3600));
+        assert_eq!(Some(policy.max_delay), policy.delay_for(&NotionError::Api(error.clone()), 0));
+        assert_eq!(None, policy.delay_for(&NotionError::Api(error), policy.max_retries));
+    }
+}
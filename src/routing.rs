@@ -0,0 +1,239 @@
+//! Rules that choose the databases where each alert is sent.
+//!
+//! The rules are a JSON array stored in the `rules` key of the `NotionAlerterRoutes` dataset. They are
+//! evaluated in order and the first rule that matches the alert decides its destinations: one or more
+//! databases, or none to drop the alert. Alerts that do not match any rule go to the default database
+//! of their tenant.
+//!
+//! ```json
+//! [
+//!     {"rule": "ruleset::example::*", "severity": "High", "to": ["5a0b6f4e-7c2d-4b8e-9f1a-3c6d8e2b4a70", "default"]},
+//!     {"severity": "Informational", "tags": ["Noise"], "to": []}
+//! ]
+//! ```
+
+use std::convert::TryFrom;
+
+use serde::Deserialize;
+use usiem::prelude::alert::{AlertSeverity, SiemAlert};
+use usiem::prelude::dataset::text_map::TextMapSynDataset;
+use usiem::serde_json;
+
+/// Key of the dataset with the rules
+pub const RULES_KEY : &str = "rules";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(try_from = "String")]
+pub enum Severity {
+    Informational,
+    Low,
+    Medium,
+    High,
+    Critical,
+}
+
+impl From<&AlertSeverity> for Severity {
+    fn from(severity : &AlertSeverity) -> Self {
+        match severity {
+            AlertSeverity::INFORMATIONAL => Severity::Informational,
+            AlertSeverity::LOW => Severity::Low,
+            AlertSeverity::MEDIUM => Severity::Medium,
+            AlertSeverity::HIGH => Severity::High,
+            AlertSeverity::CRITICAL => Severity::Critical,
+        }
+    }
+}
+
+impl TryFrom<String> for Severity {
+    type Error = String;
+
+    fn try_from(value : String) -> Result<Self, Self::Error> {
+        match &value.to_lowercase()[..] {
+            "informational" => Ok(Severity::Informational),
+            "low" => Ok(Severity::Low),
+            "medium" => Ok(Severity::Medium),
+            "high" => Ok(Severity::High),
+            "critical" => Ok(Severity::Critical),
+            _ => Err(format!("unknown severity '{}'", value))
+        }
+    }
+}
+
+/// Database where a rule sends the alert
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(from = "String")]
+pub enum RouteTarget {
+    /// "default": the database of the tenant of the alert, or DATABASE_ID
+    Default,
+    Database(String),
+}
+
+impl From<String> for RouteTarget {
+    fn from(value : String) -> Self {
+        if value == "default" {
+            RouteTarget::Default
+        } else {
+            RouteTarget::Database(value)
+        }
+    }
+}
+
+/// Conditions of a rule. The alert must match all the conditions that are set.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct RoutingRule {
+    /// Minimum severity of the alert
+    #[serde(default)]
+    pub severity : Option<Severity>,
+    /// Rule of the alert. A trailing `*` matches the rules that start with the prefix, like `ruleset::example::*`
+    #[serde(default)]
+    pub rule : Option<String>,
+    /// The alert has any of these tags
+    #[serde(default)]
+    pub tags : Vec<String>,
+    /// The alert has any of these MITRE techniques. A technique also matches its sub-techniques:
+    /// "T1003" matches "T1003.001", which can also be written "T1003_001".
+    #[serde(default)]
+    pub techniques : Vec<String>,
+    /// Destinations of the alert. The alert is dropped if there are none.
+    pub to : Vec<RouteTarget>,
+}
+
+impl RoutingRule {
+    pub fn matches(&self, alert : &SiemAlert) -> bool {
+        if let Some(severity) = self.severity {
+            if Severity::from(&alert.severity) < severity {
+                return false;
+            }
+        }
+        if let Some(rule) = &self.rule {
+            let matched = match rule.strip_suffix('*') {
+                Some(prefix) => alert.rule.starts_with(prefix),
+                None => &alert.rule == rule
+            };
+            if !matched {
+                return false;
+            }
+        }
+        if !self.tags.is_empty() && !alert.tags.iter().any(|tag| self.tags.contains(tag)) {
+            return false;
+        }
+        if !self.techniques.is_empty() {
+            let techniques : Vec<String> = alert.techniques.iter().map(|v| format!("{:?}", v)).collect();
+            let matched = self.techniques.iter().map(|v| v.replace('.', "_")).any(|expected| {
+                techniques.iter().any(|technique| technique == &expected || technique.starts_with(&format!("{}_", expected)))
+            });
+            if !matched {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// Ordered list of routing rules
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RoutingTable {
+    rules : Vec<RoutingRule>,
+}
+
+impl RoutingTable {
+    pub fn new(rules : Vec<RoutingRule>) -> Self {
+        Self { rules }
+    }
+
+    /// Parses the `rules` key of the dataset. A dataset without rules sends every alert to its default database.
+    pub fn from_dataset(dataset : &TextMapSynDataset) -> Result<Self, serde_json::Error> {
+        match dataset.get(RULES_KEY) {
+            Some(rules) => Ok(Self::new(serde_json::from_str(rules)?)),
+            None => Ok(Self::default())
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.rules.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Destinations of the first rule that matches the alert, or None if no rule matches
+    pub fn route(&self, alert : &SiemAlert) -> Option<&[RouteTarget]> {
+        self.rules.iter().find(|rule| rule.matches(alert)).map(|rule| &rule.to[..])
+    }
+}
+
+#[cfg(test)]
+mod rules {
+    use usiem::prelude::{alert::{AlertSeverity, SiemAlert}, mitre::MitreTechniques, SiemLog};
+    use usiem::serde_json::{self, json};
+    use super::*;
+
+    fn alert() -> SiemAlert {
+        SiemAlert {
+            title: String::from("Credential dumping"),
+            description: String::new(),
+            severity: AlertSeverity::HIGH,
+            date: 0,
+            tags: vec![String::from("Windows"), String::from("Credentials")],
+            techniques : vec![MitreTechniques::T1001, MitreTechniques::T1003_001],
+            rule: String::from("ruleset::example::rule1"),
+            log: SiemLog::new("log", 0, "localhost"),
+            aggr_limit: 0,
+            aggr_key: String::new(),
+        }
+    }
+
+    fn table(rules : serde_json::Value) -> RoutingTable {
+        RoutingTable::new(serde_json::from_value(rules).unwrap())
+    }
+
+    fn database(id : &str) -> RouteTarget {
+        RouteTarget::Database(id.to_owned())
+    }
+
+    #[test]
+    fn should_match_conditions() {
+        let alert = alert();
+        let matches = |rule : serde_json::Value| serde_json::from_value::<RoutingRule>(rule).unwrap().matches(&alert);
+        assert!(matches(json!({"to": []})));
+        assert!(matches(json!({"severity": "high", "to": []})));
+        assert!(matches(json!({"severity": "Medium", "to": []})));
+        assert!(!matches(json!({"severity": "CRITICAL", "to": []})));
+        assert!(matches(json!({"rule": "ruleset::example::*", "to": []})));
+        assert!(matches(json!({"rule": "ruleset::example::rule1", "to": []})));
+        assert!(!matches(json!({"rule": "ruleset::example", "to": []})));
+        assert!(!matches(json!({"rule": "ruleset::other::*", "to": []})));
+        assert!(matches(json!({"tags": ["Linux", "Credentials"], "to": []})));
+        assert!(!matches(json!({"tags": ["Linux"], "to": []})));
+        assert!(matches(json!({"techniques": ["T1003"], "to": []})));
+        assert!(matches(json!({"techniques": ["T1003.001"], "to": []})));
+        assert!(matches(json!({"techniques": ["T1001"], "to": []})));
+        assert!(!matches(json!({"techniques": ["T1003.002", "T100"], "to": []})));
+        assert!(!matches(json!({"rule": "ruleset::example::*", "tags": ["Linux"], "to": []})));
+    }
+
+    #[test]
+    fn should_route_with_first_matching_rule() {
+        let table = table(json!([
+            {"severity": "critical", "to": ["critical-db"]},
+            {"rule": "ruleset::example::*", "to": ["example-db", "default"]},
+            {"tags": ["Windows"], "to": []}
+        ]));
+        let mut alert = alert();
+        assert_eq!(Some(&[database("example-db"), RouteTarget::Default][..]), table.route(&alert));
+        alert.severity = AlertSeverity::CRITICAL;
+        assert_eq!(Some(&[database("critical-db")][..]), table.route(&alert));
+        alert.severity = AlertSeverity::LOW;
+        alert.rule = String::from("ruleset::other::rule1");
+        assert_eq!(Some(&[][..]), table.route(&alert));
+        alert.tags.clear();
+        assert_eq!(None, table.route(&alert));
+    }
+
+    #[test]
+    fn should_reject_invalid_rules() {
+        assert!(serde_json::from_value::<RoutingRule>(json!({"severity": "urgent", "to": []})).is_err());
+        assert!(serde_json::from_value::<RoutingRule>(json!({"rule": "ruleset::example::*"})).is_err());
+    }
+}
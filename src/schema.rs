@@ -0,0 +1,378 @@
+//! Validation of the database where the alerts are stored
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::api::database::{DatabaseDefinition, DatabaseUpdate, OptionSchema, PropertySchema};
+use crate::mapping::{AlertField, PropertyMapping};
+
+/// Options of the Priority property, one for each alert severity, with their colors
+pub const PRIORITY_OPTIONS : [(&str, &str); 5] = [("Informational", "gray"), ("Low", "blue"), ("Medium", "yellow"), ("High", "orange"), ("Critical", "red")];
+/// Options of Status. The first one is set on new alerts when Status is a select property.
+pub const STATUS_OPTIONS : [(&str, &str); 3] = [("Not started", "default"), ("In progress", "blue"), ("Done", "green")];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MissingProperty {
+    pub field : AlertField,
+    pub property : String,
+    pub expected : &'static str,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WrongPropertyType {
+    pub field : AlertField,
+    pub property : String,
+    pub expected : &'static str,
+    pub found : &'static str,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MissingOption {
+    pub field : AlertField,
+    pub property : String,
+    pub option : String,
+}
+
+/// Differences between the database and the properties the alerter needs.
+///
+/// Missing options do not make the schema invalid: Notion creates the select options when a page uses them.
+/// Status can also be a select property, as the Notion API cannot create status properties.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SchemaReport {
+    pub missing_properties : Vec<MissingProperty>,
+    pub wrong_types : Vec<WrongPropertyType>,
+    pub missing_options : Vec<MissingOption>,
+    /// Optional properties that are not in the database. They are not reported, but the migration adds them.
+    pub missing_optional : Vec<MissingProperty>,
+    /// The database has the optional Occurrences and Last seen properties
+    pub aggregation_properties : bool,
+    /// Status is a select property instead of a status one
+    pub status_select : bool,
+}
+
+impl SchemaReport {
+    /// Compares the database with the properties of the mapping, which must be already resolved to names
+    pub fn new(database : &DatabaseDefinition, properties : &PropertyMapping) -> Self {
+        let mut report = Self::default();
+        for field in AlertField::REQUIRED {
+            let property = properties.property(field);
+            let definition = match database.properties.get(property) {
+                Some(v) => v,
+                None => {
+                    report.missing_properties.push(MissingProperty { field, property : property.to_owned(), expected : field.property_type() });
+                    continue;
+                }
+            };
+            let status_select = field == AlertField::Status && definition.type_name() == "select";
+            report.status_select |= status_select;
+            if definition.type_name() != field.property_type() && !status_select {
+                report.wrong_types.push(WrongPropertyType { field, property : property.to_owned(), expected : field.property_type(), found : definition.type_name() });
+                continue;
+            }
+            let options = definition.option_names();
+            for (option, _) in expected_options(field, status_select) {
+                if !options.contains(option) {
+                    report.missing_options.push(MissingOption { field, property : property.to_owned(), option : (*option).to_owned() });
+                }
+            }
+        }
+        report.aggregation_properties = [AlertField::Occurrences, AlertField::LastSeen].iter().all(|field| {
+            database.properties.get(properties.property(*field)).map(|v| v.type_name() == field.property_type()).unwrap_or(false)
+        });
+        for field in [AlertField::Occurrences, AlertField::LastSeen] {
+            let property = properties.property(field);
+            if !database.properties.contains_key(property) {
+                report.missing_optional.push(MissingProperty { field, property : property.to_owned(), expected : field.property_type() });
+            }
+        }
+        report
+    }
+
+    /// Alerts can be stored in the database
+    pub fn is_valid(&self) -> bool {
+        self.missing_properties.is_empty() && self.wrong_types.is_empty()
+    }
+
+    /// There is nothing to report
+    pub fn is_empty(&self) -> bool {
+        self.is_valid() && self.missing_options.is_empty()
+    }
+}
+
+fn expected_options(field : AlertField, status_select : bool) -> &'static [(&'static str, &'static str)] {
+    match field {
+        AlertField::Priority => &PRIORITY_OPTIONS,
+        AlertField::Status if status_select => &STATUS_OPTIONS[..1],
+        AlertField::Status => &STATUS_OPTIONS,
+        _ => &[]
+    }
+}
+
+impl fmt::Display for SchemaReport {
+    fn fmt(&self, f : &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_valid() {
+            write!(f, "The Notion database is valid")?;
+        } else {
+            write!(f, "The Notion database does not have the required properties")?;
+        }
+        let mut issues = Vec::new();
+        for v in &self.missing_properties {
+            issues.push(format!("missing {} property '{}'", v.expected, v.property));
+        }
+        for v in &self.wrong_types {
+            issues.push(format!("property '{}' is {} instead of {}", v.property, v.found, v.expected));
+        }
+        for v in &self.missing_options {
+            issues.push(format!("option '{}' missing in '{}'", v.option, v.property));
+        }
+        if !issues.is_empty() {
+            write!(f, ": {}", issues.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// Difference that a migration cannot fix
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UnresolvedProperty {
+    /// Properties cannot change their type
+    WrongType(WrongPropertyType),
+    /// Databases have a single title property, a second one cannot be added. `title` is the name of the
+    /// existing title property.
+    MissingTitle { property : String, title : Option<String> },
+    /// Status options cannot be added with the API
+    MissingOption(MissingOption),
+}
+
+impl fmt::Display for UnresolvedProperty {
+    fn fmt(&self, f : &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UnresolvedProperty::WrongType(v) => write!(f, "property '{}' is {} instead of {} and must be fixed by hand", v.property, v.found, v.expected),
+            UnresolvedProperty::MissingTitle { property, title : Some(title) } => write!(f, "the title property is '{}' instead of '{}' and must be renamed by hand", title, property),
+            UnresolvedProperty::MissingTitle { property, title : None } => write!(f, "title property '{}' must be added by hand", property),
+            UnresolvedProperty::MissingOption(v) => write!(f, "option '{}' of '{}' must be added by hand", v.option, v.property),
+        }
+    }
+}
+
+/// How the component handles a database that does not match the expected schema
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SchemaMigrationMode {
+    /// The database is only validated
+    #[default]
+    Disabled,
+    /// The planned changes are notified, but not applied
+    DryRun,
+    /// Missing properties and options are added to the database
+    Apply,
+}
+
+/// Changes that make the database match the expected schema, including the optional properties. Properties
+/// and options are only added: properties with a wrong type, a missing title and missing status options are
+/// reported in `unresolved` and must be fixed by hand.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SchemaMigration {
+    /// New properties and the complete option lists of the changed selects
+    pub properties : BTreeMap<String, PropertySchema>,
+    /// Human readable description of each change
+    pub changes : Vec<String>,
+    pub unresolved : Vec<UnresolvedProperty>,
+}
+
+impl SchemaMigration {
+    pub fn new(database : &DatabaseDefinition, report : &SchemaReport) -> Self {
+        let mut migration = Self::default();
+        for missing in report.missing_properties.iter().chain(&report.missing_optional) {
+            let schema = match missing.field {
+                AlertField::Name => {
+                    let title = database.properties.iter().find(|(_, v)| v.type_name() == "title").map(|(name, _)| name.clone());
+                    migration.unresolved.push(UnresolvedProperty::MissingTitle { property : missing.property.clone(), title });
+                    continue;
+                },
+                AlertField::Priority => PropertySchema::select(&PRIORITY_OPTIONS),
+                // Status properties cannot be created with the API
+                AlertField::Status => PropertySchema::select(&STATUS_OPTIONS),
+                AlertField::Mitre | AlertField::Tags => PropertySchema::multi_select(),
+                AlertField::Occurrences => PropertySchema::number(),
+                AlertField::Fired | AlertField::LastSeen => PropertySchema::Date {},
+            };
+            let kind = match schema {
+                PropertySchema::Select { .. } => "select",
+                _ => missing.expected
+            };
+            migration.changes.push(format!("add {} property '{}'", kind, missing.property));
+            migration.properties.insert(missing.property.clone(), schema);
+        }
+        let mut missing_options : BTreeMap<&str, Vec<&MissingOption>> = BTreeMap::new();
+        for missing in &report.missing_options {
+            missing_options.entry(&missing.property).or_default().push(missing);
+        }
+        for (property, missing) in missing_options {
+            let definition = match database.properties.get(property) {
+                Some(v) => v,
+                None => continue
+            };
+            // Status options cannot be changed with the API
+            if definition.type_name() != "select" {
+                migration.unresolved.extend(missing.into_iter().cloned().map(UnresolvedProperty::MissingOption));
+                continue;
+            }
+            let mut options : Vec<OptionSchema> = definition.option_names().into_iter().map(|name| OptionSchema::new(name, None)).collect();
+            for option in &missing {
+                let color = expected_options(option.field, report.status_select).iter().find(|(name, _)| *name == option.option).map(|(_, color)| *color);
+                options.push(OptionSchema::new(&option.option, color));
+                migration.changes.push(format!("add option '{}' to '{}'", option.option, property));
+            }
+            migration.properties.insert(property.to_owned(), PropertySchema::Select { options });
+        }
+        migration.unresolved.extend(report.wrong_types.iter().cloned().map(UnresolvedProperty::WrongType));
+        migration
+    }
+
+    /// There is nothing to change in the database
+    pub fn is_empty(&self) -> bool {
+        self.properties.is_empty()
+    }
+
+    /// Body of the request that applies the migration
+    pub fn update(&self) -> DatabaseUpdate {
+        DatabaseUpdate {
+            properties : self.properties.clone()
+        }
+    }
+}
+
+impl fmt::Display for SchemaMigration {
+    fn fmt(&self, f : &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.changes.is_empty() {
+            write!(f, "no changes")?;
+        } else {
+            write!(f, "{}", self.changes.join(", "))?;
+        }
+        for v in &self.unresolved {
+            write!(f, ", {}", v)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod report {
+    use super::*;
+    use usiem::serde_json::{self, json, Value};
+
+    fn database(properties : Value) -> DatabaseDefinition {
+        serde_json::from_value(json!({
+            "object": "database",
+            "id": "1234",
+            "created_time": "2022-10-01T10:00:00.000Z",
+            "last_edited_time": "2022-10-01T10:00:00.000Z",
+            "properties": properties
+        })).unwrap()
+    }
+
+    #[test]
+    fn should_report_schema_differences() {
+        let database = database(json!({
+            "Name": {"id": "title", "type": "title", "title": {}},
+            "Priority": {"id": "p", "type": "select", "select": {"options": [
+                {"id": "1", "name": "Low", "color": "blue"},
+                {"id": "2", "name": "High", "color": "orange"}
+            ]}},
+            "MITRE": {"id": "m", "type": "multi_select", "multi_select": {"options": []}},
+            "Tags": {"id": "t", "type": "rich_text", "rich_text": []},
+            "Fired": {"id": "f", "type": "date", "date": {}}
+        }));
+        let report = SchemaReport::new(&database, &PropertyMapping::new());
+        assert!(!report.is_valid());
+        assert_eq!(vec![MissingProperty { field : AlertField::Status, property : "Status".to_owned(), expected : "status" }], report.missing_properties);
+        assert_eq!(vec![WrongPropertyType { field : AlertField::Tags, property : "Tags".to_owned(), expected : "multi_select", found : "rich_text" }], report.wrong_types);
+        let options : Vec<&str> = report.missing_options.iter().map(|v| &v.option[..]).collect();
+        assert_eq!(vec!["Informational", "Medium", "Critical"], options);
+        assert!(!report.aggregation_properties);
+        assert_eq!("The Notion database does not have the required properties: missing status property 'Status', property 'Tags' is rich_text instead of multi_select, option 'Informational' missing in 'Priority', option 'Medium' missing in 'Priority', option 'Critical' missing in 'Priority'", report.to_string());
+    }
+
+    #[test]
+    fn should_accept_siem_database() {
+        let mut database = crate::mock::siem_database("1234");
+        database["properties"]["Occurrences"] = json!({"id": "o", "type": "number", "number": {"format": "number"}});
+        database["properties"]["Last seen"] = json!({"id": "l", "type": "date", "date": {}});
+        let database : DatabaseDefinition = serde_json::from_value(database).unwrap();
+        let report = SchemaReport::new(&database, &PropertyMapping::new());
+        assert!(report.is_empty());
+        assert!(report.aggregation_properties);
+        assert!(!report.status_select);
+        assert_eq!("The Notion database is valid", report.to_string());
+    }
+
+    #[test]
+    fn should_plan_migration() {
+        let database = database(json!({
+            "Name": {"id": "title", "type": "title", "title": {}},
+            "Priority": {"id": "p", "type": "select", "select": {"options": [
+                {"id": "1", "name": "Low", "color": "blue"},
+                {"id": "2", "name": "Urgent", "color": "pink"}
+            ]}},
+            "Tags": {"id": "t", "type": "rich_text", "rich_text": {}},
+            "Fired": {"id": "f", "type": "date", "date": {}}
+        }));
+        let report = SchemaReport::new(&database, &PropertyMapping::new());
+        let migration = SchemaMigration::new(&database, &report);
+        assert_eq!(vec!["Last seen", "MITRE", "Occurrences", "Priority", "Status"], migration.properties.keys().collect::<Vec<&String>>());
+        assert_eq!(PropertySchema::number(), migration.properties["Occurrences"]);
+        assert_eq!(PropertySchema::select(&STATUS_OPTIONS), migration.properties["Status"]);
+        let priority = json!({"select": {"options": [
+            {"name": "Low"},
+            {"name": "Urgent"},
+            {"name": "Informational", "color": "gray"},
+            {"name": "Medium", "color": "yellow"},
+            {"name": "High", "color": "orange"},
+            {"name": "Critical", "color": "red"}
+        ]}});
+        assert_eq!(priority, serde_json::to_value(migration.update()).unwrap()["properties"]["Priority"]);
+        assert_eq!("add multi_select property 'MITRE', add select property 'Status', add number property 'Occurrences', add date property 'Last seen', add option 'Informational' to 'Priority', add option 'Medium' to 'Priority', add option 'High' to 'Priority', add option 'Critical' to 'Priority', property 'Tags' is rich_text instead of multi_select and must be fixed by hand", migration.to_string());
+    }
+
+    #[test]
+    fn should_not_add_a_second_title() {
+        let mut database = crate::mock::siem_database("1234");
+        let title = database["properties"].as_object_mut().unwrap().remove("Name").unwrap();
+        database["properties"]["Alert"] = title;
+        database["properties"]["Occurrences"] = json!({"id": "o", "type": "number", "number": {"format": "number"}});
+        database["properties"]["Last seen"] = json!({"id": "l", "type": "date", "date": {}});
+        let database : DatabaseDefinition = serde_json::from_value(database).unwrap();
+        let report = SchemaReport::new(&database, &PropertyMapping::new());
+        let migration = SchemaMigration::new(&database, &report);
+        assert!(migration.is_empty());
+        assert_eq!(vec![UnresolvedProperty::MissingTitle { property : "Name".to_owned(), title : Some("Alert".to_owned()) }], migration.unresolved);
+        assert_eq!("no changes, the title property is 'Alert' instead of 'Name' and must be renamed by hand", migration.to_string());
+    }
+
+    #[test]
+    fn should_report_missing_status_options() {
+        let mut database = crate::mock::siem_database("1234");
+        database["properties"]["Status"]["status"]["options"].as_array_mut().unwrap().remove(1);
+        let database : DatabaseDefinition = serde_json::from_value(database).unwrap();
+        let report = SchemaReport::new(&database, &PropertyMapping::new());
+        assert!(report.is_valid());
+        assert_eq!(vec![MissingOption { field : AlertField::Status, property : "Status".to_owned(), option : "In progress".to_owned() }], report.missing_options);
+        assert_eq!("The Notion database is valid: option 'In progress' missing in 'Status'", report.to_string());
+        // Only the optional properties are added, the status option must be added by hand
+        let migration = SchemaMigration::new(&database, &report);
+        assert_eq!(vec!["Last seen", "Occurrences"], migration.properties.keys().collect::<Vec<&String>>());
+        assert_eq!(vec![UnresolvedProperty::MissingOption(report.missing_options[0].clone())], migration.unresolved);
+        assert_eq!("add number property 'Occurrences', add date property 'Last seen', option 'In progress' of 'Status' must be added by hand", migration.to_string());
+    }
+
+    #[test]
+    fn should_accept_status_as_select() {
+        let mut database = crate::mock::siem_database("1234");
+        database["properties"]["Status"] = json!({"id": "s", "type": "select", "select": {"options": [{"id": "1", "name": "Done", "color": "green"}]}});
+        let database : DatabaseDefinition = serde_json::from_value(database).unwrap();
+        let report = SchemaReport::new(&database, &PropertyMapping::new());
+        assert!(report.is_valid());
+        assert!(report.status_select);
+        assert_eq!("option 'Not started' missing in 'Status'", report.to_string().split(": ").nth(1).unwrap());
+    }
+}
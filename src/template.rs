@@ -0,0 +1,448 @@
+//! Templates of the pages created for the alerts.
+//!
+//! A template is a JSON object with the extra `properties` of the page and the `blocks` of its body. Texts
+//! can include `{{variable}}` placeholders that are replaced with the fields of the alert:
+//!
+//! * `title`, `description`, `rule`, `severity`, `date`, `tags`, `techniques` and `aggr_key`
+//! * `log.message`, `log.json` (the whole log), `log.origin`, `log.tenant`, `log.product`, `log.service`,
+//!   `log.category` and `log.vendor`
+//! * `field.<name>`: a field of the log, like `field.source.ip`
+//!
+//! Unknown variables are replaced with an empty text.
+//!
+//! ```json
+//! {
+//!     "properties": {"Host": {"rich_text": "{{field.host.hostname}}"}},
+//!     "blocks": [
+//!         {"heading_1": "{{title}}"},
+//!         {"callout": "{{severity}} alert of {{rule}}", "emoji": "🚨"},
+//!         {"markdown": "{{description}}"},
+//!         {"paragraph": "User: {{field.user.name}}", "if": "field.user.name"},
+//!         {"toggle": "Log", "children": [{"code": "{{log.json}}", "language": "json"}]}
+//!     ]
+//! }
+//! ```
+
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use serde::de::IgnoredAny;
+use serde::Deserialize;
+use usiem::prelude::alert::SiemAlert;
+use usiem::prelude::dataset::text_map::TextMapSynDataset;
+use usiem::serde_json;
+
+use crate::api::block::BlockElement;
+use crate::api::database::properties::*;
+use crate::api::page::PropertyValue;
+use crate::client::{alert_date, alert_severity};
+use crate::event;
+
+/// Key of the dataset with the template of the rules without their own template
+pub const DEFAULT_TEMPLATE : &str = "default";
+const DEFAULT_EMOJI : &str = "💡";
+
+/// Value of a property. The text is rendered and converted to the property type: `multi_select`
+/// options are separated by commas, `number` and `checkbox` are parsed. Properties with an empty
+/// text are not set.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PropertyTemplate {
+    Title(String),
+    RichText(String),
+    Select(String),
+    MultiSelect(String),
+    Number(String),
+    Checkbox(String),
+    Url(String),
+    Email(String),
+    PhoneNumber(String),
+    Date(String),
+}
+
+impl PropertyTemplate {
+    pub fn render(&self, alert : &SiemAlert) -> Option<PropertyValue> {
+        let template = match self {
+            PropertyTemplate::Title(v) | PropertyTemplate::RichText(v) | PropertyTemplate::Select(v) | PropertyTemplate::MultiSelect(v)
+            | PropertyTemplate::Number(v) | PropertyTemplate::Checkbox(v) | PropertyTemplate::Url(v) | PropertyTemplate::Email(v)
+            | PropertyTemplate::PhoneNumber(v) | PropertyTemplate::Date(v) => v
+        };
+        let text = render(template, alert);
+        let text = text.trim();
+        if text.is_empty() {
+            return None;
+        }
+        Some(match self {
+            PropertyTemplate::Title(_) => PropertyValue::Title(TitleValue::new(text)),
+            PropertyTemplate::RichText(_) => PropertyValue::RichText(RichTextValue::new(text)),
+            PropertyTemplate::Select(_) => PropertyValue::Select(SelectValue::new(text)),
+            PropertyTemplate::MultiSelect(_) => PropertyValue::MultiSelect(MultiSelectValue::new(text.split(',').map(|v| v.trim()).filter(|v| !v.is_empty()))),
+            PropertyTemplate::Number(_) => PropertyValue::Number(NumberValue::new(text.parse().ok()?)),
+            PropertyTemplate::Checkbox(_) => PropertyValue::CheckBox(CheckboxValue { checkbox : text == "true" || text == "1" }),
+            PropertyTemplate::Url(_) => PropertyValue::Url(UrlValue { url : Some(text.to_owned()) }),
+            PropertyTemplate::Email(_) => PropertyValue::Email(EmailValue { email : Some(text.to_owned()) }),
+            PropertyTemplate::PhoneNumber(_) => PropertyValue::PhoneNumber(PhoneNumberValue { phone_number : Some(text.to_owned()) }),
+            PropertyTemplate::Date(_) => PropertyValue::Date(DateValue::new(text.to_owned())),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BlockKind {
+    #[serde(rename = "heading_1")]
+    Heading1(String),
+    #[serde(rename = "heading_2")]
+    Heading2(String),
+    #[serde(rename = "heading_3")]
+    Heading3(String),
+    Paragraph(String),
+    /// The rendered text is converted from Markdown
+    Markdown(String),
+    Code(String),
+    Quote(String),
+    Callout(String),
+    BulletedListItem(String),
+    NumberedListItem(String),
+    Toggle(String),
+    /// `{"divider": {}}`
+    Divider(IgnoredAny),
+    /// `{"event": {}}`: table with the fields of the parsed event, like the IPs, the user or the outcome
+    Event(IgnoredAny),
+    /// `{"fields": {}}`: table with the fields of the log
+    Fields(IgnoredAny),
+}
+
+/// Block of the page body
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct BlockTemplate {
+    #[serde(flatten)]
+    pub kind : BlockKind,
+    /// The block is only added if this variable is not empty
+    #[serde(default, rename = "if")]
+    pub condition : Option<String>,
+    /// Language of code blocks
+    #[serde(default)]
+    pub language : Option<String>,
+    /// Icon of callouts
+    #[serde(default)]
+    pub emoji : Option<String>,
+    /// Nested blocks of list items, toggles, quotes and callouts
+    #[serde(default)]
+    pub children : Vec<BlockTemplate>,
+}
+
+impl BlockTemplate {
+    pub fn new(kind : BlockKind) -> Self {
+        Self {
+            kind,
+            condition : None,
+            language : None,
+            emoji : None,
+            children : Vec::new()
+        }
+    }
+
+    pub fn language(mut self, language : &str) -> Self {
+        self.language = Some(language.to_owned());
+        self
+    }
+
+    pub fn render(&self, alert : &SiemAlert) -> Vec<BlockElement> {
+        if let Some(condition) = &self.condition {
+            if variable(condition.trim(), alert).map(|v| v.trim().is_empty()).unwrap_or(true) {
+                return Vec::new();
+            }
+        }
+        let mut blocks = match &self.kind {
+            BlockKind::Heading1(text) => vec![BlockElement::heading_one(&render(text, alert))],
+            BlockKind::Heading2(text) => vec![BlockElement::heading_two(&render(text, alert))],
+            BlockKind::Heading3(text) => vec![BlockElement::heading_three(&render(text, alert))],
+            BlockKind::Paragraph(text) => BlockElement::paragraphs(&render(text, alert)),
+            BlockKind::Markdown(text) => BlockElement::markdown(&render(text, alert)),
+            BlockKind::Code(text) => BlockElement::code_blocks(&render(text, alert), self.language.clone()),
+            BlockKind::Quote(text) => vec![BlockElement::quote(&render(text, alert))],
+            BlockKind::Callout(text) => vec![BlockElement::callout(&render(text, alert), self.emoji.as_deref().unwrap_or(DEFAULT_EMOJI))],
+            BlockKind::BulletedListItem(text) => vec![BlockElement::bulleted_list_item(&render(text, alert))],
+            BlockKind::NumberedListItem(text) => vec![BlockElement::numbered_list_item(&render(text, alert))],
+            BlockKind::Toggle(text) => vec![BlockElement::toggle(&render(text, alert), Vec::new())],
+            BlockKind::Divider(_) => vec![BlockElement::divider()],
+            BlockKind::Event(_) => event::event_table(&alert.log),
+            BlockKind::Fields(_) => event::fields_table(&alert.log),
+        };
+        if !self.children.is_empty() {
+            if let Some(children) = blocks.last_mut().and_then(|block| block.children_mut()) {
+                children.extend(self.children.iter().flat_map(|child| child.render(alert)));
+            }
+        }
+        blocks
+    }
+}
+
+/// Properties and body of the alert pages. The default template has the title, the rule, the description,
+/// the tables of the event and the log fields, the log and the log as JSON.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct PageTemplate {
+    /// Properties set in addition to the alert fields, by property name
+    #[serde(default)]
+    pub properties : BTreeMap<String, PropertyTemplate>,
+    pub blocks : Vec<BlockTemplate>,
+}
+
+impl Default for PageTemplate {
+    fn default() -> Self {
+        Self {
+            properties : BTreeMap::new(),
+            blocks : vec![
+                BlockTemplate::new(BlockKind::Heading1("{{title}}".to_owned())),
+                BlockTemplate::new(BlockKind::Heading3("{{rule}}".to_owned())),
+                BlockTemplate::new(BlockKind::Markdown("{{description}}".to_owned())),
+                BlockTemplate::new(BlockKind::Event(IgnoredAny)),
+                BlockTemplate::new(BlockKind::Fields(IgnoredAny)),
+                BlockTemplate::new(BlockKind::Code("{{log.message}}".to_owned())),
+                BlockTemplate::new(BlockKind::Code("{{log.json}}".to_owned())).language("json"),
+            ]
+        }
+    }
+}
+
+impl PageTemplate {
+    /// Template of the rule in the dataset. The keys are rules or rule prefixes ending in `::`, without
+    /// the separator: `ruleset::example` applies to `ruleset::example::rule1`. The longest key wins and
+    /// the `default` key applies to the other rules. Without any of them the default template is used.
+    pub fn from_dataset(dataset : &TextMapSynDataset, rule : &str) -> Result<Self, serde_json::Error> {
+        match dataset_key(dataset, rule).and_then(|key| dataset.get(key)) {
+            Some(template) => serde_json::from_str(template),
+            None => Ok(Self::default())
+        }
+    }
+
+    pub fn properties(&self, alert : &SiemAlert) -> BTreeMap<String, PropertyValue> {
+        self.properties.iter().filter_map(|(name, property)| Some((name.clone(), property.render(alert)?))).collect()
+    }
+
+    pub fn blocks(&self, alert : &SiemAlert) -> Vec<BlockElement> {
+        self.blocks.iter().flat_map(|block| block.render(alert)).collect()
+    }
+}
+
+/// Key of the dataset with the template of the rule, None for the default template
+fn dataset_key<'a>(dataset : &TextMapSynDataset, rule : &'a str) -> Option<&'a str> {
+    let mut key = rule;
+    loop {
+        if dataset.get(key).is_some() {
+            return Some(key);
+        }
+        match key.rfind("::") {
+            Some(pos) => key = &key[..pos],
+            None => break
+        }
+    }
+    dataset.get(DEFAULT_TEMPLATE).map(|_| DEFAULT_TEMPLATE)
+}
+
+/// Templates of a dataset, parsed once. The dataset cannot be listed, so each template is parsed the first
+/// time a rule uses it and kept until the dataset is replaced. Invalid templates are replaced by the default one.
+#[derive(Debug, Clone, Default)]
+pub struct PageTemplates {
+    dataset : Option<TextMapSynDataset>,
+    parsed : BTreeMap<String, Arc<PageTemplate>>,
+    default : Arc<PageTemplate>,
+}
+
+impl PageTemplates {
+    pub fn new(dataset : TextMapSynDataset) -> Self {
+        Self {
+            dataset : Some(dataset),
+            ..Default::default()
+        }
+    }
+
+    /// Template of the rule, selected like `PageTemplate::from_dataset`. The parsing error of an invalid
+    /// template is only returned the first time.
+    pub fn get(&mut self, rule : &str) -> (Arc<PageTemplate>, Option<serde_json::Error>) {
+        let (key, template) = match &self.dataset {
+            Some(dataset) => match dataset_key(dataset, rule).and_then(|key| Some((key, dataset.get(key)?))) {
+                Some(v) => v,
+                None => return (self.default.clone(), None)
+            },
+            None => return (self.default.clone(), None)
+        };
+        if let Some(parsed) = self.parsed.get(key) {
+            return (parsed.clone(), None);
+        }
+        let (parsed, error) = match serde_json::from_str(template) {
+            Ok(v) => (Arc::new(v), None),
+            Err(e) => (self.default.clone(), Some(e))
+        };
+        self.parsed.insert(key.to_owned(), parsed.clone());
+        (parsed, error)
+    }
+}
+
+/// Replaces the `{{variable}}` placeholders of the text
+pub fn render(template : &str, alert : &SiemAlert) -> String {
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        let end = match rest[start + 2..].find("}}") {
+            Some(end) => start + 2 + end,
+            None => break
+        };
+        rendered.push_str(&rest[..start]);
+        if let Some(value) = variable(rest[start + 2..end].trim(), alert) {
+            rendered.push_str(&value);
+        }
+        rest = &rest[end + 2..];
+    }
+    rendered.push_str(rest);
+    rendered
+}
+
+fn variable<'a>(name : &str, alert : &'a SiemAlert) -> Option<Cow<'a, str>> {
+    if let Some(field) = name.strip_prefix("field.") {
+        return alert.log.field(field).map(|v| Cow::Owned(v.to_string()));
+    }
+    let log = &alert.log;
+    Some(match name {
+        "title" => Cow::Borrowed(&alert.title[..]),
+        "description" => Cow::Borrowed(&alert.description[..]),
+        "rule" => Cow::Borrowed(&alert.rule[..]),
+        "severity" => Cow::Owned(alert_severity(&alert.severity)),
+        "date" => Cow::Owned(alert_date(alert)),
+        "tags" => Cow::Owned(alert.tags.join(", ")),
+        "techniques" => Cow::Owned(alert.techniques.iter().map(|v| format!("{:?}", v)).collect::<Vec<String>>().join(", ")),
+        "aggr_key" => Cow::Borrowed(&alert.aggr_key[..]),
+        "log.message" => Cow::Borrowed(log.message()),
+        "log.json" => Cow::Owned(serde_json::to_string_pretty(log).unwrap_or_else(|_| "Cannot show the log".to_owned())),
+        "log.origin" => Cow::Borrowed(log.origin()),
+        "log.tenant" => Cow::Borrowed(log.tenant()),
+        "log.product" => Cow::Borrowed(log.product()),
+        "log.service" => Cow::Borrowed(log.service()),
+        "log.category" => Cow::Borrowed(log.category()),
+        "log.vendor" => Cow::Borrowed(log.vendor()),
+        _ => return None
+    })
+}
+
+#[cfg(test)]
+mod rendering {
+    use std::sync::Arc;
+    use usiem::crossbeam_channel::bounded;
+    use usiem::prelude::{alert::{AlertSeverity, SiemAlert}, dataset::text_map::TextMapDataset, mitre::MitreTechniques, SiemLog, field::SiemField};
+    use usiem::serde_json::json;
+    use super::*;
+
+    fn json(blocks : &Vec<BlockElement>) -> serde_json::Value {
+        serde_json::to_value(blocks).unwrap()
+    }
+
+    fn alert() -> SiemAlert {
+        let mut log = SiemLog::new("Failed password for root from 10.10.10.10", 0, "localhost");
+        log.set_product(Cow::Borrowed("OpenSSH"));
+        log.add_field("user.name", SiemField::User(String::from("root")));
+        SiemAlert {
+            title: String::from("SSH brute force"),
+            description: String::from("Too many **failed** logins"),
+            severity: AlertSeverity::HIGH,
+            date: 0,
+            tags: vec![String::from("Linux"), String::from("SSH")],
+            techniques : vec![MitreTechniques::T1110],
+            rule: String::from("ruleset::ssh::brute_force"),
+            log,
+            aggr_limit: 0,
+            aggr_key: String::new(),
+        }
+    }
+
+    #[test]
+    fn should_replace_variables() {
+        let alert = alert();
+        assert_eq!("SSH brute force (High) by root on OpenSSH", render("{{title}} ({{ severity }}) by {{field.user.name}} on {{log.product}}", &alert));
+        assert_eq!("Linux, SSH - T1110 - ", render("{{tags}} - {{techniques}} - {{unknown}}{{field.unknown}}", &alert));
+        assert_eq!("1970-01-01T00:00:00Z {{rule", render("{{date}} {{rule", &alert));
+    }
+
+    #[test]
+    fn should_render_default_layout() {
+        let alert = alert();
+        let mut expected = vec![
+            BlockElement::heading_one("SSH brute force"),
+            BlockElement::heading_three("ruleset::ssh::brute_force"),
+        ];
+        expected.extend(BlockElement::markdown("Too many **failed** logins"));
+        expected.extend(event::fields_table(&alert.log));
+        expected.extend(BlockElement::code_blocks("Failed password for root from 10.10.10.10", None));
+        expected.extend(BlockElement::code_blocks(&serde_json::to_string_pretty(&alert.log).unwrap(), Some("json".to_owned())));
+        assert_eq!(json(&expected), json(&PageTemplate::default().blocks(&alert)));
+        assert!(PageTemplate::default().properties(&alert).is_empty());
+    }
+
+    #[test]
+    fn should_render_template() {
+        let template : PageTemplate = serde_json::from_value(json!({
+            "properties": {
+                "User": {"rich_text": "{{field.user.name}}"},
+                "Product": {"select": "{{log.product}}"},
+                "Labels": {"multi_select": "{{tags}}, extra"},
+                "Host": {"rich_text": "{{field.host.hostname}}"}
+            },
+            "blocks": [
+                {"callout": "{{severity}} alert", "emoji": "🚨"},
+                {"paragraph": "Host: {{field.host.hostname}}", "if": "field.host.hostname"},
+                {"toggle": "Log", "children": [{"code": "{{log.message}}", "language": "shell"}, {"divider": {}}]}
+            ]
+        })).unwrap();
+        let alert = alert();
+        let properties = template.properties(&alert);
+        assert_eq!(3, properties.len());
+        assert_eq!(Some(&PropertyValue::RichText(RichTextValue::new("root"))), properties.get("User"));
+        assert_eq!(Some(&PropertyValue::Select(SelectValue::new("OpenSSH"))), properties.get("Product"));
+        assert_eq!(Some(&PropertyValue::MultiSelect(MultiSelectValue::new(["Linux", "SSH", "extra"]))), properties.get("Labels"));
+        let mut toggle = BlockElement::code_blocks("Failed password for root from 10.10.10.10", Some("shell".to_owned()));
+        toggle.push(BlockElement::divider());
+        assert_eq!(json(&vec![
+            BlockElement::callout("High alert", "🚨"),
+            BlockElement::toggle("Log", toggle),
+        ]), json(&template.blocks(&alert)));
+    }
+
+    #[test]
+    fn should_select_template_by_rule() {
+        let mut templates = TextMapDataset::new();
+        templates.insert(Cow::Borrowed("ruleset::ssh"), Cow::Owned(json!({"blocks": [{"heading_1": "SSH"}]}).to_string()));
+        templates.insert(Cow::Borrowed("ruleset::ssh::brute_force"), Cow::Owned(json!({"blocks": [{"heading_1": "Brute force"}]}).to_string()));
+        templates.insert(Cow::Borrowed("default"), Cow::Owned(json!({"blocks": [{"heading_1": "Other"}]}).to_string()));
+        templates.insert(Cow::Borrowed("ruleset::broken"), Cow::Borrowed("{"));
+        let (comm, _recv) = bounded(1);
+        let templates = TextMapSynDataset::new(Arc::new(templates), comm);
+        let heading = |rule : &str| json(&PageTemplate::from_dataset(&templates, rule).unwrap().blocks(&alert()));
+        assert_eq!(json(&vec![BlockElement::heading_one("Brute force")]), heading("ruleset::ssh::brute_force"));
+        assert_eq!(json(&vec![BlockElement::heading_one("SSH")]), heading("ruleset::ssh::invalid_user"));
+        assert_eq!(json(&vec![BlockElement::heading_one("Other")]), heading("ruleset::sshd::rule1"));
+        assert!(PageTemplate::from_dataset(&templates, "ruleset::broken::rule1").is_err());
+
+        let (comm, _recv) = bounded(1);
+        let empty = TextMapSynDataset::new(Arc::new(TextMapDataset::new()), comm);
+        assert_eq!(Ok(PageTemplate::default()), PageTemplate::from_dataset(&empty, "ruleset::ssh::brute_force").map_err(|_| ()));
+    }
+
+    #[test]
+    fn should_parse_templates_once() {
+        let mut templates = TextMapDataset::new();
+        templates.insert(Cow::Borrowed("ruleset::ssh"), Cow::Owned(json!({"blocks": [{"heading_1": "SSH"}]}).to_string()));
+        templates.insert(Cow::Borrowed("ruleset::broken"), Cow::Borrowed("{"));
+        let (comm, _recv) = bounded(1);
+        let mut templates = PageTemplates::new(TextMapSynDataset::new(Arc::new(templates), comm));
+        let (ssh, error) = templates.get("ruleset::ssh::brute_force");
+        assert!(error.is_none());
+        assert!(Arc::ptr_eq(&ssh, &templates.get("ruleset::ssh::invalid_user").0));
+        // The error of an invalid template is reported once
+        let (broken, error) = templates.get("ruleset::broken::rule1");
+        assert!(error.is_some());
+        assert_eq!(PageTemplate::default(), *broken);
+        assert!(templates.get("ruleset::broken::rule2").1.is_none());
+        assert_eq!(PageTemplate::default(), *PageTemplates::default().get("ruleset::ssh::brute_force").0);
+    }
+}